@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+
+/// PoolAccounts as serialized by the checker, the pubkeys are already
+/// stringified by its serde helpers
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PoolAccounts {
+    pub amm_pool: String,
+    pub lp_mint: String,
+    pub coin_mint: String,
+    pub pc_mint: String,
+    pub pool_coin_token_account: String,
+    pub pool_pc_token_account: String,
+    pub user_wallet: String,
+    pub user_token_coin: String,
+    pub user_token_pc: String,
+    pub user_lp_token: String,
+}
+
+/// Checklist is the result of the checker's `run_checks` as it comes over the
+/// wire, the extension flags default for checklists produced before them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Checklist {
+    pub slot: u64,
+    pub is_pump_fun: bool,
+    pub lp_burnt: bool,
+    pub mint_authority_renounced: bool,
+    pub freeze_authority_renounced: bool,
+    pub sol_pooled: f64,
+    pub timeout: bool,
+    #[serde(default)]
+    pub has_transfer_fee: bool,
+    #[serde(default)]
+    pub has_transfer_hook: bool,
+    #[serde(default)]
+    pub has_permanent_delegate: bool,
+    pub accounts: PoolAccounts,
+    pub mint: String,
+}
+
+/// ChecklistRecord is a flattened checklist row, bools are stored as UInt8
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "storage", derive(clickhouse::Row))]
+pub struct ChecklistRecord {
+    pub slot: u64,
+    pub mint: String,
+    pub amm_pool: String,
+    pub lp_mint: String,
+    pub coin_mint: String,
+    pub pc_mint: String,
+    pub creator: String,
+    pub is_pump_fun: u8,
+    pub lp_burnt: u8,
+    pub mint_authority_renounced: u8,
+    pub freeze_authority_renounced: u8,
+    pub sol_pooled: f64,
+    pub timeout: u8,
+    pub has_transfer_fee: u8,
+    pub has_transfer_hook: u8,
+    pub has_permanent_delegate: u8,
+}
+
+impl From<&Checklist> for ChecklistRecord {
+    fn from(checklist: &Checklist) -> Self {
+        Self {
+            slot: checklist.slot,
+            mint: checklist.mint.clone(),
+            amm_pool: checklist.accounts.amm_pool.clone(),
+            lp_mint: checklist.accounts.lp_mint.clone(),
+            coin_mint: checklist.accounts.coin_mint.clone(),
+            pc_mint: checklist.accounts.pc_mint.clone(),
+            creator: checklist.accounts.user_wallet.clone(),
+            is_pump_fun: checklist.is_pump_fun.into(),
+            lp_burnt: checklist.lp_burnt.into(),
+            mint_authority_renounced: checklist.mint_authority_renounced.into(),
+            freeze_authority_renounced: checklist
+                .freeze_authority_renounced
+                .into(),
+            sol_pooled: checklist.sol_pooled,
+            timeout: checklist.timeout.into(),
+            has_transfer_fee: checklist.has_transfer_fee.into(),
+            has_transfer_hook: checklist.has_transfer_hook.into(),
+            has_permanent_delegate: checklist.has_permanent_delegate.into(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "storage"))]
+mod tests {
+    use super::*;
+    use crate::db::{ChecklistSink, MemorySwapSink};
+
+    #[tokio::test]
+    async fn test_checklist_record_columns() {
+        let checklist: Checklist = serde_json::from_reader(
+            std::fs::File::open("fixtures/checklist.json").unwrap(),
+        )
+        .unwrap();
+        let sink = MemorySwapSink::default();
+        sink.insert_checklist(&ChecklistRecord::from(&checklist))
+            .await
+            .unwrap();
+
+        let records = sink.checklists().await;
+        assert_eq!(
+            records,
+            vec![ChecklistRecord {
+                slot: 268470163,
+                mint: "JAJNpxXwtmoPn3DGX5vNe2HJSaa6zLd6LLn1wiPvFnSR"
+                    .to_string(),
+                amm_pool: "Ho8Endys75fzwxFcnp555kzm8Yg8YxTBTXMUzVupiZJZ"
+                    .to_string(),
+                lp_mint: "J3zjncU3RJLyqpyTCaJ22Wh3VN9UbgXwjejDJyeWiTrw"
+                    .to_string(),
+                coin_mint: "JAJNpxXwtmoPn3DGX5vNe2HJSaa6zLd6LLn1wiPvFnSR"
+                    .to_string(),
+                pc_mint: "So11111111111111111111111111111111111111112"
+                    .to_string(),
+                creator: "75mcNqWEY7v1xC3JPgfUQDUhmcFiGGCPa9CaYNdVhw3u"
+                    .to_string(),
+                is_pump_fun: 0,
+                lp_burnt: 1,
+                mint_authority_renounced: 1,
+                freeze_authority_renounced: 0,
+                sol_pooled: 79.000000001,
+                timeout: 0,
+                has_transfer_fee: 0,
+                has_transfer_hook: 0,
+                has_permanent_delegate: 0,
+            }]
+        );
+    }
+}
@@ -17,6 +17,28 @@ pub const WSOL_MINT_KEY_STR: &str =
 pub const USDC_MINT_KEY_STR: &str =
     "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
 
+/// QuoteKind is how a quote mint is valued, off the sol price or 1:1 usd
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuoteKind {
+    Sol,
+    Usd,
+}
+
+/// QUOTE_MINTS are the mints accepted as the quote side of a swap, adding a
+/// quote currency is a matter of adding a row here
+pub const QUOTE_MINTS: &[(&str, QuoteKind)] = &[
+    (WSOL_MINT_KEY_STR, QuoteKind::Sol),
+    (USDC_MINT_KEY_STR, QuoteKind::Usd),
+    (USDT_MINT_KEY_STR, QuoteKind::Usd),
+];
+
+pub fn classify_quote(mint: &str) -> Option<QuoteKind> {
+    QUOTE_MINTS
+        .iter()
+        .find(|(quote, _)| *quote == mint)
+        .map(|(_, kind)| *kind)
+}
+
 pub const RAYDIUM_AUTHORITY_MINT_KEY: Pubkey =
     pubkey!("5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1");
 
@@ -25,3 +47,47 @@ pub const RAYDIUM_AUTHORITY_MINT_KEY_STR: &str =
 
 pub const RAYDIUM_AMM_V4_PROGRAM_ID: Pubkey =
     pubkey!("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8");
+
+pub const RAYDIUM_CP_SWAP_AUTHORITY_KEY_STR: &str =
+    "GpMZbSM2GgvTKHJirzeGfMFoaZ8UR2X7F4v8vHTvxFbL";
+
+pub const RAYDIUM_CP_SWAP_PROGRAM_ID: Pubkey =
+    pubkey!("CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C");
+
+/// PoolKind is the raydium program behind a pool vault
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolKind {
+    AmmV4,
+    CpSwap,
+}
+
+/// POOL_AUTHORITIES are the vault authorities whose balance changes are
+/// collected as swap diffs, each program signs for all of its vaults with a
+/// single authority
+pub const POOL_AUTHORITIES: &[(&str, PoolKind)] = &[
+    (RAYDIUM_AUTHORITY_MINT_KEY_STR, PoolKind::AmmV4),
+    (RAYDIUM_CP_SWAP_AUTHORITY_KEY_STR, PoolKind::CpSwap),
+];
+
+pub fn classify_pool_authority(owner: &str) -> Option<PoolKind> {
+    POOL_AUTHORITIES
+        .iter()
+        .find(|(authority, _)| *authority == owner)
+        .map(|(_, kind)| *kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_quote() {
+        assert_eq!(classify_quote(WSOL_MINT_KEY_STR), Some(QuoteKind::Sol));
+        assert_eq!(classify_quote(USDC_MINT_KEY_STR), Some(QuoteKind::Usd));
+        assert_eq!(classify_quote(USDT_MINT_KEY_STR), Some(QuoteKind::Usd));
+        assert_eq!(
+            classify_quote("CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon"),
+            None
+        );
+    }
+}
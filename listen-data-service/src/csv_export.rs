@@ -0,0 +1,326 @@
+use std::io::Write;
+
+use anyhow::{anyhow, Result};
+use solana_sdk::{pubkey::Pubkey, transaction::VersionedTransaction};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer,
+    EncodedConfirmedTransactionWithStatusMeta, UiInnerInstructions,
+    UiInstruction, UiTransactionStatusMeta,
+};
+use tracing::debug;
+
+use crate::{
+    constants::{RAYDIUM_AMM_V4_PROGRAM_ID, RAYDIUM_CP_SWAP_PROGRAM_ID},
+    diffs::{get_token_balance_diff, process_diffs},
+    sol_price_source::SolPriceSource,
+};
+
+const CSV_HEADER: &str =
+    "signature,slot,pool,mint,price,sol_amount,token_amount,is_buy,timestamp";
+
+/// SwapRecord is a swap as exported for offline analysis, the amounts are
+/// magnitudes in ui units with the direction in `is_buy`; `sol_amount` is of
+/// the quote mint of the pool, sol or a stablecoin
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwapRecord {
+    pub signature: String,
+    pub slot: u64,
+    /// empty if no raydium instruction of the pool was found
+    pub pool: String,
+    pub mint: String,
+    pub price: f64,
+    pub sol_amount: f64,
+    pub token_amount: f64,
+    pub is_buy: bool,
+    /// block time, None if the node didn't have it
+    pub timestamp: Option<i64>,
+}
+
+/// swaps_to_csv writes a header row and a row per swap, none of the fields
+/// can contain a comma so nothing is quoted
+pub fn swaps_to_csv<W: Write>(
+    swaps: &[SwapRecord],
+    mut writer: W,
+) -> Result<()> {
+    writeln!(writer, "{}", CSV_HEADER)?;
+    for swap in swaps {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{}",
+            swap.signature,
+            swap.slot,
+            swap.pool,
+            swap.mint,
+            swap.price,
+            swap.sol_amount,
+            swap.token_amount,
+            swap.is_buy,
+            swap.timestamp.map(|t| t.to_string()).unwrap_or_default(),
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// decode_swap_records runs the transactions through the same diffs the
+/// processor prices swaps from, failed transactions and the ones that are
+/// not a single two-token swap are skipped
+pub fn decode_swap_records(
+    txs: &[EncodedConfirmedTransactionWithStatusMeta],
+    sol_price: &dyn SolPriceSource,
+) -> Vec<SwapRecord> {
+    txs.iter()
+        .filter_map(|tx| match decode_swap_record(tx, sol_price) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                debug!(?e, slot = tx.slot, "skipping transaction");
+                None
+            }
+        })
+        .collect()
+}
+
+fn decode_swap_record(
+    tx: &EncodedConfirmedTransactionWithStatusMeta,
+    sol_price: &dyn SolPriceSource,
+) -> Result<SwapRecord> {
+    let transaction = tx
+        .transaction
+        .transaction
+        .decode()
+        .ok_or_else(|| anyhow!("failed to decode transaction"))?;
+    let meta = tx
+        .transaction
+        .meta
+        .as_ref()
+        .ok_or_else(|| anyhow!("transaction without meta"))?;
+    if let Some(err) = &meta.err {
+        return Err(anyhow!("failed transaction: {}", err));
+    }
+    let (OptionSerializer::Some(pre), OptionSerializer::Some(post)) =
+        (&meta.pre_token_balances, &meta.post_token_balances)
+    else {
+        return Err(anyhow!("transaction without token balances"));
+    };
+
+    let pool = find_pool(&transaction, meta);
+    let diffs = get_token_balance_diff(pre, post);
+    let result = process_diffs(&diffs, sol_price, pool.unwrap_or_default())?;
+
+    Ok(SwapRecord {
+        signature: transaction
+            .signatures
+            .first()
+            .map(|signature| signature.to_string())
+            .unwrap_or_default(),
+        slot: tx.slot,
+        pool: pool.map(|pool| pool.to_string()).unwrap_or_default(),
+        mint: result.coin_mint,
+        price: result.price,
+        sol_amount: result.sol_amount.abs(),
+        token_amount: result.token_amount.abs(),
+        is_buy: result.is_buy,
+        timestamp: tx.block_time,
+    })
+}
+
+/// find_pool reads the pool off the first raydium instruction, top level or
+/// inner (routed through an aggregator); the amm is the second account of a
+/// v4 instruction and the pool state the fourth of a cp swap one, the keys
+/// of a v0 transaction continue with the ones loaded from lookup tables
+fn find_pool(
+    transaction: &VersionedTransaction,
+    meta: &UiTransactionStatusMeta,
+) -> Option<Pubkey> {
+    let mut keys = transaction.message.static_account_keys().to_vec();
+    if let OptionSerializer::Some(loaded) = &meta.loaded_addresses {
+        for key in loaded.writable.iter().chain(&loaded.readonly) {
+            keys.push(key.parse().ok()?);
+        }
+    }
+
+    let top_level = transaction
+        .message
+        .instructions()
+        .iter()
+        .map(|ix| (ix.program_id_index, ix.accounts.as_slice()));
+    let inner: &[UiInnerInstructions] = match &meta.inner_instructions {
+        OptionSerializer::Some(inner) => inner,
+        _ => &[],
+    };
+    let inner = inner.iter().flat_map(|ixs| &ixs.instructions).filter_map(
+        |ix| match ix {
+            UiInstruction::Compiled(ix) => {
+                Some((ix.program_id_index, ix.accounts.as_slice()))
+            }
+            _ => None,
+        },
+    );
+
+    top_level
+        .chain(inner)
+        .find_map(|(program_id_index, accounts)| {
+            let pool_index = match keys.get(program_id_index as usize)? {
+                program if *program == RAYDIUM_AMM_V4_PROGRAM_ID => 1,
+                program if *program == RAYDIUM_CP_SWAP_PROGRAM_ID => 3,
+                _ => return None,
+            };
+            keys.get(*accounts.get(pool_index)? as usize).copied()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sol_price_source::FixedSolPrice;
+    use solana_sdk::{
+        hash::Hash,
+        instruction::{AccountMeta, Instruction},
+        signature::Keypair,
+        signer::Signer,
+        transaction::Transaction,
+    };
+    use solana_transaction_status::{
+        Encodable, EncodedTransactionWithStatusMeta, TransactionStatusMeta,
+        UiTransactionEncoding, UiTransactionTokenBalance,
+    };
+
+    /// make_tx wraps the balances of a fixture in a transaction calling
+    /// `program` with `pool` at `pool_index`
+    fn make_tx(
+        fixture: &str,
+        program: Pubkey,
+        pool: Pubkey,
+        pool_index: usize,
+        block_time: Option<i64>,
+        reverse: bool,
+    ) -> (EncodedConfirmedTransactionWithStatusMeta, String) {
+        let fixture: serde_json::Value =
+            serde_json::from_reader(std::fs::File::open(fixture).unwrap())
+                .unwrap();
+        let balances = |key: &str| {
+            OptionSerializer::Some(
+                serde_json::from_value::<Vec<UiTransactionTokenBalance>>(
+                    fixture["meta"][key].clone(),
+                )
+                .unwrap(),
+            )
+        };
+        let (mut pre, mut post) =
+            (balances("preTokenBalances"), balances("postTokenBalances"));
+        if reverse {
+            std::mem::swap(&mut pre, &mut post);
+        }
+
+        let payer = Keypair::new();
+        let mut accounts = (0..pool_index)
+            .map(|_| AccountMeta::new_readonly(Pubkey::new_unique(), false))
+            .collect::<Vec<_>>();
+        accounts.push(AccountMeta::new(pool, false));
+        let tx = Transaction::new_signed_with_payer(
+            &[Instruction {
+                program_id: program,
+                accounts,
+                data: vec![9],
+            }],
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::default(),
+        );
+        let mut meta: UiTransactionStatusMeta =
+            TransactionStatusMeta::default().into();
+        meta.pre_token_balances = pre;
+        meta.post_token_balances = post;
+
+        let encoded = EncodedConfirmedTransactionWithStatusMeta {
+            slot: fixture["slot"].as_u64().unwrap(),
+            transaction: EncodedTransactionWithStatusMeta {
+                transaction: tx.encode(UiTransactionEncoding::Base64),
+                meta: Some(meta),
+                version: None,
+            },
+            block_time,
+        };
+        (encoded, tx.signatures[0].to_string())
+    }
+
+    #[test]
+    fn test_swaps_to_csv() {
+        let (amm, cp_pool) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // a 0.55 sol buy on a v4 pool
+        let (buy, buy_signature) = make_tx(
+            "fixtures/ephemeral_wsol_tx.json",
+            RAYDIUM_AMM_V4_PROGRAM_ID,
+            amm,
+            1,
+            Some(1_733_000_000),
+            false,
+        );
+        // 1.4m tokens sold for 1 sol on a cp swap pool
+        let (sell, sell_signature) = make_tx(
+            "fixtures/cp_swap_tx.json",
+            RAYDIUM_CP_SWAP_PROGRAM_ID,
+            cp_pool,
+            3,
+            None,
+            true,
+        );
+        // no token balances, not a swap
+        let (mut transfer, _) = make_tx(
+            "fixtures/cp_swap_tx.json",
+            RAYDIUM_CP_SWAP_PROGRAM_ID,
+            cp_pool,
+            3,
+            None,
+            false,
+        );
+        transfer.transaction.meta =
+            Some(TransactionStatusMeta::default().into());
+
+        let records =
+            decode_swap_records(&[buy, transfer, sell], &FixedSolPrice(200.0));
+        let mut csv = vec![];
+        swaps_to_csv(&records, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some(CSV_HEADER));
+        let mint = "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon";
+        let expected = [
+            (
+                buy_signature,
+                amm,
+                0.55 / 6822.422379 * 200.0,
+                0.55,
+                6822.422379,
+                "true",
+                "1733000000",
+            ),
+            (
+                sell_signature,
+                cp_pool,
+                1.0 / 1_400_000.0 * 200.0,
+                1.0,
+                1_400_000.0,
+                "false",
+                "",
+            ),
+        ];
+        for (signature, pool, price, sol, tokens, is_buy, timestamp) in expected
+        {
+            let row = lines.next().unwrap().split(',').collect::<Vec<_>>();
+            let float = |i: usize| row[i].parse::<f64>().unwrap();
+            assert_eq!(row.len(), 9);
+            assert_eq!(row[0], signature);
+            assert_eq!(row[1], "312000000");
+            assert_eq!(row[2], pool.to_string());
+            assert_eq!(row[3], mint);
+            assert!((float(4) - price).abs() / price < 1e-9, "{}", row[4]);
+            assert!((float(5) - sol).abs() < 1e-9, "{}", row[5]);
+            assert!((float(6) - tokens).abs() < 1e-6, "{}", row[6]);
+            assert_eq!(row[7], is_buy);
+            assert_eq!(row[8], timestamp);
+        }
+        assert_eq!(lines.next(), None);
+    }
+}
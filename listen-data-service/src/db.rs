@@ -1,6 +1,6 @@
 use std::{sync::Arc, time::Duration};
 
-use crate::price::PriceUpdate;
+use crate::{checklist::ChecklistRecord, price::PriceUpdate};
 use anyhow::{Context, Result};
 use clickhouse::inserter::Inserter;
 use clickhouse::Client;
@@ -24,6 +24,20 @@ pub trait Database {
     async fn insert_price(&self, price: &PriceUpdate) -> Result<()>;
 }
 
+/// SwapSink is the narrow write path used by the swap processor, it allows
+/// running the processing logic against something other than Clickhouse
+#[async_trait::async_trait]
+pub trait SwapSink: Send + Sync {
+    async fn insert_swap(&self, swap: &PriceUpdate) -> Result<()>;
+}
+
+/// ChecklistSink persists the checker's results alongside the swaps, for a
+/// dataset of the launches that were flagged safe vs the ones that rugged
+#[async_trait::async_trait]
+pub trait ChecklistSink: Send + Sync {
+    async fn insert_checklist(&self, record: &ChecklistRecord) -> Result<()>;
+}
+
 pub struct ClickhouseDb {
     client: Client,
     inserter: Option<Arc<RwLock<Inserter<PriceUpdate>>>>,
@@ -100,6 +114,11 @@ impl Database for ClickhouseDb {
                     signature String,
                     multi_hop Bool,
                     is_buy Bool,
+                    is_suspected_wash Bool,
+                    spot_price Nullable(Float64),
+                    sol_amount Float64,
+                    token_amount Float64,
+                    amm_pool String,
                     INDEX idx_mints (name, pubkey) TYPE minmax GRANULARITY 1
                 ) 
                 ENGINE = MergeTree()
@@ -110,6 +129,72 @@ impl Database for ClickhouseDb {
             .await
             .context("Failed to create price_updates table")?;
 
+        // tables created before the column existed
+        self.client
+            .query(
+                "ALTER TABLE price_updates ADD COLUMN IF NOT EXISTS is_suspected_wash Bool DEFAULT false",
+            )
+            .execute()
+            .await
+            .context("Failed to add is_suspected_wash column")?;
+        self.client
+            .query(
+                "ALTER TABLE price_updates ADD COLUMN IF NOT EXISTS spot_price Nullable(Float64)",
+            )
+            .execute()
+            .await
+            .context("Failed to add spot_price column")?;
+        self.client
+            .query(
+                "ALTER TABLE price_updates ADD COLUMN IF NOT EXISTS sol_amount Float64 DEFAULT 0",
+            )
+            .execute()
+            .await
+            .context("Failed to add sol_amount column")?;
+        self.client
+            .query(
+                "ALTER TABLE price_updates ADD COLUMN IF NOT EXISTS token_amount Float64 DEFAULT 0",
+            )
+            .execute()
+            .await
+            .context("Failed to add token_amount column")?;
+        self.client
+            .query(
+                "ALTER TABLE price_updates ADD COLUMN IF NOT EXISTS amm_pool String DEFAULT ''",
+            )
+            .execute()
+            .await
+            .context("Failed to add amm_pool column")?;
+
+        self.client
+            .query(
+                r#"
+                CREATE TABLE IF NOT EXISTS checklists (
+                    slot UInt64,
+                    mint String,
+                    amm_pool String,
+                    lp_mint String,
+                    coin_mint String,
+                    pc_mint String,
+                    creator String,
+                    is_pump_fun UInt8,
+                    lp_burnt UInt8,
+                    mint_authority_renounced UInt8,
+                    freeze_authority_renounced UInt8,
+                    sol_pooled Float64,
+                    timeout UInt8,
+                    has_transfer_fee UInt8,
+                    has_transfer_hook UInt8,
+                    has_permanent_delegate UInt8
+                )
+                ENGINE = MergeTree()
+                ORDER BY (mint, slot)
+                "#,
+            )
+            .execute()
+            .await
+            .context("Failed to create checklists table")?;
+
         self.inserter = Some(Arc::new(RwLock::new(self.create_inserter()?)));
         self.is_initialized = true;
 
@@ -144,6 +229,80 @@ impl Database for ClickhouseDb {
     }
 }
 
+#[async_trait::async_trait]
+impl SwapSink for ClickhouseDb {
+    async fn insert_swap(&self, swap: &PriceUpdate) -> Result<()> {
+        self.insert_price(swap).await
+    }
+}
+
+#[async_trait::async_trait]
+impl ChecklistSink for ClickhouseDb {
+    /// checklists are rare compared to swaps, no need to batch these
+    async fn insert_checklist(&self, record: &ChecklistRecord) -> Result<()> {
+        debug!("inserting checklist: {}", record.mint);
+        let mut insert = self
+            .client
+            .insert::<ChecklistRecord>("checklists")
+            .context("failed to prepare checklist insert statement")?;
+        insert
+            .write(record)
+            .await
+            .context("Failed to write checklist")?;
+        insert.end().await.context("Failed to insert checklist")?;
+        Ok(())
+    }
+}
+
+/// in-memory sink for testing the processor without a Clickhouse instance
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemorySwapSink {
+    swaps: RwLock<Vec<PriceUpdate>>,
+    checklists: RwLock<Vec<ChecklistRecord>>,
+    fail_inserts: bool,
+}
+
+#[cfg(test)]
+impl MemorySwapSink {
+    /// failing rejects every swap insert, like an unreachable Clickhouse
+    pub fn failing() -> Self {
+        Self {
+            fail_inserts: true,
+            ..Default::default()
+        }
+    }
+
+    pub async fn swaps(&self) -> Vec<PriceUpdate> {
+        self.swaps.read().await.clone()
+    }
+
+    pub async fn checklists(&self) -> Vec<ChecklistRecord> {
+        self.checklists.read().await.clone()
+    }
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl ChecklistSink for MemorySwapSink {
+    async fn insert_checklist(&self, record: &ChecklistRecord) -> Result<()> {
+        self.checklists.write().await.push(record.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl SwapSink for MemorySwapSink {
+    async fn insert_swap(&self, swap: &PriceUpdate) -> Result<()> {
+        if self.fail_inserts {
+            return Err(anyhow::anyhow!("Memory sink rejected the insert"));
+        }
+        self.swaps.write().await.push(swap.clone());
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::util::make_db;
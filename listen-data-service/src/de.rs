@@ -59,7 +59,7 @@ where
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "storage"))]
 mod tests {
     use crate::metadata::IpfsMetadata;
 
@@ -0,0 +1,246 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{program_pack::Pack, pubkey::Pubkey};
+use spl_token::state::Mint;
+use tokio::sync::RwLock;
+use tracing::debug;
+
+use crate::kv_store::{KVStore, RedisKVStore};
+
+/// DecimalsStore persists the decimals of mints, they never change once the
+/// mint is initialized so entries don't expire
+#[async_trait::async_trait]
+pub trait DecimalsStore: Send + Sync {
+    async fn get_decimals(&self, mint: &Pubkey) -> Result<Option<u8>>;
+    async fn set_decimals(&self, mint: &Pubkey, decimals: u8) -> Result<()>;
+}
+
+#[async_trait::async_trait]
+impl DecimalsStore for RedisKVStore {
+    async fn get_decimals(&self, mint: &Pubkey) -> Result<Option<u8>> {
+        self.get(&RedisKVStore::make_decimals_key(mint)).await
+    }
+
+    async fn set_decimals(&self, mint: &Pubkey, decimals: u8) -> Result<()> {
+        self.set(&RedisKVStore::make_decimals_key(mint), &decimals)
+            .await
+    }
+}
+
+/// MintFetcher reads the raw mint account on a cache miss
+#[async_trait::async_trait]
+pub trait MintFetcher: Send + Sync {
+    async fn fetch_mint(&self, mint: &Pubkey) -> Result<Vec<u8>>;
+}
+
+#[async_trait::async_trait]
+impl MintFetcher for RpcClient {
+    async fn fetch_mint(&self, mint: &Pubkey) -> Result<Vec<u8>> {
+        self.get_account_data(mint)
+            .await
+            .context("failed to get mint account")
+    }
+}
+
+/// mint fetcher for testing, for stores that already hold the decimals
+#[cfg(test)]
+pub struct NoMintFetcher;
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl MintFetcher for NoMintFetcher {
+    async fn fetch_mint(&self, mint: &Pubkey) -> Result<Vec<u8>> {
+        Err(anyhow!("Unexpected fetch of mint {}", mint))
+    }
+}
+
+/// mints held in memory by default, every new token that trades adds one
+pub const DEFAULT_MEMORY_CAPACITY: usize = 100_000;
+
+/// MemoryDecimals is the in-process layer, bounded to `capacity` mints with
+/// the oldest one evicted first; decimals never change so there is nothing
+/// to gain from tracking recency, an evicted mint is read back from the store
+struct MemoryDecimals {
+    decimals: HashMap<Pubkey, u8>,
+    order: VecDeque<Pubkey>,
+    capacity: usize,
+}
+
+impl MemoryDecimals {
+    fn new(capacity: usize) -> Self {
+        Self {
+            decimals: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    fn insert(&mut self, mint: Pubkey, decimals: u8) {
+        if self.capacity == 0 || self.decimals.contains_key(&mint) {
+            return;
+        }
+        if self.decimals.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.decimals.remove(&oldest);
+            }
+        }
+        self.decimals.insert(mint, decimals);
+        self.order.push_back(mint);
+    }
+}
+
+/// DecimalsCache resolves the decimals of a mint from memory, then the kv
+/// store, then the mint account itself, filling the layers above on the way
+pub struct DecimalsCache<
+    S: DecimalsStore = RedisKVStore,
+    F: MintFetcher = RpcClient,
+> {
+    store: Arc<S>,
+    fetcher: Arc<F>,
+    memory: RwLock<MemoryDecimals>,
+}
+
+impl<S: DecimalsStore, F: MintFetcher> DecimalsCache<S, F> {
+    pub fn new(store: Arc<S>, fetcher: Arc<F>) -> Self {
+        Self {
+            store,
+            fetcher,
+            memory: RwLock::new(MemoryDecimals::new(DEFAULT_MEMORY_CAPACITY)),
+        }
+    }
+
+    /// with_capacity bounds the number of mints held in memory
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.memory = RwLock::new(MemoryDecimals::new(capacity));
+        self
+    }
+
+    pub async fn decimals(&self, mint: &Pubkey) -> Result<u8> {
+        if let Some(decimals) = self.memory.read().await.decimals.get(mint) {
+            return Ok(*decimals);
+        }
+
+        let decimals = match self.store.get_decimals(mint).await? {
+            Some(decimals) => decimals,
+            None => {
+                let decimals =
+                    unpack_decimals(&self.fetcher.fetch_mint(mint).await?)?;
+                self.store.set_decimals(mint, decimals).await?;
+                debug!(%mint, decimals, "decimals fetched");
+                decimals
+            }
+        };
+        self.memory.write().await.insert(*mint, decimals);
+        Ok(decimals)
+    }
+}
+
+/// unpack_decimals reads the decimals of a mint of either token program,
+/// Token-2022 extensions come after the base mint layout
+pub fn unpack_decimals(data: &[u8]) -> Result<u8> {
+    let base = data
+        .get(..Mint::LEN)
+        .ok_or_else(|| anyhow!("Mint account too short: {}", data.len()))?;
+    Ok(Mint::unpack(base)
+        .context("failed to unpack mint data")?
+        .decimals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct MemoryDecimalsStore {
+        decimals: std::sync::Mutex<HashMap<Pubkey, u8>>,
+    }
+
+    #[async_trait::async_trait]
+    impl DecimalsStore for MemoryDecimalsStore {
+        async fn get_decimals(&self, mint: &Pubkey) -> Result<Option<u8>> {
+            Ok(self.decimals.lock().unwrap().get(mint).copied())
+        }
+
+        async fn set_decimals(
+            &self,
+            mint: &Pubkey,
+            decimals: u8,
+        ) -> Result<()> {
+            self.decimals.lock().unwrap().insert(*mint, decimals);
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct CountingFetcher {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl MintFetcher for CountingFetcher {
+        async fn fetch_mint(&self, _mint: &Pubkey) -> Result<Vec<u8>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let mut data = vec![0; Mint::LEN];
+            Mint {
+                decimals: 6,
+                is_initialized: true,
+                ..Default::default()
+            }
+            .pack_into_slice(&mut data);
+            Ok(data)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decimals_cached_after_first_lookup() {
+        let store = Arc::new(MemoryDecimalsStore::default());
+        let fetcher = Arc::new(CountingFetcher::default());
+        let mint = Pubkey::new_unique();
+
+        let cache = DecimalsCache::new(store.clone(), fetcher.clone());
+        assert_eq!(cache.decimals(&mint).await.unwrap(), 6);
+        assert_eq!(cache.decimals(&mint).await.unwrap(), 6);
+        assert_eq!(fetcher.calls.load(Ordering::SeqCst), 1);
+
+        // a fresh instance (e.g. after a restart) reads the persisted value
+        let cache = DecimalsCache::new(store.clone(), fetcher.clone());
+        assert_eq!(cache.decimals(&mint).await.unwrap(), 6);
+        assert_eq!(fetcher.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_memory_bounded() {
+        let store = Arc::new(MemoryDecimalsStore::default());
+        let fetcher = Arc::new(CountingFetcher::default());
+        let mints = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+
+        let cache =
+            DecimalsCache::new(store.clone(), fetcher.clone()).with_capacity(2);
+        for mint in &mints {
+            assert_eq!(cache.decimals(mint).await.unwrap(), 6);
+        }
+        {
+            let memory = cache.memory.read().await;
+            assert_eq!(memory.decimals.len(), 2);
+            assert!(!memory.decimals.contains_key(&mints[0]));
+        }
+
+        // the evicted mint comes back from the store, not the account
+        assert_eq!(cache.decimals(&mints[0]).await.unwrap(), 6);
+        assert_eq!(fetcher.calls.load(Ordering::SeqCst), 3);
+        assert!(!cache.memory.read().await.decimals.contains_key(&mints[1]));
+    }
+
+    #[test]
+    fn test_unpack_decimals_short_data() {
+        assert!(unpack_decimals(&[0; 10]).is_err());
+    }
+}
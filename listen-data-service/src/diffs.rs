@@ -1,16 +1,86 @@
 use std::collections::HashMap;
 
 use anyhow::Result;
+use solana_sdk::pubkey::Pubkey;
 use solana_transaction_status::{
-    TransactionTokenBalance, UiTransactionTokenBalance,
+    option_serializer::OptionSerializer, TransactionTokenBalance,
+    UiTransactionStatusMeta, UiTransactionTokenBalance,
 };
 
-use crate::constants::{RAYDIUM_AUTHORITY_MINT_KEY_STR, WSOL_MINT_KEY_STR};
+use crate::constants::{
+    classify_pool_authority, classify_quote, PoolKind, QuoteKind,
+    POOL_AUTHORITIES, WSOL_MINT_KEY_STR,
+};
+use crate::sol_price_source::SolPriceSource;
 
 pub trait TokenBalanceInfo {
     fn get_mint(&self) -> &str;
     fn get_ui_amount(&self) -> Option<f64>;
     fn get_owner(&self) -> &str;
+    fn get_raw_amount(&self) -> Option<u64>;
+    fn get_decimals(&self) -> u8;
+
+    /// amount in ui units, computed from the raw amount and decimals when the
+    /// node left ui_amount null
+    fn get_amount(&self) -> Option<f64> {
+        self.get_ui_amount().or_else(|| {
+            self.get_raw_amount()
+                .map(|raw| normalize_amount(raw, self.get_decimals()))
+        })
+    }
+}
+
+/// normalize_amount converts a raw token amount to ui units, the legs of a
+/// swap have different decimals (9 for wsol, 6 for most tokens) so raw
+/// amounts can't be divided into a price before this
+pub fn normalize_amount(raw: u64, decimals: u8) -> f64 {
+    raw as f64 / 10f64.powi(decimals as i32)
+}
+
+/// balances can be passed by reference or boxed (`Box<dyn TokenBalanceInfo>`),
+/// e.g. to put native and parsed balances in the same slice
+impl<T: TokenBalanceInfo + ?Sized> TokenBalanceInfo for &T {
+    fn get_mint(&self) -> &str {
+        (**self).get_mint()
+    }
+
+    fn get_ui_amount(&self) -> Option<f64> {
+        (**self).get_ui_amount()
+    }
+
+    fn get_owner(&self) -> &str {
+        (**self).get_owner()
+    }
+
+    fn get_raw_amount(&self) -> Option<u64> {
+        (**self).get_raw_amount()
+    }
+
+    fn get_decimals(&self) -> u8 {
+        (**self).get_decimals()
+    }
+}
+
+impl<T: TokenBalanceInfo + ?Sized> TokenBalanceInfo for Box<T> {
+    fn get_mint(&self) -> &str {
+        (**self).get_mint()
+    }
+
+    fn get_ui_amount(&self) -> Option<f64> {
+        (**self).get_ui_amount()
+    }
+
+    fn get_owner(&self) -> &str {
+        (**self).get_owner()
+    }
+
+    fn get_raw_amount(&self) -> Option<u64> {
+        (**self).get_raw_amount()
+    }
+
+    fn get_decimals(&self) -> u8 {
+        (**self).get_decimals()
+    }
 }
 
 impl TokenBalanceInfo for TransactionTokenBalance {
@@ -25,6 +95,14 @@ impl TokenBalanceInfo for TransactionTokenBalance {
     fn get_owner(&self) -> &str {
         &self.owner
     }
+
+    fn get_raw_amount(&self) -> Option<u64> {
+        self.ui_token_amount.amount.parse().ok()
+    }
+
+    fn get_decimals(&self) -> u8 {
+        self.ui_token_amount.decimals
+    }
 }
 
 impl TokenBalanceInfo for UiTransactionTokenBalance {
@@ -39,6 +117,71 @@ impl TokenBalanceInfo for UiTransactionTokenBalance {
     fn get_owner(&self) -> &str {
         self.owner.as_ref().map(|s| s.as_str()).unwrap_or_default()
     }
+
+    fn get_raw_amount(&self) -> Option<u64> {
+        self.ui_token_amount.amount.parse().ok()
+    }
+
+    fn get_decimals(&self) -> u8 {
+        self.ui_token_amount.decimals
+    }
+}
+
+/// pool_spot_price is the usd price of the coin from the pool reserves, the
+/// reserve ratio in ui units times the usd price of the pc side (the sol
+/// price for a wsol pool); 0 for a pool without coin reserve
+pub fn pool_spot_price(
+    coin_reserve: u64,
+    pc_reserve: u64,
+    coin_decimals: u8,
+    pc_decimals: u8,
+    sol_price: f64,
+) -> f64 {
+    if coin_reserve == 0 {
+        return 0.0;
+    }
+    normalize_amount(pc_reserve, pc_decimals)
+        / normalize_amount(coin_reserve, coin_decimals)
+        * sol_price
+}
+
+/// get_pool_spot_price prices `coin_mint` from the post balances of the pool
+/// vaults, the state the transaction left the pool in; None if the vaults
+/// of a single pool can't be told apart, e.g. a multi-hop touching two
+/// pools with a wsol vault
+pub fn get_pool_spot_price<T: TokenBalanceInfo>(
+    post_balances: &[T],
+    coin_mint: &str,
+    sol_price: &dyn SolPriceSource,
+) -> Option<f64> {
+    let mut coin_vault = None;
+    let mut pc_vault = None;
+    for balance in post_balances
+        .iter()
+        .filter(|b| classify_pool_authority(b.get_owner()).is_some())
+    {
+        if balance.get_mint() == coin_mint {
+            if coin_vault.replace(balance).is_some() {
+                return None;
+            }
+        } else if let Some(quote) = classify_quote(balance.get_mint()) {
+            if pc_vault.replace((balance, quote)).is_some() {
+                return None;
+            }
+        }
+    }
+    let (coin_vault, (pc_vault, quote)) = (coin_vault?, pc_vault?);
+    let quote_price = match quote {
+        QuoteKind::Sol => sol_price.sol_price(),
+        QuoteKind::Usd => 1.0,
+    };
+    Some(pool_spot_price(
+        coin_vault.get_raw_amount()?,
+        pc_vault.get_raw_amount()?,
+        coin_vault.get_decimals(),
+        pc_vault.get_decimals(),
+        quote_price,
+    ))
 }
 
 #[derive(Debug)]
@@ -47,39 +190,169 @@ pub struct DiffsResult {
     pub swap_amount: f64,
     pub coin_mint: String,
     pub is_buy: bool,
+    /// signed change of the pool's quote vault (sol or a stablecoin),
+    /// positive on a buy
+    pub sol_amount: f64,
+    /// signed change of the pool's token vault, negative on a buy
+    pub token_amount: f64,
+    pub amm_pool: Pubkey,
+    /// program of the pool, from the authority owning its vaults
+    pub pool_kind: Option<PoolKind>,
 }
 
-pub fn process_diffs(diffs: &Vec<Diff>, sol_price: f64) -> Result<DiffsResult> {
+/// process_diffs prices the swap from the pool's vault diffs, the diffs are
+/// in ui units (see `TokenBalanceInfo::get_amount`) so the price comes out in
+/// quote per token regardless of the decimals of either mint
+pub fn process_diffs(
+    diffs: &Vec<Diff>,
+    sol_price: &dyn SolPriceSource,
+    amm_pool: Pubkey,
+) -> Result<DiffsResult> {
     if diffs.len() != 2 {
         return Err(anyhow::anyhow!("Expected exactly 2 token balance diffs"));
     }
 
     let (token0, token1) = (&diffs[0], &diffs[1]);
+    let pool_kind = classify_pool_authority(&token0.owner)
+        .or_else(|| classify_pool_authority(&token1.owner));
 
     let amount0 = token0.diff;
     let amount1 = token1.diff;
 
-    let (sol_amount, token_amount, coin_mint) =
-        match (token0.mint.as_str(), token1.mint.as_str()) {
-            (WSOL_MINT_KEY_STR, other_mint) => (amount0, amount1, other_mint),
-            (other_mint, WSOL_MINT_KEY_STR) => (amount1, amount0, other_mint),
-            _ => return Err(anyhow::anyhow!("Non-WSOL swap")),
+    let (quote, sol_amount, token_amount, coin_mint) =
+        match (classify_quote(&token0.mint), classify_quote(&token1.mint)) {
+            (Some(quote), None) => (quote, amount0, amount1, &token1.mint),
+            (None, Some(quote)) => (quote, amount1, amount0, &token0.mint),
+            (Some(_), Some(_)) => {
+                return Err(anyhow::anyhow!("Quote to quote swap"))
+            }
+            (None, None) => return Err(anyhow::anyhow!("Non-quote swap")),
         };
 
-    // raydium token balance negative
-    let is_buy = token_amount < 0.0;
+    let is_buy = is_buy(pool_kind, sol_amount, token_amount)?;
 
     let sol_amount_abs = sol_amount.abs();
     let token_amount_abs = token_amount.abs();
 
-    let price = (sol_amount_abs / token_amount_abs) * sol_price;
-    let swap_amount = sol_amount_abs * sol_price;
+    let quote_price = match quote {
+        QuoteKind::Sol => sol_price.sol_price(),
+        QuoteKind::Usd => 1.0,
+    };
+    let price = (sol_amount_abs / token_amount_abs) * quote_price;
+    let swap_amount = sol_amount_abs * quote_price;
 
     Ok(DiffsResult {
         price,
         swap_amount,
         coin_mint: coin_mint.to_string(),
         is_buy,
+        sol_amount,
+        token_amount,
+        amm_pool,
+        pool_kind,
+    })
+}
+
+/// is_buy tells a buy from a sell by the signs of the legs, which side of
+/// the swap the diffs are on depends on the pool kind
+///
+/// a swap moves the legs in opposite directions, legs moving the same way
+/// come from wrapping/unwrapping sol around another action (or liquidity
+/// changes) and would be priced as a phantom swap
+pub fn is_buy(
+    pool_kind: Option<PoolKind>,
+    sol_amount: f64,
+    token_amount: f64,
+) -> Result<bool> {
+    if sol_amount * token_amount >= 0.0 {
+        return Err(anyhow::anyhow!(
+            "Not a pool swap, sol {} and token {} move the same way",
+            sol_amount,
+            token_amount
+        ));
+    }
+    match pool_kind {
+        // the diffs are of the vaults, a buy pays the tokens out of the pool
+        Some(PoolKind::AmmV4 | PoolKind::CpSwap) => Ok(token_amount < 0.0),
+        None => Err(anyhow::anyhow!(
+            "Unknown pool kind, can't tell the side of the diffs"
+        )),
+    }
+}
+
+/// NativeBalance is the trader's lamport balance around the transaction,
+/// fee should be 0 unless the trader is the fee payer
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NativeBalance {
+    pub pre_lamports: u64,
+    pub post_lamports: u64,
+    pub fee: u64,
+}
+
+/// TraderSwap is the swap from the trader's side, positive amounts are
+/// received and negative ones spent
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraderSwap {
+    /// every lamport the trader moved, not only the swap itself, see
+    /// [`get_trader_diff`]
+    pub sol_amount: f64,
+    pub token_amount: f64,
+    pub coin_mint: String,
+}
+
+/// get_trader_diff computes the trader's own sol and token deltas
+///
+/// the sol delta is the lamport change (fee excluded) plus the change of a
+/// persistent wsol account; a wsol account funded from native sol, swapped
+/// and closed (or left as it was) within the transaction nets to zero in the
+/// token balances and the cost is all in the lamport change, an ephemeral one
+/// doesn't show in the token balances at all; the wsol leg is summed in raw
+/// lamports so that a zero net doesn't leave float noise in the cost basis
+///
+/// the lamport change is everything the trader paid or got back in the
+/// transaction, not only the swap: the rent of accounts it created (the
+/// token ata on a first buy, ~0.00204 sol) and refunded on close, jito or
+/// priority tips transferred to other accounts are all in `sol_amount`; the
+/// rent and tips can't be told apart from the swap without the instructions,
+/// so on small swaps the cost basis is inflated by them
+pub fn get_trader_diff<T: TokenBalanceInfo>(
+    pre_balances: &[T],
+    post_balances: &[T],
+    trader: &str,
+    native: NativeBalance,
+) -> Option<TraderSwap> {
+    let mut token_diffs: HashMap<String, f64> = HashMap::new();
+    let mut wsol_lamports_diff: i128 = 0;
+    for (balances, sign) in [(pre_balances, -1), (post_balances, 1)] {
+        for balance in balances.iter().filter(|b| b.get_owner() == trader) {
+            if balance.get_mint() == WSOL_MINT_KEY_STR {
+                wsol_lamports_diff +=
+                    sign * balance.get_raw_amount().unwrap_or_default() as i128;
+                continue;
+            }
+            *token_diffs
+                .entry(balance.get_mint().to_string())
+                .or_default() +=
+                sign as f64 * balance.get_amount().unwrap_or_default();
+        }
+    }
+
+    let lamports_diff = native.post_lamports as i128
+        - native.pre_lamports as i128
+        + native.fee as i128;
+    let sol_amount = (lamports_diff + wsol_lamports_diff) as f64 / 1e9;
+
+    let mut moved = token_diffs.into_iter().filter(|(_, diff)| *diff != 0.0);
+    let (coin_mint, token_amount) = moved.next()?;
+    if moved.next().is_some() {
+        // multi-token routes can't be attributed to a single mint
+        return None;
+    }
+
+    Some(TraderSwap {
+        sol_amount,
+        token_amount,
+        coin_mint,
     })
 }
 
@@ -92,16 +365,28 @@ pub struct Diff {
     pub owner: String,
 }
 
-pub fn get_token_balance_diff<T: TokenBalanceInfo + std::fmt::Debug>(
-    pre_balances: &[T],
-    post_balances: &[T],
+/// get_token_balance_diff collects the diffs of the raydium vaults, the pre
+/// and post balances don't have to be of the same representation
+pub fn get_token_balance_diff<P: TokenBalanceInfo, Q: TokenBalanceInfo>(
+    pre_balances: &[P],
+    post_balances: &[Q],
+) -> Vec<Diff> {
+    get_token_balance_diff_for(pre_balances, post_balances, POOL_AUTHORITIES)
+}
+
+/// get_token_balance_diff_for collects the diffs of the vaults owned by one
+/// of `authorities`
+pub fn get_token_balance_diff_for<P: TokenBalanceInfo, Q: TokenBalanceInfo>(
+    pre_balances: &[P],
+    post_balances: &[Q],
+    authorities: &[(&str, PoolKind)],
 ) -> Vec<Diff> {
     let mut diffs = Vec::new();
     let mut pre_balances_map = HashMap::new();
     let mut post_balances_map = HashMap::new();
 
     for balance in pre_balances {
-        if let Some(amount) = balance.get_ui_amount() {
+        if let Some(amount) = balance.get_amount() {
             let key = (
                 balance.get_mint().to_string(),
                 balance.get_owner().to_string(),
@@ -111,7 +396,7 @@ pub fn get_token_balance_diff<T: TokenBalanceInfo + std::fmt::Debug>(
     }
 
     for balance in post_balances {
-        if let Some(amount) = balance.get_ui_amount() {
+        if let Some(amount) = balance.get_amount() {
             let key = (
                 balance.get_mint().to_string(),
                 balance.get_owner().to_string(),
@@ -120,8 +405,11 @@ pub fn get_token_balance_diff<T: TokenBalanceInfo + std::fmt::Debug>(
         }
     }
 
-    let should_collect =
-        |diff: &Diff| diff.owner == RAYDIUM_AUTHORITY_MINT_KEY_STR;
+    let should_collect = |diff: &Diff| {
+        authorities
+            .iter()
+            .any(|(authority, _)| diff.owner == *authority)
+    };
 
     for ((mint, owner), pre_amount) in pre_balances_map.iter() {
         if let Some(post_amount) =
@@ -158,3 +446,542 @@ pub fn get_token_balance_diff<T: TokenBalanceInfo + std::fmt::Debug>(
 
     diffs
 }
+
+/// diffs_from_meta collects the vault diffs straight from the token balances
+/// of a transaction's meta, a meta without them has no diffs
+pub fn diffs_from_meta(meta: &UiTransactionStatusMeta) -> Vec<Diff> {
+    fn balances(
+        balances: &OptionSerializer<Vec<UiTransactionTokenBalance>>,
+    ) -> &[UiTransactionTokenBalance] {
+        match balances {
+            OptionSerializer::Some(balances) => balances,
+            _ => &[],
+        }
+    }
+    get_token_balance_diff(
+        balances(&meta.pre_token_balances),
+        balances(&meta.post_token_balances),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::RAYDIUM_AUTHORITY_MINT_KEY_STR;
+    use crate::sol_price_source::FixedSolPrice;
+    use crate::util::{make_rpc_client, round_to_decimals};
+
+    #[derive(serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Meta {
+        fee: u64,
+        pre_balances: Vec<u64>,
+        post_balances: Vec<u64>,
+        pre_token_balances: Vec<UiTransactionTokenBalance>,
+        post_token_balances: Vec<UiTransactionTokenBalance>,
+    }
+
+    #[derive(serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Fixture {
+        account_keys: Vec<String>,
+        meta: Meta,
+    }
+
+    #[test]
+    fn test_trader_diff_ephemeral_wsol() {
+        // buy of 0.55 sol, the wsol account of the trader (index 4) is created
+        // and closed within the transaction so it has no token balances
+        let fixture: Fixture = serde_json::from_reader(
+            std::fs::File::open("fixtures/ephemeral_wsol_tx.json").unwrap(),
+        )
+        .unwrap();
+        let trader = &fixture.account_keys[0];
+        let meta = &fixture.meta;
+
+        let swap = get_trader_diff(
+            &meta.pre_token_balances,
+            &meta.post_token_balances,
+            trader,
+            NativeBalance {
+                pre_lamports: meta.pre_balances[0],
+                post_lamports: meta.post_balances[0],
+                fee: meta.fee,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            swap.coin_mint,
+            "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon"
+        );
+        assert_eq!(swap.sol_amount, -0.55);
+        assert_eq!(swap.token_amount, 6822.422379);
+
+        // the pool side is the mirror of it
+        let diffs = get_token_balance_diff(
+            &meta.pre_token_balances,
+            &meta.post_token_balances,
+        );
+        let pool_sol = diffs
+            .iter()
+            .find(|d| d.mint == WSOL_MINT_KEY_STR)
+            .unwrap()
+            .diff;
+        assert!((pool_sol + swap.sol_amount).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_trader_diff_persistent_wsol_nets_zero() {
+        // buy of 0.5 sol through the persistent wsol account of the trader
+        // (index 4), wrapped from native sol and swapped out in the same
+        // transaction, its token balance is 0.1 both before and after
+        let fixture: Fixture = serde_json::from_reader(
+            std::fs::File::open("fixtures/persistent_wsol_tx.json").unwrap(),
+        )
+        .unwrap();
+        let trader = &fixture.account_keys[0];
+        let meta = &fixture.meta;
+
+        let swap = get_trader_diff(
+            &meta.pre_token_balances,
+            &meta.post_token_balances,
+            trader,
+            NativeBalance {
+                pre_lamports: meta.pre_balances[0],
+                post_lamports: meta.post_balances[0],
+                fee: meta.fee,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            swap.coin_mint,
+            "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon"
+        );
+        assert_eq!(swap.sol_amount, -0.5);
+        assert_eq!(swap.token_amount, 6202.200163);
+    }
+
+    #[test]
+    fn test_null_ui_amount_falls_back_to_raw() {
+        let balance = |mint: &str, amount: &str, ui_amount: Option<f64>| {
+            serde_json::from_value::<UiTransactionTokenBalance>(
+                serde_json::json!({
+                    "accountIndex": 1,
+                    "mint": mint,
+                    "uiTokenAmount": {
+                        "amount": amount,
+                        "decimals": 6,
+                        "uiAmount": ui_amount,
+                        "uiAmountString": "",
+                    },
+                    "owner": RAYDIUM_AUTHORITY_MINT_KEY_STR,
+                }),
+            )
+            .unwrap()
+        };
+        let mint = "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon";
+        let pre = vec![balance(mint, "2500000", None)];
+        let post = vec![balance(mint, "1000000", Some(1.0))];
+
+        assert_eq!(pre[0].get_amount(), Some(2.5));
+        let diffs = get_token_balance_diff(&pre, &post);
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].pre_amount, 2.5);
+        assert_eq!(diffs[0].diff, -1.5);
+    }
+
+    #[test]
+    fn test_price_same_from_raw_amounts() {
+        let fixture: serde_json::Value = serde_json::from_reader(
+            std::fs::File::open("fixtures/ephemeral_wsol_tx.json").unwrap(),
+        )
+        .unwrap();
+        // same transaction as served by a node leaving ui_amount null
+        let mut raw_only = fixture.clone();
+        for key in ["preTokenBalances", "postTokenBalances"] {
+            for balance in raw_only["meta"][key].as_array_mut().unwrap() {
+                balance["uiTokenAmount"]["uiAmount"] = serde_json::Value::Null;
+            }
+        }
+
+        let price = |fixture: serde_json::Value| {
+            let fixture: Fixture = serde_json::from_value(fixture).unwrap();
+            let diffs = get_token_balance_diff(
+                &fixture.meta.pre_token_balances,
+                &fixture.meta.post_token_balances,
+            );
+            process_diffs(&diffs, &FixedSolPrice(200.0), Pubkey::default())
+                .unwrap()
+                .price
+        };
+        let from_ui = price(fixture);
+        let from_raw = price(raw_only);
+        // 0.55 sol for ~6822 tokens (6 decimals vs 9 for wsol)
+        assert!((from_ui - 0.55 / 6822.422379 * 200.0).abs() < 1e-6);
+        assert!((from_ui - from_raw).abs() / from_ui < 1e-9);
+    }
+
+    #[test]
+    fn test_diffs_from_meta_mixed_representations() {
+        let mut fixture: serde_json::Value = serde_json::from_reader(
+            std::fs::File::open("fixtures/ephemeral_wsol_tx.json").unwrap(),
+        )
+        .unwrap();
+        // the fixture only keeps the fields the other tests need
+        fixture["meta"]["err"] = serde_json::Value::Null;
+        fixture["meta"]["status"] = serde_json::json!({ "Ok": null });
+        let meta: UiTransactionStatusMeta =
+            serde_json::from_value(fixture["meta"].take()).unwrap();
+
+        let sorted = |mut diffs: Vec<Diff>| {
+            diffs.sort_by(|a, b| a.mint.cmp(&b.mint));
+            diffs
+                .into_iter()
+                .map(|d| (d.mint, d.diff))
+                .collect::<Vec<_>>()
+        };
+        let diffs = sorted(diffs_from_meta(&meta));
+        assert_eq!(diffs.len(), 2);
+        let pool_sol = diffs
+            .iter()
+            .find(|(mint, _)| mint == WSOL_MINT_KEY_STR)
+            .unwrap()
+            .1;
+        assert!((pool_sol - 0.55).abs() < 1e-9);
+
+        // native pre balances against parsed post balances
+        let OptionSerializer::Some(pre) = &meta.pre_token_balances else {
+            panic!("no pre token balances");
+        };
+        let OptionSerializer::Some(post) = &meta.post_token_balances else {
+            panic!("no post token balances");
+        };
+        let native_pre = pre
+            .iter()
+            .map(|b| TransactionTokenBalance {
+                account_index: b.account_index,
+                mint: b.mint.clone(),
+                ui_token_amount: b.ui_token_amount.clone(),
+                owner: b.get_owner().to_string(),
+                program_id: String::new(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sorted(get_token_balance_diff(&native_pre, post)), diffs);
+
+        // or boxed in a single slice
+        let boxed_pre = native_pre
+            .into_iter()
+            .map(|b| Box::new(b) as Box<dyn TokenBalanceInfo>)
+            .collect::<Vec<_>>();
+        assert_eq!(sorted(get_token_balance_diff(&boxed_pre, post)), diffs);
+    }
+
+    #[test]
+    fn test_is_buy() {
+        // the pool takes sol in and pays tokens out
+        assert!(is_buy(Some(PoolKind::AmmV4), 0.55, -6822.422379).unwrap());
+        assert!(is_buy(Some(PoolKind::CpSwap), 1.0, -119.1).unwrap());
+        // and the other way around
+        assert!(!is_buy(Some(PoolKind::AmmV4), -0.55, 6822.422379).unwrap());
+        assert!(!is_buy(Some(PoolKind::CpSwap), -1.0, 119.1).unwrap());
+
+        // both vaults up (or one untouched) is not a swap
+        assert!(is_buy(Some(PoolKind::AmmV4), 0.55, 6822.422379).is_err());
+        assert!(is_buy(Some(PoolKind::AmmV4), -0.55, -6822.422379).is_err());
+        assert!(is_buy(Some(PoolKind::CpSwap), 0.0, -119.1).is_err());
+
+        // diffs not collected from a known pool can't be read either way
+        assert!(is_buy(None, 0.55, -6822.422379).is_err());
+    }
+
+    #[test]
+    fn test_cp_swap_diffs_collected() {
+        let fixture: serde_json::Value = serde_json::from_reader(
+            std::fs::File::open("fixtures/cp_swap_tx.json").unwrap(),
+        )
+        .unwrap();
+        let balances = |key: &str| {
+            serde_json::from_value::<Vec<UiTransactionTokenBalance>>(
+                fixture["meta"][key].clone(),
+            )
+            .unwrap()
+        };
+        let (pre, post) =
+            (balances("preTokenBalances"), balances("postTokenBalances"));
+
+        // the trader's token account is not a vault
+        let diffs = get_token_balance_diff(&pre, &post);
+        assert_eq!(diffs.len(), 2);
+        assert!(diffs.iter().all(|d| {
+            classify_pool_authority(&d.owner) == Some(PoolKind::CpSwap)
+        }));
+
+        let sol_price = FixedSolPrice(200.0);
+        let result =
+            process_diffs(&diffs, &sol_price, Pubkey::default()).unwrap();
+        assert_eq!(result.pool_kind, Some(PoolKind::CpSwap));
+        assert!(result.is_buy);
+        assert!((result.sol_amount - 1.0).abs() < 1e-9);
+
+        // not collected when only the v4 authority is known
+        let v4_only = &POOL_AUTHORITIES[..1];
+        assert!(get_token_balance_diff_for(&pre, &post, v4_only).is_empty());
+
+        // the swap price falls between the spot prices before and after
+        let pre_spot =
+            get_pool_spot_price(&pre, &result.coin_mint, &sol_price).unwrap();
+        let post_spot =
+            get_pool_spot_price(&post, &result.coin_mint, &sol_price).unwrap();
+        assert!(pre_spot < result.price && result.price < post_spot);
+        // 86.12 sol against 119.1m tokens after the buy
+        assert_eq!(
+            post_spot,
+            pool_spot_price(119_100_000_000_000, 86_120_000_000, 6, 9, 200.0)
+        );
+        assert!((post_spot - 86.12 / 119_100_000.0 * 200.0).abs() < 1e-15);
+    }
+
+    #[tokio::test]
+    async fn test_sol_for_token() {
+        let diffs = vec![
+            Diff {
+                mint: "G6ZaVuWEuGtFRooaiHQWjDzoCzr2f7BWr3PhsQRnjSTE"
+                    .to_string(),
+                pre_amount: 9502698.632123,
+                post_amount: 9493791.483438,
+                diff: -8907.148685000837,
+                owner: "8CNuwDVRshWyZtWRvgb31AMaBge4q6KSRHNPdJHP29HU"
+                    .to_string(),
+            },
+            Diff {
+                mint: "So11111111111111111111111111111111111111112".to_string(),
+                pre_amount: 145.774357667,
+                post_amount: 142.421949398,
+                diff: -3.3524082689999943,
+                owner: "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1"
+                    .to_string(),
+            },
+        ];
+
+        let DiffsResult {
+            price, swap_amount, ..
+        } = process_diffs(&diffs, &FixedSolPrice(201.36), Pubkey::default())
+            .unwrap();
+        let rounded_price = round_to_decimals(price, 4);
+        assert!(rounded_price == 0.0758, "price: {}", rounded_price);
+        assert!(
+            swap_amount == 3.3524082689999943 * 201.36,
+            "swap_amount: {}",
+            swap_amount
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sol_for_token_2() {
+        let diffs = vec![
+            Diff {
+                mint: "So11111111111111111111111111111111111111112".to_string(),
+                pre_amount: 450.295597127,
+                post_amount: 450.345597127,
+                diff: 0.05000000000001137,
+                owner: "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1"
+                    .to_string(),
+            },
+            Diff {
+                mint: "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon"
+                    .to_string(),
+                pre_amount: 61602947.9232689,
+                post_amount: 61596125.50088912,
+                diff: -6822.422379776835,
+                owner: "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1"
+                    .to_string(),
+            },
+        ];
+
+        let DiffsResult {
+            price, swap_amount, ..
+        } = process_diffs(&diffs, &FixedSolPrice(202.12), Pubkey::default())
+            .unwrap();
+        let rounded_price = round_to_decimals(price, 5);
+        assert!(rounded_price == 0.00148, "price: {}", rounded_price);
+        assert!(
+            swap_amount == 0.05000000000001137 * 202.12,
+            "swap_amount: {}",
+            swap_amount
+        );
+    }
+
+    #[test]
+    fn test_signed_amounts_and_pool() {
+        let diffs = vec![
+            Diff {
+                mint: "So11111111111111111111111111111111111111112".to_string(),
+                pre_amount: 450.295597127,
+                post_amount: 450.345597127,
+                diff: 0.05000000000001137,
+                owner: "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1"
+                    .to_string(),
+            },
+            Diff {
+                mint: "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon"
+                    .to_string(),
+                pre_amount: 61602947.9232689,
+                post_amount: 61596125.50088912,
+                diff: -6822.422379776835,
+                owner: "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1"
+                    .to_string(),
+            },
+        ];
+        let amm_pool = Pubkey::new_unique();
+
+        let buy =
+            process_diffs(&diffs, &FixedSolPrice(202.12), amm_pool).unwrap();
+        assert_eq!(buy.sol_amount, diffs[0].diff);
+        assert_eq!(buy.token_amount, diffs[1].diff);
+        assert_eq!(buy.amm_pool, amm_pool);
+        assert!(buy.is_buy);
+
+        // same swap the other way around, order of the diffs does not matter
+        let sell_diffs = diffs
+            .iter()
+            .rev()
+            .map(|d| Diff {
+                diff: -d.diff,
+                ..d.clone()
+            })
+            .collect::<Vec<_>>();
+        let sell = process_diffs(&sell_diffs, &FixedSolPrice(202.12), amm_pool)
+            .unwrap();
+        assert_eq!(sell.sol_amount, -diffs[0].diff);
+        assert_eq!(sell.token_amount, -diffs[1].diff);
+        assert!(!sell.is_buy);
+    }
+
+    #[test]
+    fn test_usd_quote_priced_without_sol_price() {
+        let diffs = vec![
+            Diff {
+                mint: "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon"
+                    .to_string(),
+                pre_amount: 1_000_000.0,
+                post_amount: 990_000.0,
+                diff: -10_000.0,
+                owner: "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1"
+                    .to_string(),
+            },
+            Diff {
+                mint: "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"
+                    .to_string(),
+                pre_amount: 5_000.0,
+                post_amount: 5_050.0,
+                diff: 50.0,
+                owner: "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1"
+                    .to_string(),
+            },
+        ];
+
+        let buy =
+            process_diffs(&diffs, &FixedSolPrice(202.12), Pubkey::default())
+                .unwrap();
+        assert_eq!(buy.coin_mint, diffs[0].mint);
+        assert_eq!(buy.price, 0.005);
+        assert_eq!(buy.swap_amount, 50.0);
+        assert!(buy.is_buy);
+    }
+
+    #[test]
+    fn test_wsol_wrap_not_a_swap() {
+        #[derive(serde::Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Meta {
+            pre_token_balances: Vec<UiTransactionTokenBalance>,
+            post_token_balances: Vec<UiTransactionTokenBalance>,
+        }
+        #[derive(serde::Deserialize)]
+        struct Fixture {
+            meta: Meta,
+        }
+
+        // sol wrapped into the pool vault alongside a token transfer in, both
+        // vaults go up
+        let fixture: Fixture = serde_json::from_reader(
+            std::fs::File::open("fixtures/wsol_wrap_tx.json").unwrap(),
+        )
+        .unwrap();
+        let diffs = get_token_balance_diff(
+            &fixture.meta.pre_token_balances,
+            &fixture.meta.post_token_balances,
+        );
+        assert_eq!(diffs.len(), 2);
+        assert!(process_diffs(
+            &diffs,
+            &FixedSolPrice(202.12),
+            Pubkey::default()
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_by_signature() {
+        let signature = "538voMuFQKp3oE6Tu598R8kJN12sum2cGMxZBxrV2Vuip1TL4qdWaXiJ8u3yRxgJy9SFX4faP2zC83oDX68D2wuW";
+        let transaction = make_rpc_client()
+            .unwrap()
+            .get_transaction_with_config(
+                &signature.parse().unwrap(),
+                solana_client::rpc_config::RpcTransactionConfig {
+                    encoding: Some(solana_transaction_status::UiTransactionEncoding::JsonParsed),
+                    max_supported_transaction_version: Some(0),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let transaction_meta = transaction.transaction.meta.unwrap();
+
+        let diffs = get_token_balance_diff(
+            transaction_meta.pre_token_balances.as_ref().unwrap(),
+            transaction_meta.post_token_balances.as_ref().unwrap(),
+        );
+        println!("diffs: {:#?}", diffs);
+        let DiffsResult {
+            price, swap_amount, ..
+        } = process_diffs(&diffs, &FixedSolPrice(203.67), Pubkey::default())
+            .unwrap();
+        let rounded_price = round_to_decimals(price, 5);
+        assert!(rounded_price == 0.00035, "price: {}", rounded_price);
+        let rounded_swap_amount = round_to_decimals(swap_amount, 4);
+        assert!(
+            rounded_swap_amount == 0.8618,
+            "swap_amount: {}",
+            rounded_swap_amount
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "placeholder, useful for debugging"]
+    async fn test_by_signature_2() {
+        let signature = "5HdnMgQTdsuYjL8fgb6RphArEyr9qECCbDy22D8DwJA1i4mdCbhNqv645AHJMHUU4JGjsjfjPyKFfnxVZkaX8PMG";
+        let transaction = make_rpc_client()
+            .unwrap()
+            .get_transaction_with_config(
+                &signature.parse().unwrap(),
+                solana_client::rpc_config::RpcTransactionConfig {
+                    encoding: Some(solana_transaction_status::UiTransactionEncoding::JsonParsed),
+                    max_supported_transaction_version: Some(0),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let transaction_meta = transaction.transaction.meta.unwrap();
+
+        let _diffs = get_token_balance_diff(
+            transaction_meta.pre_token_balances.as_ref().unwrap(),
+            transaction_meta.post_token_balances.as_ref().unwrap(),
+        );
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use tokio::{sync::Mutex, task::JoinHandle};
+use tracing::{debug, error, warn};
+
+use crate::{db::SwapSink, price::PriceUpdate};
+
+/// FinalizedSlots reports how far the chain is finalized and which of those
+/// slots actually produced a block
+#[async_trait::async_trait]
+pub trait FinalizedSlots: Send + Sync {
+    async fn finalized_slot(&self) -> Result<u64>;
+
+    /// finalized blocks in [start, end], slots missing from it were skipped
+    async fn finalized_blocks(&self, start: u64, end: u64) -> Result<Vec<u64>>;
+}
+
+#[async_trait::async_trait]
+impl FinalizedSlots for RpcClient {
+    async fn finalized_slot(&self) -> Result<u64> {
+        Ok(self
+            .get_slot_with_commitment(CommitmentConfig::finalized())
+            .await?)
+    }
+
+    async fn finalized_blocks(&self, start: u64, end: u64) -> Result<Vec<u64>> {
+        Ok(self
+            .get_blocks_with_commitment(
+                start,
+                Some(end),
+                CommitmentConfig::finalized(),
+            )
+            .await?)
+    }
+}
+
+/// FinalizedSwapSink holds swaps back until their slot is finalized, swaps
+/// from slots that got skipped are dropped instead of being inserted
+///
+/// at most `buffer_depth` slots are held, past that the oldest slot is
+/// dropped, which only happens if finality stalls or the flush is failing
+pub struct FinalizedSwapSink<S: SwapSink, F: FinalizedSlots> {
+    inner: Arc<S>,
+    slots: F,
+    buffer: Mutex<BTreeMap<u64, Vec<PriceUpdate>>>,
+    buffer_depth: usize,
+}
+
+impl<S: SwapSink + 'static, F: FinalizedSlots + 'static>
+    FinalizedSwapSink<S, F>
+{
+    pub fn new(inner: Arc<S>, slots: F, buffer_depth: usize) -> Self {
+        Self {
+            inner,
+            slots,
+            buffer: Mutex::new(BTreeMap::new()),
+            buffer_depth,
+        }
+    }
+
+    pub async fn buffered_slots(&self) -> usize {
+        self.buffer.lock().await.len()
+    }
+
+    /// flush inserts the swaps of every finalized slot that produced a block
+    /// and drops those of skipped slots, returns the number inserted
+    pub async fn flush(&self) -> Result<usize> {
+        let finalized_slot = self.slots.finalized_slot().await?;
+        let ready = {
+            let mut buffer = self.buffer.lock().await;
+            let pending = buffer.split_off(&(finalized_slot + 1));
+            std::mem::replace(&mut *buffer, pending)
+        };
+        let (first, last) = match (ready.keys().next(), ready.keys().last()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return Ok(0),
+        };
+
+        let blocks = match self.slots.finalized_blocks(first, last).await {
+            Ok(blocks) => blocks.into_iter().collect::<HashSet<_>>(),
+            Err(e) => {
+                // put them back for the next flush
+                self.restore(ready).await;
+                return Err(e);
+            }
+        };
+
+        let mut inserted = 0;
+        let mut ready = ready.into_iter();
+        while let Some((slot, mut swaps)) = ready.next() {
+            if !blocks.contains(&slot) {
+                warn!(
+                    slot,
+                    count = swaps.len(),
+                    "dropping swaps of skipped slot"
+                );
+                continue;
+            }
+            let mut failed = None;
+            for (i, swap) in swaps.iter().enumerate() {
+                if let Err(e) = self.inner.insert_swap(swap).await {
+                    failed = Some((i, e));
+                    break;
+                }
+                inserted += 1;
+            }
+            if let Some((i, e)) = failed {
+                // the ones not inserted yet go back for the next flush
+                let rest = swaps.split_off(i);
+                self.restore(std::iter::once((slot, rest)).chain(ready))
+                    .await;
+                return Err(e);
+            }
+        }
+        debug!(finalized_slot, inserted, "flushed finalized swaps");
+        Ok(inserted)
+    }
+
+    /// restore puts swaps taken for a flush back into the buffer, ahead of
+    /// any that arrived for the same slot in the meantime
+    async fn restore(
+        &self,
+        slots: impl IntoIterator<Item = (u64, Vec<PriceUpdate>)>,
+    ) {
+        let mut buffer = self.buffer.lock().await;
+        for (slot, mut swaps) in slots {
+            let arrived = buffer.entry(slot).or_default();
+            swaps.append(arrived);
+            *arrived = swaps;
+        }
+    }
+
+    pub fn spawn_flush(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let sink = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = sink.flush().await {
+                    error!(?e, "failed to flush finalized swaps");
+                }
+            }
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: SwapSink, F: FinalizedSlots> SwapSink for FinalizedSwapSink<S, F> {
+    async fn insert_swap(&self, swap: &PriceUpdate) -> Result<()> {
+        let mut buffer = self.buffer.lock().await;
+        buffer.entry(swap.slot).or_default().push(swap.clone());
+        while buffer.len() > self.buffer_depth {
+            if let Some((slot, swaps)) = buffer.pop_first() {
+                warn!(
+                    slot,
+                    count = swaps.len(),
+                    "finalized buffer full, dropping oldest slot"
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemorySwapSink;
+
+    struct StubSlots {
+        finalized_slot: u64,
+        blocks: Vec<u64>,
+    }
+
+    #[async_trait::async_trait]
+    impl FinalizedSlots for StubSlots {
+        async fn finalized_slot(&self) -> Result<u64> {
+            Ok(self.finalized_slot)
+        }
+
+        async fn finalized_blocks(
+            &self,
+            start: u64,
+            end: u64,
+        ) -> Result<Vec<u64>> {
+            Ok(self
+                .blocks
+                .iter()
+                .copied()
+                .filter(|slot| (start..=end).contains(slot))
+                .collect())
+        }
+    }
+
+    fn make_swap(slot: u64) -> PriceUpdate {
+        PriceUpdate {
+            name: "test".to_string(),
+            pubkey: "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon".to_string(),
+            price: 0.00148,
+            market_cap: 0.0,
+            timestamp: 0,
+            slot,
+            swap_amount: 10.0,
+            owner: "owner".to_string(),
+            signature: format!("https://solscan.io/tx/{}", slot),
+            multi_hop: false,
+            is_buy: true,
+            is_suspected_wash: false,
+            spot_price: None,
+            sol_amount: 0.05,
+            token_amount: -33.78,
+            amm_pool: "amm".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_skipped_slot_not_inserted() {
+        let inner = Arc::new(MemorySwapSink::default());
+        // 101 was skipped, 103 is not finalized yet
+        let sink = FinalizedSwapSink::new(
+            inner.clone(),
+            StubSlots {
+                finalized_slot: 102,
+                blocks: vec![100, 102, 103],
+            },
+            16,
+        );
+        for slot in [100, 101, 101, 102, 103] {
+            sink.insert_swap(&make_swap(slot)).await.unwrap();
+        }
+        assert!(inner.swaps().await.is_empty());
+
+        assert_eq!(sink.flush().await.unwrap(), 2);
+        let slots = inner
+            .swaps()
+            .await
+            .iter()
+            .map(|swap| swap.slot)
+            .collect::<Vec<_>>();
+        assert_eq!(slots, vec![100, 102]);
+        assert_eq!(sink.buffered_slots().await, 1);
+    }
+
+    /// FlakySink rejects the insert with the given signature once
+    struct FlakySink {
+        inner: MemorySwapSink,
+        fail_on: std::sync::Mutex<Option<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl SwapSink for FlakySink {
+        async fn insert_swap(&self, swap: &PriceUpdate) -> Result<()> {
+            let fail = {
+                let mut fail_on = self.fail_on.lock().unwrap();
+                fail_on.take_if(|signature| *signature == swap.signature)
+            };
+            if fail.is_some() {
+                return Err(anyhow::anyhow!("insert failed"));
+            }
+            self.inner.insert_swap(swap).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_insert_kept_for_next_flush() {
+        let inner = Arc::new(FlakySink {
+            inner: MemorySwapSink::default(),
+            fail_on: std::sync::Mutex::new(Some(make_swap(101).signature)),
+        });
+        let sink = FinalizedSwapSink::new(
+            inner.clone(),
+            StubSlots {
+                finalized_slot: 102,
+                blocks: vec![100, 101, 102],
+            },
+            16,
+        );
+        for slot in [100, 101, 102] {
+            sink.insert_swap(&make_swap(slot)).await.unwrap();
+        }
+
+        assert!(sink.flush().await.is_err());
+        assert_eq!(inner.inner.swaps().await.len(), 1);
+        assert_eq!(sink.buffered_slots().await, 2);
+
+        assert_eq!(sink.flush().await.unwrap(), 2);
+        let slots = inner
+            .inner
+            .swaps()
+            .await
+            .iter()
+            .map(|swap| swap.slot)
+            .collect::<Vec<_>>();
+        assert_eq!(slots, vec![100, 101, 102]);
+        assert_eq!(sink.buffered_slots().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_buffer_depth() {
+        let sink = FinalizedSwapSink::new(
+            Arc::new(MemorySwapSink::default()),
+            StubSlots {
+                finalized_slot: 0,
+                blocks: vec![],
+            },
+            2,
+        );
+        for slot in [100, 101, 102] {
+            sink.insert_swap(&make_swap(slot)).await.unwrap();
+        }
+        assert_eq!(sink.buffered_slots().await, 2);
+    }
+}
@@ -1,33 +1,67 @@
-use anyhow::Result;
-use carbon_core::pipeline::{Pipeline, ShutdownStrategy};
-use carbon_log_metrics::LogMetrics;
-use carbon_raydium_amm_v4_decoder::RaydiumAmmV4Decoder;
-use carbon_yellowstone_grpc_datasource::YellowstoneGrpcGeyserClient;
-use std::{
-    collections::{HashMap, HashSet},
-    sync::Arc,
+use anyhow::{anyhow, Result};
+use carbon_core::{
+    datasource::{Datasource, TransactionUpdate, Update, UpdateType},
+    error::CarbonResult,
+    metrics::{Metrics, MetricsCollection},
+    pipeline::Pipeline,
 };
-use tokio::sync::RwLock;
-use yellowstone_grpc_proto::geyser::{
-    CommitmentLevel, SubscribeRequestFilterAccounts,
-    SubscribeRequestFilterTransactions,
+use futures_util::{stream::BoxStream, StreamExt};
+use solana_sdk::signature::Signature;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+use yellowstone_grpc_client::GeyserGrpcClient;
+use yellowstone_grpc_proto::{
+    convert_from::{create_tx_meta, create_tx_versioned},
+    prelude::{
+        subscribe_update::UpdateOneof, CommitmentLevel, SubscribeRequest,
+        SubscribeRequestFilterTransactions, SubscribeUpdate,
+    },
+    tonic::transport::ClientTlsConfig,
 };
 
 use crate::{
     constants::RAYDIUM_AMM_V4_PROGRAM_ID, db::ClickhouseDb,
     kv_store::RedisKVStore, message_queue::RedisMessageQueue,
-    raydium_intruction_processor::RaydiumAmmV4InstructionProcessor,
-    util::must_get_env,
+    pipeline::make_raydium_instruction_pipeline,
+    sol_price_source::SolPriceSource,
 };
 
-pub fn make_raydium_geyser_instruction_pipeline(
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// make_raydium_geyser_pipeline streams raydium transactions from a
+/// yellowstone grpc endpoint, the stream is re-established whenever it ends
+pub fn make_raydium_geyser_pipeline(
+    grpc_endpoint: String,
+    x_token: Option<String>,
     kv_store: Arc<RedisKVStore>,
     message_queue: Arc<RedisMessageQueue>,
     db: Arc<ClickhouseDb>,
+    sol_price: Arc<dyn SolPriceSource>,
+    metrics: Arc<dyn Metrics>,
 ) -> Result<Pipeline> {
-    // Set up transaction filters to only process Raydium transactions
-    let mut transaction_filters = HashMap::new();
-    transaction_filters.insert(
+    make_raydium_instruction_pipeline(
+        GeyserDatasource::new(
+            YellowstoneConnector {
+                grpc_endpoint,
+                x_token,
+            },
+            raydium_subscribe_request(),
+        ),
+        kv_store,
+        message_queue,
+        db,
+        sol_price,
+        metrics,
+    )
+}
+
+/// raydium_subscribe_request subscribes to successful, non-vote transactions
+/// invoking the raydium amm v4 program
+pub fn raydium_subscribe_request() -> SubscribeRequest {
+    let mut transactions = HashMap::new();
+    transactions.insert(
         "raydium_transaction_filter".to_string(),
         SubscribeRequestFilterTransactions {
             vote: Some(false),
@@ -38,27 +72,301 @@ pub fn make_raydium_geyser_instruction_pipeline(
             signature: None,
         },
     );
+    SubscribeRequest {
+        transactions,
+        commitment: Some(CommitmentLevel::Processed as i32),
+        ..Default::default()
+    }
+}
+
+/// GeyserStream opens a subscription, a new one is opened on every reconnect
+#[async_trait::async_trait]
+pub trait GeyserStream: Send + Sync {
+    async fn subscribe(
+        &self,
+        request: SubscribeRequest,
+    ) -> Result<BoxStream<'static, Result<SubscribeUpdate>>>;
+}
+
+pub struct YellowstoneConnector {
+    pub grpc_endpoint: String,
+    pub x_token: Option<String>,
+}
+
+#[async_trait::async_trait]
+impl GeyserStream for YellowstoneConnector {
+    async fn subscribe(
+        &self,
+        request: SubscribeRequest,
+    ) -> Result<BoxStream<'static, Result<SubscribeUpdate>>> {
+        let mut client =
+            GeyserGrpcClient::build_from_shared(self.grpc_endpoint.clone())?
+                .x_token(self.x_token.clone())?
+                .tls_config(ClientTlsConfig::new().with_native_roots())?
+                .connect()
+                .await?;
+
+        // the stream borrows the client, so it is driven from its own task
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let stream = match client.subscribe_once(request).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    let _ = sender.send(Err(anyhow!(e)));
+                    return;
+                }
+            };
+            futures_util::pin_mut!(stream);
+            while let Some(update) = stream.next().await {
+                if sender.send(update.map_err(|e| anyhow!(e))).is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(
+            futures_util::stream::unfold(receiver, |mut receiver| async move {
+                receiver.recv().await.map(|update| (update, receiver))
+            })
+            .boxed(),
+        )
+    }
+}
+
+pub struct GeyserDatasource<S: GeyserStream> {
+    stream: S,
+    request: SubscribeRequest,
+    reconnect_delay: Duration,
+}
+
+impl<S: GeyserStream> GeyserDatasource<S> {
+    pub fn new(stream: S, request: SubscribeRequest) -> Self {
+        Self {
+            stream,
+            request,
+            reconnect_delay: RECONNECT_DELAY,
+        }
+    }
 
-    // Create empty account filters since we only care about transactions
-    let account_filters: HashMap<String, SubscribeRequestFilterAccounts> =
-        HashMap::new();
-
-    let pipeline = Pipeline::builder()
-        .datasource(YellowstoneGrpcGeyserClient::new(
-            must_get_env("GEYSER_URL"),
-            Some(must_get_env("GEYSER_X_TOKEN")),
-            Some(CommitmentLevel::Processed),
-            account_filters,
-            transaction_filters,
-            Arc::new(RwLock::new(HashSet::new())),
-        ))
-        .metrics(Arc::new(LogMetrics::new()))
-        .shutdown_strategy(ShutdownStrategy::Immediate)
-        .instruction(
-            RaydiumAmmV4Decoder,
-            RaydiumAmmV4InstructionProcessor::new(kv_store, message_queue, db),
+    pub fn with_reconnect_delay(mut self, reconnect_delay: Duration) -> Self {
+        self.reconnect_delay = reconnect_delay;
+        self
+    }
+
+    /// run forwards transactions until cancelled, resubscribing whenever the
+    /// stream fails or ends
+    pub async fn run(
+        &self,
+        sender: &UnboundedSender<Update>,
+        cancellation_token: CancellationToken,
+    ) {
+        loop {
+            tokio::select! {
+                _ = cancellation_token.cancelled() => {
+                    info!("geyser subscription cancelled");
+                    return;
+                }
+                res = self.forward(sender) => {
+                    match res {
+                        Ok(_) => warn!("geyser stream ended, reconnecting"),
+                        Err(e) => error!(?e, "geyser stream failed"),
+                    }
+                    tokio::time::sleep(self.reconnect_delay).await;
+                }
+            }
+        }
+    }
+
+    async fn forward(&self, sender: &UnboundedSender<Update>) -> Result<()> {
+        let mut stream = self.stream.subscribe(self.request.clone()).await?;
+        info!("subscribed to geyser transactions");
+        while let Some(update) = stream.next().await {
+            if let Some(update) = transaction_update_from_geyser(update?) {
+                sender.send(update)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: GeyserStream> Datasource for GeyserDatasource<S> {
+    async fn consume(
+        &self,
+        sender: &UnboundedSender<Update>,
+        cancellation_token: CancellationToken,
+        _metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()> {
+        self.run(sender, cancellation_token).await;
+        Ok(())
+    }
+
+    fn update_types(&self) -> Vec<UpdateType> {
+        vec![UpdateType::Transaction]
+    }
+}
+
+/// transaction_update_from_geyser converts a geyser transaction update into
+/// the pipeline's, anything else (pings, slots) or a malformed transaction
+/// yields None
+pub fn transaction_update_from_geyser(
+    update: SubscribeUpdate,
+) -> Option<Update> {
+    let Some(UpdateOneof::Transaction(update)) = update.update_oneof else {
+        return None;
+    };
+    let info = update.transaction?;
+    let signature = match Signature::try_from(info.signature.as_slice()) {
+        Ok(signature) => signature,
+        Err(e) => {
+            warn!(?e, "invalid signature in geyser update");
+            return None;
+        }
+    };
+    let transaction = match create_tx_versioned(info.transaction?) {
+        Ok(transaction) => transaction,
+        Err(e) => {
+            warn!(%e, %signature, "failed to convert geyser transaction");
+            return None;
+        }
+    };
+    let meta = match create_tx_meta(info.meta?) {
+        Ok(meta) => meta,
+        Err(e) => {
+            warn!(%e, %signature, "failed to convert geyser meta");
+            return None;
+        }
+    };
+    Some(Update::Transaction(TransactionUpdate {
+        signature,
+        transaction,
+        meta,
+        is_vote: info.is_vote,
+        slot: update.slot,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use tokio::sync::Mutex;
+    use yellowstone_grpc_proto::prelude::{
+        CompiledInstruction, Message, MessageHeader,
+        SubscribeUpdateTransaction, SubscribeUpdateTransactionInfo,
+        Transaction, TransactionStatusMeta,
+    };
+
+    /// MockGeyserStream hands out one scripted session per subscribe
+    struct MockGeyserStream {
+        sessions: Mutex<VecDeque<Vec<Result<SubscribeUpdate>>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl GeyserStream for MockGeyserStream {
+        async fn subscribe(
+            &self,
+            _request: SubscribeRequest,
+        ) -> Result<BoxStream<'static, Result<SubscribeUpdate>>> {
+            match self.sessions.lock().await.pop_front() {
+                Some(session) => {
+                    Ok(futures_util::stream::iter(session).boxed())
+                }
+                None => Ok(futures_util::stream::pending().boxed()),
+            }
+        }
+    }
+
+    fn make_raydium_update(slot: u64) -> SubscribeUpdate {
+        let payer = solana_sdk::pubkey::Pubkey::new_unique();
+        SubscribeUpdate {
+            filters: vec!["raydium_transaction_filter".to_string()],
+            update_oneof: Some(UpdateOneof::Transaction(
+                SubscribeUpdateTransaction {
+                    transaction: Some(SubscribeUpdateTransactionInfo {
+                        signature: vec![7; 64],
+                        is_vote: false,
+                        transaction: Some(Transaction {
+                            signatures: vec![vec![7; 64]],
+                            message: Some(Message {
+                                header: Some(MessageHeader {
+                                    num_required_signatures: 1,
+                                    num_readonly_signed_accounts: 0,
+                                    num_readonly_unsigned_accounts: 1,
+                                }),
+                                account_keys: vec![
+                                    payer.to_bytes().to_vec(),
+                                    RAYDIUM_AMM_V4_PROGRAM_ID
+                                        .to_bytes()
+                                        .to_vec(),
+                                ],
+                                recent_blockhash: vec![0; 32],
+                                instructions: vec![CompiledInstruction {
+                                    program_id_index: 1,
+                                    accounts: vec![0],
+                                    // swap_base_in
+                                    data: vec![9],
+                                }],
+                                versioned: false,
+                                address_table_lookups: vec![],
+                            }),
+                        }),
+                        meta: Some(TransactionStatusMeta::default()),
+                        index: 0,
+                    }),
+                    slot,
+                },
+            )),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_geyser_datasource_reconnects() {
+        // the first session drops with an error, the second delivers
+        let datasource = GeyserDatasource::new(
+            MockGeyserStream {
+                sessions: Mutex::new(VecDeque::from(vec![
+                    vec![Err(anyhow!("stream reset"))],
+                    vec![
+                        Ok(SubscribeUpdate::default()),
+                        Ok(make_raydium_update(312_000_000)),
+                    ],
+                ])),
+            },
+            raydium_subscribe_request(),
         )
-        .build()?;
+        .with_reconnect_delay(Duration::ZERO);
+
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let cancellation_token = CancellationToken::new();
+        let run = datasource.run(&sender, cancellation_token.clone());
+        let recv = async {
+            let update = receiver.recv().await;
+            cancellation_token.cancel();
+            update
+        };
+        let (_, update) = tokio::join!(run, recv);
+
+        match update {
+            Some(Update::Transaction(update)) => {
+                assert_eq!(update.slot, 312_000_000);
+                assert_eq!(update.signature, Signature::from([7; 64]));
+                let keys = update.transaction.message.static_account_keys();
+                assert_eq!(keys[1], RAYDIUM_AMM_V4_PROGRAM_ID);
+            }
+            _ => panic!("expected a transaction update"),
+        }
+    }
 
-    Ok(pipeline)
+    #[test]
+    fn test_raydium_subscribe_request() {
+        let request = raydium_subscribe_request();
+        let filter = &request.transactions["raydium_transaction_filter"];
+        assert_eq!(
+            filter.account_required,
+            vec![RAYDIUM_AMM_V4_PROGRAM_ID.to_string()]
+        );
+        assert_eq!(filter.vote, Some(false));
+        assert_eq!(filter.failed, Some(false));
+    }
 }
@@ -0,0 +1,309 @@
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use http_body_util::Full;
+use hyper::{
+    body::{Bytes, Incoming},
+    server::conn::http1,
+    service::service_fn,
+    Request, Response, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use serde::Serialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use tokio::net::TcpListener;
+use tracing::{debug, error, info, warn};
+
+/// the instructions per second are measured over at least this long, scrapes
+/// closer together report the previous rate
+const MIN_RATE_WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+struct RateSample {
+    at: Instant,
+    processed: u64,
+    per_sec: f64,
+}
+
+/// PipelineHealth is the progress of the pipeline, the processor records
+/// every instruction it handles and the health endpoint reads it
+#[derive(Debug)]
+pub struct PipelineHealth {
+    last_slot: AtomicU64,
+    processed: AtomicU64,
+    rate: Mutex<RateSample>,
+}
+
+impl Default for PipelineHealth {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PipelineHealth {
+    pub fn new() -> Self {
+        Self {
+            last_slot: AtomicU64::new(0),
+            processed: AtomicU64::new(0),
+            rate: Mutex::new(RateSample {
+                at: Instant::now(),
+                processed: 0,
+                per_sec: 0.0,
+            }),
+        }
+    }
+
+    /// record counts an instruction of the given slot, datasources can
+    /// deliver slightly out of order so the last slot only moves forward
+    pub fn record(&self, slot: u64) {
+        self.last_slot.fetch_max(slot, Ordering::Relaxed);
+        self.processed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn last_slot(&self) -> u64 {
+        self.last_slot.load(Ordering::Relaxed)
+    }
+
+    /// instructions_per_sec is the rate since the previous measurement
+    pub fn instructions_per_sec(&self) -> f64 {
+        let mut sample = self.rate.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(sample.at);
+        if elapsed >= MIN_RATE_WINDOW {
+            let processed = self.processed.load(Ordering::Relaxed);
+            sample.per_sec =
+                (processed - sample.processed) as f64 / elapsed.as_secs_f64();
+            sample.at = now;
+            sample.processed = processed;
+        }
+        sample.per_sec
+    }
+}
+
+/// TipSlot is where the chain tip is read from, the lag is measured against
+/// it
+#[async_trait::async_trait]
+pub trait TipSlot: Send + Sync {
+    async fn tip_slot(&self) -> Result<u64>;
+}
+
+#[async_trait::async_trait]
+impl TipSlot for RpcClient {
+    async fn tip_slot(&self) -> Result<u64> {
+        Ok(self
+            .get_slot_with_commitment(CommitmentConfig::processed())
+            .await?)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    pub last_processed_slot: u64,
+    /// None if the tip could not be read
+    pub tip_slot: Option<u64>,
+    pub lag_slots: Option<u64>,
+    pub instructions_per_sec: f64,
+    pub healthy: bool,
+}
+
+/// HealthServer serves the `/health` endpoint, it responds with a
+/// HealthReport and 503 when the pipeline lags more than `max_lag_slots`
+/// behind the tip (or the tip can't be read)
+pub struct HealthServer {
+    health: Arc<PipelineHealth>,
+    tip: Arc<dyn TipSlot>,
+    max_lag_slots: u64,
+    addr: SocketAddr,
+}
+
+impl HealthServer {
+    pub fn new(
+        port: u16,
+        health: Arc<PipelineHealth>,
+        tip: Arc<dyn TipSlot>,
+        max_lag_slots: u64,
+    ) -> Self {
+        Self {
+            health,
+            tip,
+            max_lag_slots,
+            addr: SocketAddr::from(([0, 0, 0, 0], port)),
+        }
+    }
+
+    /// start binds the port and serves in the background, returns the bound
+    /// address (the port is picked by the os if 0)
+    pub async fn start(self) -> Result<SocketAddr> {
+        let listener = TcpListener::bind(self.addr).await?;
+        let addr = listener.local_addr()?;
+        info!("Health server running on {}", addr);
+        tokio::spawn(serve_health(listener, Arc::new(self)));
+        Ok(addr)
+    }
+
+    pub async fn report(&self) -> HealthReport {
+        let last_processed_slot = self.health.last_slot();
+        let tip_slot = match self.tip.tip_slot().await {
+            Ok(tip_slot) => Some(tip_slot),
+            Err(e) => {
+                warn!(?e, "failed to get tip slot");
+                None
+            }
+        };
+        let lag_slots =
+            tip_slot.map(|tip| tip.saturating_sub(last_processed_slot));
+        HealthReport {
+            last_processed_slot,
+            tip_slot,
+            lag_slots,
+            instructions_per_sec: self.health.instructions_per_sec(),
+            healthy: lag_slots.is_some_and(|lag| lag <= self.max_lag_slots),
+        }
+    }
+}
+
+async fn serve_health(listener: TcpListener, server: Arc<HealthServer>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                error!(?e, "health server accept failed");
+                continue;
+            }
+        };
+        let server = server.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req: Request<Incoming>| {
+                let server = server.clone();
+                async move {
+                    Ok::<_, Infallible>(handle_request(&req, &server).await)
+                }
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!(?e, "health connection closed");
+            }
+        });
+    }
+}
+
+async fn handle_request(
+    req: &Request<Incoming>,
+    server: &HealthServer,
+) -> Response<Full<Bytes>> {
+    if req.uri().path() != "/health" {
+        let mut res = Response::new(Full::new(Bytes::new()));
+        *res.status_mut() = StatusCode::NOT_FOUND;
+        return res;
+    }
+
+    let report = server.report().await;
+    let body = match serde_json::to_vec(&report) {
+        Ok(body) => body,
+        Err(e) => {
+            error!(?e, "failed to encode health report");
+            let mut res = Response::new(Full::new(Bytes::new()));
+            *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            return res;
+        }
+    };
+    let mut res = Response::new(Full::new(Bytes::from(body)));
+    if !report.healthy {
+        *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+    }
+    res.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static("application/json"),
+    );
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedTip(AtomicU64);
+
+    #[async_trait::async_trait]
+    impl TipSlot for FixedTip {
+        async fn tip_slot(&self) -> Result<u64> {
+            Ok(self.0.load(Ordering::Relaxed))
+        }
+    }
+
+    #[cfg(feature = "storage")]
+    #[tokio::test]
+    async fn test_health_endpoint() {
+        use crate::{
+            db::MemorySwapSink,
+            decimals_cache::NoMintFetcher,
+            kv_store::MemoryKVStore,
+            message_queue::MemoryMessageQueue,
+            process_swap::load_transaction_metadata,
+            raydium_intruction_processor::{
+                swap_base_in_input, RaydiumAmmV4InstructionProcessor,
+            },
+            sol_price_source::FixedSolPrice,
+        };
+        use carbon_core::{metrics::MetricsCollection, processor::Processor};
+
+        let tx_meta =
+            load_transaction_metadata("fixtures/ephemeral_wsol_tx.json");
+        let slot = tx_meta.slot;
+
+        let health = Arc::new(PipelineHealth::new());
+        let tip = Arc::new(FixedTip(AtomicU64::new(slot + 10)));
+        let addr = HealthServer::new(0, health.clone(), tip.clone(), 150)
+            .start()
+            .await
+            .unwrap();
+        let url = format!("http://127.0.0.1:{}/health", addr.port());
+
+        let mut processor = RaydiumAmmV4InstructionProcessor::new(
+            Arc::new(MemoryKVStore::default()),
+            Arc::new(MemoryMessageQueue::default()),
+            Arc::new(MemorySwapSink::default()),
+            Arc::new(NoMintFetcher),
+            Arc::new(FixedSolPrice(200.0)),
+        )
+        .with_health(health.clone());
+        let mut late = tx_meta.clone();
+        late.slot -= 5;
+        // a late instruction of an older slot doesn't move it back
+        for tx_meta in [tx_meta, late] {
+            processor
+                .process(
+                    swap_base_in_input(tx_meta),
+                    Arc::new(MetricsCollection::new(vec![])),
+                )
+                .await
+                .unwrap();
+        }
+
+        let res = reqwest::get(&url).await.unwrap();
+        assert_eq!(res.status(), reqwest::StatusCode::OK);
+        let report: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(report["last_processed_slot"], 312_000_000);
+        assert_eq!(report["tip_slot"], 312_000_010);
+        assert_eq!(report["lag_slots"], 10);
+        assert_eq!(report["healthy"], true);
+
+        // the tip moved on, the pipeline didn't
+        tip.0.store(slot + 1000, Ordering::Relaxed);
+        let res = reqwest::get(&url).await.unwrap();
+        assert_eq!(res.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        let report: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(report["lag_slots"], 1000);
+    }
+}
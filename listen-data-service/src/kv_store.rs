@@ -1,6 +1,7 @@
 use anyhow::{Context, Result};
 use bb8_redis::{bb8, redis::cmd, RedisConnectionManager};
 use serde::{de::DeserializeOwned, Serialize};
+use solana_sdk::pubkey::Pubkey;
 use tracing::{debug, info};
 
 use crate::metadata::TokenMetadata;
@@ -24,6 +25,16 @@ pub trait KVStore {
     async fn get_metadata(&self, mint: &str) -> Result<Option<TokenMetadata>>;
 }
 
+/// SeenStore backs the ingestion deduplication, mark_seen is a check-and-set
+/// that returns true only for the first caller within the ttl; unmark_seen
+/// releases the key of a swap that failed to be recorded so that a redelivery
+/// gets through
+#[async_trait::async_trait]
+pub trait SeenStore: Send + Sync {
+    async fn mark_seen(&self, key: &str, ttl_secs: u64) -> Result<bool>;
+    async fn unmark_seen(&self, key: &str) -> Result<()>;
+}
+
 pub struct RedisKVStore {
     pool: bb8::Pool<RedisConnectionManager>,
 }
@@ -125,13 +136,105 @@ impl KVStore for RedisKVStore {
     }
 }
 
+#[async_trait::async_trait]
+impl SeenStore for RedisKVStore {
+    async fn mark_seen(&self, key: &str, ttl_secs: u64) -> Result<bool> {
+        let mut conn = self
+            .pool
+            .get()
+            .await
+            .context("Failed to get connection from pool")?;
+        let res: Option<String> = cmd("SET")
+            .arg(key)
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl_secs)
+            .query_async(&mut *conn)
+            .await
+            .context("Failed to set seen key")?;
+        let first_seen = res.is_some();
+        debug!(key, first_seen, "redis mark seen ok");
+        Ok(first_seen)
+    }
+
+    async fn unmark_seen(&self, key: &str) -> Result<()> {
+        let mut conn = self
+            .pool
+            .get()
+            .await
+            .context("Failed to get connection from pool")?;
+        let _: () = cmd("DEL")
+            .arg(key)
+            .query_async(&mut *conn)
+            .await
+            .context("Failed to delete seen key")?;
+        debug!(key, "redis unmark seen ok");
+        Ok(())
+    }
+}
+
+/// in-memory kv store for testing, ttls are not enforced and metadata is
+/// only what was put in, nothing is fetched
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryKVStore {
+    pub seen: tokio::sync::Mutex<std::collections::HashSet<String>>,
+    pub decimals: tokio::sync::Mutex<std::collections::HashMap<Pubkey, u8>>,
+    pub metadata:
+        tokio::sync::Mutex<std::collections::HashMap<String, TokenMetadata>>,
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl SeenStore for MemoryKVStore {
+    async fn mark_seen(&self, key: &str, _ttl_secs: u64) -> Result<bool> {
+        Ok(self.seen.lock().await.insert(key.to_string()))
+    }
+
+    async fn unmark_seen(&self, key: &str) -> Result<()> {
+        self.seen.lock().await.remove(key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl crate::decimals_cache::DecimalsStore for MemoryKVStore {
+    async fn get_decimals(&self, mint: &Pubkey) -> Result<Option<u8>> {
+        Ok(self.decimals.lock().await.get(mint).copied())
+    }
+
+    async fn set_decimals(&self, mint: &Pubkey, decimals: u8) -> Result<()> {
+        self.decimals.lock().await.insert(*mint, decimals);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl crate::metadata::TokenMetadataSource for MemoryKVStore {
+    async fn token_metadata(
+        &self,
+        mint: &str,
+    ) -> Result<Option<TokenMetadata>> {
+        Ok(self.metadata.lock().await.get(mint).cloned())
+    }
+}
+
 impl RedisKVStore {
+    pub fn make_seen_key(signature: &str, mint: &str) -> String {
+        format!("seen:{}:{}", signature, mint)
+    }
     pub fn make_price_key(price: &Price) -> String {
         format!("solana:{}:{}", price.coin_mint, price.pc_mint)
     }
     pub fn make_metadata_key(mint: &str) -> String {
         format!("solana:{}", mint)
     }
+    pub fn make_decimals_key(mint: &Pubkey) -> String {
+        format!("decimals:{}", mint)
+    }
 
     pub async fn insert_price(&self, price: &Price) -> Result<()> {
         let key = Self::make_price_key(price);
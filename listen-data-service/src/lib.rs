@@ -6,7 +6,9 @@ fn init() {
     let _ = tracing_subscriber::fmt::try_init();
 }
 
+pub mod checklist;
 pub mod constants;
+pub mod csv_export;
 pub mod de;
 pub mod diffs;
 
@@ -16,17 +18,36 @@ pub mod rpc;
 #[cfg(feature = "geyser")]
 pub mod geyser;
 
+#[cfg(feature = "storage")]
 pub mod db;
+#[cfg(feature = "storage")]
+pub mod decimals_cache;
+#[cfg(feature = "storage")]
+pub mod finalized_sink;
+#[cfg(feature = "storage")]
 pub mod kv_store;
+#[cfg(feature = "storage")]
 pub mod message_queue;
+#[cfg(feature = "storage")]
 pub mod metadata;
-pub mod metrics;
-pub mod price;
+#[cfg(feature = "storage")]
+pub mod pipeline;
+#[cfg(feature = "storage")]
 pub mod process_swap;
+#[cfg(feature = "storage")]
 pub mod raydium_intruction_processor;
+
+pub mod health;
+pub mod metrics;
+pub mod price;
+pub mod prometheus_metrics;
+pub mod pyth;
+pub mod raydium_instruction;
 pub mod raydium_processor;
+pub mod sol_price_source;
 pub mod sol_price_stream;
 pub mod util;
+pub mod wash_trade;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "storage"))]
 pub mod debug;
@@ -2,25 +2,33 @@ use anyhow::Result;
 use carbon_core::pipeline::Pipeline;
 use clap::Parser;
 use listen_data_service::{
-    sol_price_stream::SOL_PRICE_CACHE,
-    util::{make_db, make_kv_store, make_message_queue},
+    sol_price_source::{BinanceRestOracle, CachedSolPrice, SolPriceSource},
+    util::{
+        make_db, make_kv_store, make_message_queue, make_metrics, must_get_env,
+    },
 };
 
 #[cfg(feature = "geyser")]
-use listen_data_service::geyser::make_raydium_geyser_instruction_pipeline;
+use listen_data_service::geyser::make_raydium_geyser_pipeline;
 
 #[cfg(feature = "rpc")]
 use listen_data_service::rpc::{
     account_pipeline::make_raydium_rpc_accounts_pipeline,
     instruction_pipeline::make_raydium_rpc_instruction_pipeline,
+    ws_pipeline::make_raydium_ws_pipeline,
 };
+use std::time::Duration;
 use tracing::{error, info};
 
+const SOL_PRICE_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+const SOL_PRICE_MAX_AGE: Duration = Duration::from_secs(60);
+
 #[cfg(feature = "rpc")]
 #[derive(Parser)]
 pub enum Command {
     RaydiumAccountsRpc,
     RaydiumInstructionsRpc,
+    RaydiumInstructionsWs,
 }
 
 #[cfg(feature = "geyser")]
@@ -47,12 +55,19 @@ async fn main() -> Result<()> {
     }
 
     // this is important for cold starts, once routines try all at once it can 429
-    info!("Solana price: {}", SOL_PRICE_CACHE.get_price().await);
+    let sol_price = CachedSolPrice::start(
+        BinanceRestOracle,
+        SOL_PRICE_REFRESH_INTERVAL,
+        SOL_PRICE_MAX_AGE,
+    )
+    .await?;
+    info!("Solana price: {}", sol_price.sol_price());
 
     let db = make_db().await?;
 
     let kv_store = make_kv_store()?;
     let message_queue = make_message_queue()?;
+    let metrics = make_metrics()?;
 
     #[cfg(any(feature = "rpc", feature = "geyser"))]
     {
@@ -69,6 +84,18 @@ async fn main() -> Result<()> {
                     kv_store,
                     message_queue,
                     db,
+                    sol_price,
+                    metrics,
+                )?;
+            }
+            Command::RaydiumInstructionsWs => {
+                pipeline = make_raydium_ws_pipeline(
+                    must_get_env("WS_URL"),
+                    kv_store,
+                    message_queue,
+                    db,
+                    sol_price,
+                    metrics,
                 )?;
             }
         }
@@ -76,22 +103,18 @@ async fn main() -> Result<()> {
         #[cfg(feature = "geyser")]
         match command {
             Command::RaydiumInstructionsGeyser => {
-                pipeline = make_raydium_geyser_instruction_pipeline(
+                pipeline = make_raydium_geyser_pipeline(
+                    must_get_env("GEYSER_URL"),
+                    std::env::var("GEYSER_X_TOKEN").ok(),
                     kv_store,
                     message_queue,
                     db,
+                    sol_price,
+                    metrics,
                 )?;
             }
         }
 
-        let price_cache = SOL_PRICE_CACHE.clone();
-
-        tokio::spawn(async move {
-            if let Err(e) = price_cache.start_price_stream().await {
-                error!("Error in SOL price stream: {}", e);
-            }
-        });
-
         pipeline.run().await?;
     }
 
@@ -1,5 +1,67 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use tracing::{debug, warn};
+
 use crate::price::PriceUpdate;
 
+/// MESSAGE_SCHEMA_VERSION is bumped on any breaking change to a published
+/// payload, consumers refuse versions they don't know
+pub const MESSAGE_SCHEMA_VERSION: u32 = 1;
+pub const RAYDIUM_SWAP_MESSAGE: &str = "raydium_swap";
+pub const DEAD_LETTER_MESSAGE: &str = "dead_letter";
+
+/// redis list holding the dead letters until they are drained
+pub const DEAD_LETTERS_KEY: &str = "dead_letters";
+
+/// letters kept by default, past that the oldest ones are trimmed
+pub const DEFAULT_MAX_DEAD_LETTERS: usize = 10_000;
+
+/// how long a letter blocks more letters of the same signature and pool if
+/// it isn't drained, e.g. every swap instruction of a tx failing the same way
+const DEAD_LETTER_DEDUP_TTL_SECS: u64 = 3600;
+
+/// Envelope wraps every message published to the queue
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub v: u32,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub payload: T,
+}
+
+pub fn encode_message<T: Serialize>(
+    kind: &str,
+    payload: &T,
+) -> serde_json::Result<String> {
+    serde_json::to_string(&Envelope {
+        v: MESSAGE_SCHEMA_VERSION,
+        kind: kind.to_string(),
+        payload,
+    })
+}
+
+/// decode_message unwraps a message of the expected kind, the payload is
+/// only parsed once the version and kind are known to match
+pub fn decode_message<T: DeserializeOwned>(kind: &str, raw: &str) -> Result<T> {
+    let envelope: Envelope<serde_json::Value> = serde_json::from_str(raw)?;
+    if envelope.v != MESSAGE_SCHEMA_VERSION {
+        return Err(anyhow!(
+            "Unsupported message schema version {} (expected {})",
+            envelope.v,
+            MESSAGE_SCHEMA_VERSION
+        ));
+    }
+    if envelope.kind != kind {
+        return Err(anyhow!(
+            "Unexpected message type {} (expected {})",
+            envelope.kind,
+            kind
+        ));
+    }
+    Ok(serde_json::from_value(envelope.payload)?)
+}
+
 #[async_trait::async_trait]
 pub trait MessageQueue: Send + Sync + 'static {
     type Error: std::error::Error + Send + Sync + 'static;
@@ -13,12 +75,23 @@ pub trait MessageQueue: Send + Sync + 'static {
 // Redis implementation of MessageQueue
 pub struct RedisMessageQueue {
     client: redis::Client,
+    max_dead_letters: usize,
 }
 
 impl RedisMessageQueue {
     pub fn new(redis_url: &str) -> Result<Self, redis::RedisError> {
         let client = redis::Client::open(redis_url)?;
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            max_dead_letters: DEFAULT_MAX_DEAD_LETTERS,
+        })
+    }
+
+    /// with_max_dead_letters caps the dead letter list, the oldest letters
+    /// are trimmed first
+    pub fn with_max_dead_letters(mut self, max_dead_letters: usize) -> Self {
+        self.max_dead_letters = max_dead_letters;
+        self
     }
 }
 
@@ -31,13 +104,14 @@ impl MessageQueue for RedisMessageQueue {
         price_update: PriceUpdate,
     ) -> Result<(), Self::Error> {
         let mut conn = self.client.get_multiplexed_async_connection().await?;
-        let payload = serde_json::to_string(&price_update).map_err(|e| {
-            redis::RedisError::from((
-                redis::ErrorKind::IoError,
-                "Serialization error",
-                e.to_string(),
-            ))
-        })?;
+        let payload = encode_message(RAYDIUM_SWAP_MESSAGE, &price_update)
+            .map_err(|e| {
+                redis::RedisError::from((
+                    redis::ErrorKind::IoError,
+                    "Serialization error",
+                    e.to_string(),
+                ))
+            })?;
 
         redis::cmd("PUBLISH")
             .arg("price_updates")
@@ -46,3 +120,313 @@ impl MessageQueue for RedisMessageQueue {
             .await
     }
 }
+
+/// DeadLetter is an instruction that failed processing, kept with enough
+/// to reprocess it
+///
+/// a replayed letter goes through the seen-key deduplication like any swap:
+/// a swap that failed before or at its insert has no seen key (it is
+/// released on a failed insert) and is recorded on replay, one that was
+/// recorded and failed after (publishing) is skipped as a duplicate rather
+/// than stored twice
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub signature: String,
+    /// the amm of the swap, empty if the instruction didn't have one
+    #[serde(default)]
+    pub pool: String,
+    pub error: String,
+    pub instruction_data: Vec<u8>,
+}
+
+impl DeadLetter {
+    /// key is what letters are deduplicated by, the processing is per
+    /// transaction and pool so more swap instructions of the same pool in a
+    /// tx fail the same way
+    pub fn key(&self) -> String {
+        format!("dead_letter:{}:{}", self.signature, self.pool)
+    }
+}
+
+/// DeadLetterQueue holds the instructions the processor failed on, instead
+/// of dropping them (or stopping the pipeline); a letter with the key of
+/// one still queued is dropped
+#[async_trait::async_trait]
+pub trait DeadLetterQueue: Send + Sync {
+    async fn push_dead_letter(&self, letter: &DeadLetter) -> Result<()>;
+    /// drain_dead_letters takes all the dead letters, oldest first
+    async fn drain_dead_letters(&self) -> Result<Vec<DeadLetter>>;
+}
+
+#[async_trait::async_trait]
+impl DeadLetterQueue for RedisMessageQueue {
+    async fn push_dead_letter(&self, letter: &DeadLetter) -> Result<()> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let first: Option<String> = redis::cmd("SET")
+            .arg(letter.key())
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(DEAD_LETTER_DEDUP_TTL_SECS)
+            .query_async(&mut conn)
+            .await
+            .context("failed to set dead letter key")?;
+        if first.is_none() {
+            debug!(key = letter.key(), "dead letter already queued");
+            return Ok(());
+        }
+        let _: () = redis::pipe()
+            .atomic()
+            .rpush(
+                DEAD_LETTERS_KEY,
+                encode_message(DEAD_LETTER_MESSAGE, letter)?,
+            )
+            .ignore()
+            .ltrim(DEAD_LETTERS_KEY, -(self.max_dead_letters as isize), -1)
+            .ignore()
+            .query_async(&mut conn)
+            .await
+            .context("failed to push dead letter")?;
+        Ok(())
+    }
+
+    async fn drain_dead_letters(&self) -> Result<Vec<DeadLetter>> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        // read and delete atomically, a letter pushed in between would be
+        // lost otherwise
+        let (raw,): (Vec<String>,) = redis::pipe()
+            .atomic()
+            .lrange(DEAD_LETTERS_KEY, 0, -1)
+            .del(DEAD_LETTERS_KEY)
+            .ignore()
+            .query_async(&mut conn)
+            .await
+            .context("failed to drain dead letters")?;
+        let letters = decode_dead_letters(&raw);
+        // a replay failing again gets a letter again
+        if !letters.is_empty() {
+            let _: () = redis::cmd("DEL")
+                .arg(letters.iter().map(DeadLetter::key).collect::<Vec<_>>())
+                .query_async(&mut conn)
+                .await
+                .context("failed to delete dead letter keys")?;
+        }
+        Ok(letters)
+    }
+}
+
+/// decode_dead_letters decodes the drained letters one by one, a letter that
+/// can't be decoded (e.g. written by a newer version) is logged along with
+/// its raw form and skipped, the list is already gone so the rest is kept
+pub fn decode_dead_letters(raw: &[String]) -> Vec<DeadLetter> {
+    raw.iter()
+        .filter_map(|raw| {
+            decode_message(DEAD_LETTER_MESSAGE, raw)
+                .map_err(
+                    |e| warn!(?e, %raw, "dropping undecodable dead letter"),
+                )
+                .ok()
+        })
+        .collect()
+}
+
+/// dead_letter_on_error pushes the instruction to the dead letter queue if
+/// processing it failed, the error is not propagated so that processing
+/// goes on with the next one
+pub async fn dead_letter_on_error<D: DeadLetterQueue + ?Sized>(
+    dead_letters: &D,
+    signature: &str,
+    pool: &str,
+    instruction_data: &[u8],
+    result: Result<()>,
+) -> Result<()> {
+    let Err(e) = result else {
+        return Ok(());
+    };
+    warn!(?e, signature, pool, "dead lettering instruction");
+    dead_letters
+        .push_dead_letter(&DeadLetter {
+            signature: signature.to_string(),
+            pool: pool.to_string(),
+            error: format!("{:#}", e),
+            instruction_data: instruction_data.to_vec(),
+        })
+        .await
+}
+
+/// in-memory queue for testing, keeps what was published and the dead
+/// letters, encoded, deduplicated and capped like in redis
+#[cfg(test)]
+pub struct MemoryMessageQueue {
+    published: tokio::sync::Mutex<Vec<PriceUpdate>>,
+    letters: tokio::sync::Mutex<Vec<String>>,
+    max_dead_letters: usize,
+}
+
+#[cfg(test)]
+impl Default for MemoryMessageQueue {
+    fn default() -> Self {
+        Self {
+            published: Default::default(),
+            letters: Default::default(),
+            max_dead_letters: DEFAULT_MAX_DEAD_LETTERS,
+        }
+    }
+}
+
+#[cfg(test)]
+impl MemoryMessageQueue {
+    pub fn with_max_dead_letters(mut self, max_dead_letters: usize) -> Self {
+        self.max_dead_letters = max_dead_letters;
+        self
+    }
+
+    pub async fn published(&self) -> Vec<PriceUpdate> {
+        self.published.lock().await.clone()
+    }
+
+    /// push_raw_dead_letter queues a letter as is, e.g. one that doesn't
+    /// decode
+    pub async fn push_raw_dead_letter(&self, raw: &str) {
+        self.letters.lock().await.push(raw.to_string());
+    }
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl MessageQueue for MemoryMessageQueue {
+    type Error = std::convert::Infallible;
+
+    async fn publish_price_update(
+        &self,
+        price_update: PriceUpdate,
+    ) -> Result<(), Self::Error> {
+        self.published.lock().await.push(price_update);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl DeadLetterQueue for MemoryMessageQueue {
+    async fn push_dead_letter(&self, letter: &DeadLetter) -> Result<()> {
+        let mut letters = self.letters.lock().await;
+        if decode_dead_letters(&letters)
+            .iter()
+            .any(|queued| queued.key() == letter.key())
+        {
+            return Ok(());
+        }
+        letters.push(encode_message(DEAD_LETTER_MESSAGE, letter)?);
+        let excess = letters.len().saturating_sub(self.max_dead_letters);
+        letters.drain(..excess);
+        Ok(())
+    }
+
+    async fn drain_dead_letters(&self) -> Result<Vec<DeadLetter>> {
+        let raw = std::mem::take(&mut *self.letters.lock().await);
+        Ok(decode_dead_letters(&raw))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_price_update() -> PriceUpdate {
+        PriceUpdate {
+            name: "".to_string(),
+            pubkey: "6kPvKNrLqg23mApAvHzMKWohhVdSrA54HvrpYud8pump".to_string(),
+            price: 0.000042,
+            market_cap: 42_000.0,
+            timestamp: 1_700_000_000,
+            slot: 300_000_000,
+            swap_amount: 201.36,
+            owner: "owner".to_string(),
+            signature: "sig".to_string(),
+            multi_hop: false,
+            is_buy: true,
+            is_suspected_wash: false,
+            spot_price: None,
+            sol_amount: 0.05,
+            token_amount: -33.78,
+            amm_pool: "amm".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_envelope_round_trip() {
+        let raw =
+            encode_message(RAYDIUM_SWAP_MESSAGE, &make_price_update()).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&raw).unwrap();
+        assert_eq!(value["v"], MESSAGE_SCHEMA_VERSION);
+        assert_eq!(value["type"], "raydium_swap");
+
+        let decoded: PriceUpdate =
+            decode_message(RAYDIUM_SWAP_MESSAGE, &raw).unwrap();
+        assert_eq!(decoded.signature, "sig");
+        assert_eq!(decoded.swap_amount, 201.36);
+        assert!(decode_message::<PriceUpdate>("checklist", &raw).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dead_letters_deduplicated_and_capped() {
+        let queue = MemoryMessageQueue::default().with_max_dead_letters(2);
+        let letter = |signature: &str, pool: &str| DeadLetter {
+            signature: signature.to_string(),
+            pool: pool.to_string(),
+            error: "failed to get token metadata".to_string(),
+            instruction_data: vec![9],
+        };
+        // two swap instructions of the same pool in one tx
+        queue.push_dead_letter(&letter("a", "amm")).await.unwrap();
+        queue.push_dead_letter(&letter("a", "amm")).await.unwrap();
+        queue.push_dead_letter(&letter("a", "other")).await.unwrap();
+        queue.push_dead_letter(&letter("b", "amm")).await.unwrap();
+
+        let keys = queue
+            .drain_dead_letters()
+            .await
+            .unwrap()
+            .iter()
+            .map(DeadLetter::key)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec!["dead_letter:a:other", "dead_letter:b:amm"]);
+
+        // once drained, a replay failing again is lettered again
+        queue.push_dead_letter(&letter("a", "amm")).await.unwrap();
+        assert_eq!(queue.drain_dead_letters().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_dead_letter_skipped() {
+        let queue = MemoryMessageQueue::default();
+        let letter = |signature: &str| DeadLetter {
+            signature: signature.to_string(),
+            pool: "amm".to_string(),
+            error: "failed to get token metadata".to_string(),
+            instruction_data: vec![9],
+        };
+        queue.push_dead_letter(&letter("a")).await.unwrap();
+        queue.push_raw_dead_letter("{\"v\":1,\"type\":").await;
+        queue.push_dead_letter(&letter("b")).await.unwrap();
+
+        // the bad letter doesn't take the others down with it
+        let letters = queue.drain_dead_letters().await.unwrap();
+        assert_eq!(letters, vec![letter("a"), letter("b")]);
+        assert!(queue.drain_dead_letters().await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_envelope_rejects_unknown_version() {
+        let raw = serde_json::to_string(&Envelope {
+            v: MESSAGE_SCHEMA_VERSION + 1,
+            kind: RAYDIUM_SWAP_MESSAGE.to_string(),
+            payload: make_price_update(),
+        })
+        .unwrap();
+        let err = decode_message::<PriceUpdate>(RAYDIUM_SWAP_MESSAGE, &raw)
+            .unwrap_err();
+        assert!(err.to_string().contains("schema version 2"));
+    }
+}
@@ -7,7 +7,7 @@ use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::program_pack::Pack;
 use solana_sdk::pubkey::Pubkey;
 use spl_token::state::Mint;
-use std::{str::FromStr, sync::Arc};
+use std::str::FromStr;
 use tracing::{debug, warn};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -75,8 +75,26 @@ fn convert_ipfs_uri(uri: &str) -> String {
     }
 }
 
+/// TokenMetadataSource resolves the metadata of the mints the processor
+/// prices, None if the mint has none
+#[async_trait::async_trait]
+pub trait TokenMetadataSource: Send + Sync {
+    async fn token_metadata(&self, mint: &str)
+        -> Result<Option<TokenMetadata>>;
+}
+
+#[async_trait::async_trait]
+impl TokenMetadataSource for RedisKVStore {
+    async fn token_metadata(
+        &self,
+        mint: &str,
+    ) -> Result<Option<TokenMetadata>> {
+        get_token_metadata(self, mint).await
+    }
+}
+
 pub async fn get_token_metadata(
-    kv_store: &Arc<RedisKVStore>,
+    kv_store: &RedisKVStore,
     mint: &str,
 ) -> Result<Option<TokenMetadata>> {
     if kv_store.has_metadata(mint).await? {
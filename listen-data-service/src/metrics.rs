@@ -9,6 +9,7 @@ pub struct SwapMetrics {
     pub skipped_tiny_swaps: AtomicU64,
     pub skipped_zero_swaps: AtomicU64,
     pub skipped_unexpected_number_of_tokens: AtomicU64,
+    pub skipped_duplicate_swaps: AtomicU64,
 }
 
 impl SwapMetrics {
@@ -44,6 +45,10 @@ impl SwapMetrics {
             .fetch_add(1, Ordering::Relaxed);
     }
 
+    pub fn increment_skipped_duplicate_swaps(&self) {
+        self.skipped_duplicate_swaps.fetch_add(1, Ordering::Relaxed);
+    }
+
     fn log_metrics(&self) {
         let total = self.total_swaps_processed.load(Ordering::Relaxed);
         let successful = self.successful_swaps.load(Ordering::Relaxed);
@@ -53,6 +58,7 @@ impl SwapMetrics {
         let unexpected = self
             .skipped_unexpected_number_of_tokens
             .load(Ordering::Relaxed);
+        let duplicate = self.skipped_duplicate_swaps.load(Ordering::Relaxed);
 
         let success_rate = if total > 0 {
             (successful as f64 / total as f64) * 100.0
@@ -67,8 +73,16 @@ impl SwapMetrics {
              Failed: {}\n\
              Skipped (tiny): {}\n\
              Skipped (zero): {}\n\
-             Skipped (unexpected tokens): {}",
-            total, successful, success_rate, failed, tiny, zero, unexpected
+             Skipped (unexpected tokens): {}\n\
+             Skipped (duplicate): {}",
+            total,
+            successful,
+            success_rate,
+            failed,
+            tiny,
+            zero,
+            unexpected,
+            duplicate
         );
     }
 }
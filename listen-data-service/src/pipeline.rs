@@ -0,0 +1,107 @@
+use anyhow::Result;
+use carbon_core::{
+    datasource::Datasource,
+    metrics::Metrics,
+    pipeline::{Pipeline, PipelineBuilder},
+};
+use carbon_raydium_amm_v4_decoder::RaydiumAmmV4Decoder;
+use std::{sync::Arc, time::Duration};
+use tracing::error;
+
+use crate::{
+    db::ClickhouseDb,
+    finalized_sink::FinalizedSwapSink,
+    health::{HealthServer, PipelineHealth},
+    kv_store::RedisKVStore,
+    message_queue::RedisMessageQueue,
+    raydium_intruction_processor::RaydiumAmmV4InstructionProcessor,
+    sol_price_source::SolPriceSource,
+    util::{
+        get_finalized_buffer_depth, get_health_max_lag_slots, get_health_port,
+        get_shutdown_strategy, make_rpc_client,
+    },
+};
+
+/// make_raydium_instruction_pipeline runs the raydium instruction decoder and
+/// processor over any datasource (rpc crawler, logs subscription, geyser)
+pub fn make_raydium_instruction_pipeline(
+    datasource: impl Datasource + 'static,
+    kv_store: Arc<RedisKVStore>,
+    message_queue: Arc<RedisMessageQueue>,
+    db: Arc<ClickhouseDb>,
+    sol_price: Arc<dyn SolPriceSource>,
+    metrics: Arc<dyn Metrics>,
+) -> Result<Pipeline> {
+    let builder = Pipeline::builder().datasource(datasource).metrics(metrics);
+    Ok(with_raydium_instruction_processor(
+        builder,
+        kv_store,
+        message_queue,
+        db,
+        sol_price,
+    )?
+    .build()?)
+}
+
+/// with_raydium_instruction_processor adds the raydium instruction step to a
+/// builder, for pipelines that need more than a single datasource; swaps go
+/// through the finalized sink when FINALIZED_ONLY is set, the health endpoint
+/// is served when HEALTH_PORT is set; the pipeline stops per
+/// SHUTDOWN_STRATEGY, immediately by default
+pub fn with_raydium_instruction_processor(
+    builder: PipelineBuilder,
+    kv_store: Arc<RedisKVStore>,
+    message_queue: Arc<RedisMessageQueue>,
+    db: Arc<ClickhouseDb>,
+    sol_price: Arc<dyn SolPriceSource>,
+) -> Result<PipelineBuilder> {
+    let rpc_client = Arc::new(make_rpc_client()?);
+    let health = Arc::new(PipelineHealth::new());
+    if let Some(port) = get_health_port() {
+        let server = HealthServer::new(
+            port,
+            health.clone(),
+            rpc_client.clone(),
+            get_health_max_lag_slots(),
+        );
+        tokio::spawn(async move {
+            if let Err(e) = server.start().await {
+                error!(?e, "Failed to start health server");
+            }
+        });
+    }
+    let builder = builder.shutdown_strategy(get_shutdown_strategy()?);
+    let builder = match get_finalized_buffer_depth() {
+        Some(buffer_depth) => {
+            let sink = Arc::new(FinalizedSwapSink::new(
+                db,
+                make_rpc_client()?,
+                buffer_depth,
+            ));
+            sink.spawn_flush(Duration::from_secs(2));
+            builder.instruction(
+                RaydiumAmmV4Decoder,
+                RaydiumAmmV4InstructionProcessor::new(
+                    kv_store,
+                    message_queue,
+                    sink,
+                    rpc_client,
+                    sol_price,
+                )
+                .with_health(health),
+            )
+        }
+        None => builder.instruction(
+            RaydiumAmmV4Decoder,
+            RaydiumAmmV4InstructionProcessor::new(
+                kv_store,
+                message_queue,
+                db,
+                rpc_client,
+                sol_price,
+            )
+            .with_health(health),
+        ),
+    };
+    Ok(builder)
+}
@@ -1,4 +1,3 @@
-use clickhouse::Row;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -10,7 +9,8 @@ pub struct Price {
     pub pc_decimals: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Row)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "storage", derive(clickhouse::Row))]
 pub struct PriceUpdate {
     pub name: String,
     pub pubkey: String,
@@ -23,4 +23,16 @@ pub struct PriceUpdate {
     pub signature: String,
     pub multi_hop: bool,
     pub is_buy: bool,
+    /// the wallet swapped the token the other way shortly before, see
+    /// `WashTradeDetector`
+    pub is_suspected_wash: bool,
+    /// price from the pool reserves after the transaction, see
+    /// `get_pool_spot_price`
+    pub spot_price: Option<f64>,
+    /// signed change of the pool's quote vault, positive on a buy
+    pub sol_amount: f64,
+    /// signed change of the pool's token vault, negative on a buy
+    pub token_amount: f64,
+    /// the amm of the pool the swap went through
+    pub amm_pool: String,
 }
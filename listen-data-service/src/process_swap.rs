@@ -1,27 +1,129 @@
-use std::sync::Arc;
+use std::sync::Mutex;
 
 use crate::constants::WSOL_MINT_KEY_STR;
-use crate::diffs::{get_token_balance_diff, process_diffs, Diff, DiffsResult};
+use crate::diffs::{
+    get_pool_spot_price, get_token_balance_diff, process_diffs, Diff,
+    DiffsResult,
+};
 use crate::{
-    db::{ClickhouseDb, Database},
-    kv_store::RedisKVStore,
-    message_queue::{MessageQueue, RedisMessageQueue},
-    metadata::get_token_metadata,
+    db::SwapSink,
+    decimals_cache::{DecimalsCache, DecimalsStore, MintFetcher},
+    kv_store::{RedisKVStore, SeenStore},
+    message_queue::{DeadLetterQueue, MessageQueue},
+    metadata::{TokenMetadata, TokenMetadataSource},
     metrics::SwapMetrics,
     price::PriceUpdate,
-    sol_price_stream::SOL_PRICE_CACHE,
+    sol_price_source::{FixedSolPrice, SolPriceSource},
+    wash_trade::WashTradeDetector,
 };
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use carbon_core::transaction::TransactionMetadata;
 use chrono::Utc;
+use solana_sdk::pubkey::Pubkey;
 use tracing::{debug, warn};
 
-pub async fn process_swap(
+/// SwapStore is what the swap processor keeps in the kv store: the seen keys,
+/// the decimals and the token metadata
+pub trait SwapStore:
+    SeenStore + DecimalsStore + TokenMetadataSource + 'static
+{
+}
+
+impl<T: SeenStore + DecimalsStore + TokenMetadataSource + 'static> SwapStore
+    for T
+{
+}
+
+/// SwapQueue is where the swap processor publishes the price updates and
+/// dead letters the swaps it failed on
+pub trait SwapQueue: MessageQueue + DeadLetterQueue {}
+
+impl<T: MessageQueue + DeadLetterQueue> SwapQueue for T {}
+
+/// SwapContext is what processing a swap reads from and writes to, borrowed
+/// from the processor that owns it
+pub struct SwapContext<
+    'a,
+    S: SwapSink,
+    K: SwapStore,
+    Q: SwapQueue,
+    F: MintFetcher,
+> {
+    pub message_queue: &'a Q,
+    pub kv_store: &'a K,
+    pub db: &'a S,
+    pub decimals_cache: &'a DecimalsCache<K, F>,
+    pub wash_trades: &'a Mutex<WashTradeDetector>,
+    pub metrics: &'a SwapMetrics,
+    pub sol_price: &'a dyn SolPriceSource,
+    pub seen_ttl_secs: u64,
+}
+
+/// SwapPool is the pool a swap instruction went through, as listed in its
+/// accounts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SwapPool {
+    pub amm: Pubkey,
+    pub coin_vault: Pubkey,
+    pub pc_vault: Pubkey,
+}
+
+impl SwapPool {
+    /// from_accounts reads the pool off the accounts of a v4 swap, [token
+    /// program, amm, authority, open orders, (target orders,) coin vault, pc
+    /// vault, ..] with 11 more after the vaults; the target orders are
+    /// optional so the vaults are counted from the end
+    pub fn from_accounts(accounts: &[Pubkey]) -> Option<Self> {
+        let coin_vault = accounts.len().checked_sub(13)?;
+        if !(4..=5).contains(&coin_vault) {
+            return None;
+        }
+        Some(Self {
+            amm: accounts[1],
+            coin_vault: accounts[coin_vault],
+            pc_vault: accounts[coin_vault + 1],
+        })
+    }
+
+    /// vault_mints are the mints of the pool's vaults, read off the token
+    /// balances; the keys of a v0 transaction continue with the ones loaded
+    /// from lookup tables
+    fn vault_mints(
+        &self,
+        transaction_metadata: &TransactionMetadata,
+    ) -> Vec<String> {
+        let loaded = &transaction_metadata.meta.loaded_addresses;
+        let keys = transaction_metadata
+            .message
+            .static_account_keys()
+            .iter()
+            .chain(&loaded.writable)
+            .chain(&loaded.readonly)
+            .collect::<Vec<_>>();
+        transaction_metadata
+            .meta
+            .post_token_balances
+            .iter()
+            .flatten()
+            .filter(|balance| {
+                keys.get(balance.account_index as usize).is_some_and(|key| {
+                    **key == self.coin_vault || **key == self.pc_vault
+                })
+            })
+            .map(|balance| balance.mint.clone())
+            .collect()
+    }
+}
+
+pub async fn process_swap<
+    S: SwapSink,
+    K: SwapStore,
+    Q: SwapQueue,
+    F: MintFetcher,
+>(
     transaction_metadata: &TransactionMetadata,
-    message_queue: &RedisMessageQueue,
-    kv_store: &Arc<RedisKVStore>,
-    db: &Arc<ClickhouseDb>,
-    metrics: &SwapMetrics,
+    ctx: &SwapContext<'_, S, K, Q, F>,
+    pool: SwapPool,
 ) -> Result<()> {
     let diffs = get_token_balance_diff(
         transaction_metadata
@@ -38,24 +140,32 @@ pub async fn process_swap(
 
     if diffs.iter().all(|d| d.diff.abs() < 0.01) {
         debug!("skipping tiny diffs");
-        metrics.increment_skipped_tiny_swaps();
+        ctx.metrics.increment_skipped_tiny_swaps();
         return Ok(());
     }
 
     if diffs.iter().any(|d| d.diff == 0.0) {
         debug!("skipping zero diffs (arbitrage likely)");
-        metrics.increment_skipped_zero_swaps();
+        ctx.metrics.increment_skipped_zero_swaps();
         return Ok(());
     }
 
-    let sol_price = SOL_PRICE_CACHE.get_price().await;
+    // pinned so that both hops of a multi-hop swap are quoted the same
+    let sol_price = FixedSolPrice(ctx.sol_price.sol_price());
+    if !sol_price.0.is_finite() || sol_price.0 <= 0.0 {
+        return Err(anyhow!("Invalid SOL price: {}", sol_price.0));
+    }
+    let ctx = SwapContext {
+        sol_price: &sol_price,
+        ..*ctx
+    };
 
     if diffs.len() > 3 || diffs.len() < 2 {
         warn!(
             "https://solscan.io/tx/{} Skipping swap with unexpected number of tokens {:#?}",
             transaction_metadata.signature, diffs
         );
-        metrics.increment_skipped_unexpected_number_of_tokens();
+        ctx.metrics.increment_skipped_unexpected_number_of_tokens();
         return Ok(());
     }
 
@@ -86,71 +196,75 @@ pub async fn process_swap(
                 "https://solscan.io/tx/{} three diff swap with unexpected token changes {:#?}",
                 transaction_metadata.signature, diffs
             );
-            metrics.increment_skipped_unexpected_number_of_tokens();
+            ctx.metrics.increment_skipped_unexpected_number_of_tokens();
             return Ok(());
         }
 
         if let (Some(pos), Some(neg), Some(sol)) =
             (positive_diff, negative_diff, sol_diff)
         {
+            // each hop is recorded by the swap instruction of its own pool,
+            // the other instruction of the route records the other hop
+            let vault_mints = pool.vault_mints(transaction_metadata);
+
             // Process first hop: token being sold to SOL
-            process_two_token_swap(
-                &vec![neg.clone(), sol.clone()],
-                transaction_metadata,
-                message_queue,
-                kv_store,
-                db,
-                sol_price,
-                true,
-            )
-            .await
-            .context("failed to process first hop")?;
+            if vault_mints.contains(&neg.mint) {
+                process_two_token_swap(
+                    &vec![neg.clone(), hop_sol_leg(sol, neg)],
+                    transaction_metadata,
+                    &ctx,
+                    true,
+                    pool.amm,
+                )
+                .await
+                .context("failed to process first hop")?;
+            }
 
             // Process second hop: SOL to token being bought
-            process_two_token_swap(
-                &vec![pos.clone(), sol.clone()],
-                transaction_metadata,
-                message_queue,
-                kv_store,
-                db,
-                sol_price,
-                true,
-            )
-            .await
-            .context("failed to process second hop")?;
+            if vault_mints.contains(&pos.mint) {
+                process_two_token_swap(
+                    &vec![pos.clone(), hop_sol_leg(sol, pos)],
+                    transaction_metadata,
+                    &ctx,
+                    true,
+                    pool.amm,
+                )
+                .await
+                .context("failed to process second hop")?;
+            }
 
             return Ok(());
         }
     }
 
-    process_two_token_swap(
-        &diffs,
-        transaction_metadata,
-        message_queue,
-        kv_store,
-        db,
-        sol_price,
-        false,
-    )
-    .await
+    process_two_token_swap(&diffs, transaction_metadata, &ctx, false, pool.amm)
+        .await
+}
+
+/// hop_sol_leg is the sol side of a single hop, only the net sol change of
+/// the whole route is known so it takes its magnitude with the direction
+/// opposite to the token leg
+fn hop_sol_leg(sol: &Diff, token: &Diff) -> Diff {
+    Diff {
+        diff: -token.diff.signum() * sol.diff.abs(),
+        ..sol.clone()
+    }
 }
 
 // Helper function to process a single two-token swap
-async fn process_two_token_swap(
+async fn process_two_token_swap<
+    S: SwapSink,
+    K: SwapStore,
+    Q: SwapQueue,
+    F: MintFetcher,
+>(
     diffs: &Vec<Diff>,
     transaction_metadata: &TransactionMetadata,
-    message_queue: &RedisMessageQueue,
-    kv_store: &Arc<RedisKVStore>,
-    db: &Arc<ClickhouseDb>,
-    sol_price: f64,
+    ctx: &SwapContext<'_, S, K, Q, F>,
     multi_hop: bool,
+    amm_pool: Pubkey,
 ) -> Result<()> {
-    let DiffsResult {
-        price,
-        swap_amount,
-        coin_mint,
-        is_buy,
-    } = match process_diffs(diffs, sol_price) {
+    let diffs_result = match process_diffs(diffs, ctx.sol_price, amm_pool) {
         Ok(result) => result,
         Err(e) => {
             let token_mints =
@@ -161,15 +275,124 @@ async fn process_two_token_swap(
     };
 
     // Get metadata and emit price update
-    let token_metadata = get_token_metadata(kv_store, &coin_mint)
+    let token_metadata = ctx
+        .kv_store
+        .token_metadata(&diffs_result.coin_mint)
         .await
         .context("failed to get token metadata")?;
+    let decimals = match diffs_result.coin_mint.parse() {
+        Ok(mint) => ctx
+            .decimals_cache
+            .decimals(&mint)
+            .await
+            .map_err(|e| warn!(?e, "failed to get decimals"))
+            .ok(),
+        Err(_) => None,
+    };
+
+    let mut price_update = make_price_update(
+        diffs_result,
+        token_metadata,
+        decimals,
+        transaction_metadata.slot,
+        &transaction_metadata.signature.to_string(),
+        &transaction_metadata.fee_payer.to_string(),
+        multi_hop,
+    );
+    ctx.wash_trades.lock().unwrap().tag(&mut price_update);
+    price_update.spot_price = transaction_metadata
+        .meta
+        .post_token_balances
+        .as_ref()
+        .and_then(|post| {
+            get_pool_spot_price(post, &price_update.pubkey, ctx.sol_price)
+        });
+
+    let signature = transaction_metadata.signature.to_string();
+    if !insert_swap_once(
+        ctx.db,
+        ctx.kv_store,
+        &signature,
+        ctx.seen_ttl_secs,
+        &price_update,
+    )
+    .await?
+    {
+        debug!(signature, mint = price_update.pubkey, "skipping duplicate");
+        ctx.metrics.increment_skipped_duplicate_swaps();
+        return Ok(());
+    }
+
+    ctx.message_queue
+        .publish_price_update(price_update)
+        .await
+        .context("failed to publish price update")?;
+
+    Ok(())
+}
+
+/// insert_swap_once check-and-sets the seen key of the swap before inserting,
+/// returns false if the swap was already recorded within the ttl; the key is
+/// released again if the insert fails, so that a redelivery (or a replay of
+/// the dead letter) records the swap instead of being dropped as a duplicate
+///
+/// the key is `seen:{signature}:{mint}` rather than per instruction, since
+/// the record is computed from transaction wide balance diffs and a tx with
+/// several raydium swap instructions would otherwise be stored once per ix
+pub async fn insert_swap_once<S: SwapSink, K: SeenStore>(
+    db: &S,
+    seen_store: &K,
+    signature: &str,
+    seen_ttl_secs: u64,
+    price_update: &PriceUpdate,
+) -> Result<bool> {
+    let key = RedisKVStore::make_seen_key(signature, &price_update.pubkey);
+    if !seen_store
+        .mark_seen(&key, seen_ttl_secs)
+        .await
+        .context("failed to mark swap as seen")?
+    {
+        return Ok(false);
+    }
+
+    if let Err(e) = db.insert_swap(price_update).await {
+        if let Err(e) = seen_store.unmark_seen(&key).await {
+            warn!(?e, key, "failed to release seen key");
+        }
+        return Err(e.context("failed to insert price update"));
+    }
+
+    Ok(true)
+}
+
+/// make_price_update turns the result of a single two-token swap into the
+/// record that is stored and published, the market cap is in ui units of the
+/// supply, scaled by `decimals` or the ones in the metadata if not given
+pub fn make_price_update(
+    diffs_result: DiffsResult,
+    token_metadata: Option<TokenMetadata>,
+    decimals: Option<u8>,
+    slot: u64,
+    signature: &str,
+    owner: &str,
+    multi_hop: bool,
+) -> PriceUpdate {
+    let DiffsResult {
+        price,
+        swap_amount,
+        coin_mint,
+        is_buy,
+        sol_amount,
+        token_amount,
+        amm_pool,
+        ..
+    } = diffs_result;
 
     // Calculate market cap if we have the metadata
     let market_cap = token_metadata.as_ref().map(|metadata| {
         let supply = metadata.spl.supply as f64;
-        let adjusted_supply =
-            supply / (10_f64.powi(metadata.spl.decimals as i32));
+        let decimals = decimals.unwrap_or(metadata.spl.decimals);
+        let adjusted_supply = supply / (10_f64.powi(decimals as i32));
         price * adjusted_supply
     });
 
@@ -180,80 +403,337 @@ async fn process_two_token_swap(
 
     let market_cap = market_cap.unwrap_or(0.0);
 
-    let price_update = PriceUpdate {
+    PriceUpdate {
         name,
         pubkey: coin_mint,
         price,
         market_cap,
         timestamp: Utc::now().timestamp() as u64,
-        slot: transaction_metadata.slot,
+        slot,
         swap_amount,
-        owner: transaction_metadata.fee_payer.to_string(),
-        signature: format!(
-            "https://solscan.io/tx/{}",
-            transaction_metadata.signature
-        ),
+        owner: owner.to_string(),
+        signature: format!("https://solscan.io/tx/{}", signature),
         multi_hop,
         is_buy,
+        is_suspected_wash: false,
+        spot_price: None,
+        sol_amount,
+        token_amount,
+        amm_pool: amm_pool.to_string(),
+    }
+}
+
+/// load_transaction_metadata reads a fixture into what the pipeline hands
+/// the processor, the message only carries the account keys
+#[cfg(test)]
+pub fn load_transaction_metadata(fixture: &str) -> TransactionMetadata {
+    use carbon_core::datasource::TransactionUpdate;
+    use solana_sdk::{
+        hash::Hash,
+        message::Message,
+        signature::Signature,
+        transaction::{Transaction, VersionedTransaction},
+    };
+    use solana_transaction_status::{
+        TransactionStatusMeta, TransactionTokenBalance,
+        UiTransactionTokenBalance,
     };
 
-    db.insert_price(&price_update)
-        .await
-        .context("failed to insert price update")?;
+    #[derive(serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Meta {
+        fee: u64,
+        pre_balances: Vec<u64>,
+        post_balances: Vec<u64>,
+        pre_token_balances: Vec<UiTransactionTokenBalance>,
+        post_token_balances: Vec<UiTransactionTokenBalance>,
+    }
+    #[derive(serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Fixture {
+        signature: String,
+        slot: u64,
+        account_keys: Vec<String>,
+        meta: Meta,
+    }
 
-    message_queue
-        .publish_price_update(price_update)
-        .await
-        .context("failed to publish price update")?;
+    let fixture: Fixture =
+        serde_json::from_reader(std::fs::File::open(fixture).unwrap()).unwrap();
+    let token_balances = |balances: Vec<UiTransactionTokenBalance>| {
+        balances
+            .into_iter()
+            .map(|balance| TransactionTokenBalance {
+                account_index: balance.account_index,
+                mint: balance.mint,
+                ui_token_amount: balance.ui_token_amount,
+                owner: Option::from(balance.owner).unwrap_or_default(),
+                program_id: Option::from(balance.program_id)
+                    .unwrap_or_default(),
+            })
+            .collect()
+    };
+    let account_keys = fixture
+        .account_keys
+        .iter()
+        .map(|key| key.parse().unwrap())
+        .collect();
+
+    TransactionMetadata::try_from(TransactionUpdate {
+        signature: fixture.signature.parse().unwrap(),
+        transaction: VersionedTransaction::from(Transaction {
+            signatures: vec![Signature::default()],
+            message: Message::new_with_compiled_instructions(
+                1,
+                0,
+                0,
+                account_keys,
+                Hash::default(),
+                vec![],
+            ),
+        }),
+        meta: TransactionStatusMeta {
+            fee: fixture.meta.fee,
+            pre_balances: fixture.meta.pre_balances,
+            post_balances: fixture.meta.post_balances,
+            pre_token_balances: Some(token_balances(
+                fixture.meta.pre_token_balances,
+            )),
+            post_token_balances: Some(token_balances(
+                fixture.meta.post_token_balances,
+            )),
+            ..Default::default()
+        },
+        is_vote: false,
+        slot: fixture.slot,
+    })
+    .unwrap()
+}
 
-    Ok(())
+/// fixture_pool is a pool with its coin and pc vaults at `vaults` in the
+/// account keys of the transaction
+#[cfg(test)]
+pub fn fixture_pool(
+    transaction_metadata: &TransactionMetadata,
+    vaults: [usize; 2],
+) -> SwapPool {
+    let keys = transaction_metadata.message.static_account_keys();
+    SwapPool {
+        amm: Pubkey::new_unique(),
+        coin_vault: keys[vaults[0]],
+        pc_vault: keys[vaults[1]],
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
+        db::MemorySwapSink,
         diffs::Diff,
-        util::{make_rpc_client, round_to_decimals},
+        kv_store::MemoryKVStore,
+        message_queue::{dead_letter_on_error, MemoryMessageQueue},
+        metadata::SplTokenMetadata,
     };
 
     use super::*;
+    use crate::decimals_cache::NoMintFetcher;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_swap_recorded_in_sink() {
+        // a 0.55 sol buy of 6822.42 tokens
+        let tx_meta =
+            load_transaction_metadata("fixtures/ephemeral_wsol_tx.json");
+        let mint = "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon";
+
+        let kv_store = Arc::new(MemoryKVStore::default());
+        kv_store
+            .decimals
+            .lock()
+            .await
+            .insert(mint.parse().unwrap(), 6);
+        kv_store.metadata.lock().await.insert(
+            mint.to_string(),
+            TokenMetadata {
+                mint: mint.to_string(),
+                spl: SplTokenMetadata {
+                    supply: 1_000_000_000_000_000,
+                    decimals: 6,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        let message_queue = MemoryMessageQueue::default();
+        let sink = MemorySwapSink::default();
+        let decimals_cache =
+            DecimalsCache::new(kv_store.clone(), Arc::new(NoMintFetcher));
+        let wash_trades = Mutex::new(WashTradeDetector::default());
+        let metrics = SwapMetrics::new();
+
+        // the second delivery (the crawler catching up on the websocket) is
+        // dropped by the seen key
+        let ctx = SwapContext {
+            message_queue: &message_queue,
+            kv_store: kv_store.as_ref(),
+            db: &sink,
+            decimals_cache: &decimals_cache,
+            wash_trades: &wash_trades,
+            metrics: &metrics,
+            sol_price: &FixedSolPrice(200.0),
+            seen_ttl_secs: 60,
+        };
+        let pool = fixture_pool(&tx_meta, [1, 2]);
+        for _ in 0..2 {
+            process_swap(&tx_meta, &ctx, pool).await.unwrap();
+        }
+
+        assert_eq!(
+            metrics
+                .skipped_duplicate_swaps
+                .load(std::sync::atomic::Ordering::Relaxed),
+            1
+        );
+        let swaps = sink.swaps().await;
+        assert_eq!(swaps.len(), 1);
+        let swap = &swaps[0];
+        let price = 0.55 / 6822.422379 * 200.0;
+        assert_eq!(swap.pubkey, mint);
+        assert!((swap.price - price).abs() / price < 1e-9, "{}", swap.price);
+        assert!((swap.swap_amount - 110.0).abs() < 1e-6);
+        assert_eq!(swap.market_cap, swap.price * 1_000_000_000.0);
+        assert_eq!(swap.slot, 312_000_000);
+        assert_eq!(
+            swap.signature,
+            format!("https://solscan.io/tx/{}", tx_meta.signature)
+        );
+        assert_eq!(swap.owner, "75mcNqWEY7v1xC3JPgfUQDUhmcFiGGCPa9CaYNdVhw3u");
+        assert!(swap.is_buy);
+        assert!(!swap.multi_hop);
+        assert!(swap.spot_price.is_some());
+        assert!((swap.sol_amount - 0.55).abs() < 1e-9);
+        assert!((swap.token_amount + 6822.422379).abs() < 1e-6);
+        assert_eq!(swap.amm_pool, pool.amm.to_string());
+
+        let published = message_queue.published().await;
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0].signature, swap.signature);
+    }
 
     #[tokio::test]
-    async fn test_sol_for_token() {
+    async fn test_multi_hop_hops_recorded_with_own_pool() {
+        // 1000 A sold into pool A for 1 sol, which buys 500 B out of pool B
+        let tx_meta = load_transaction_metadata("fixtures/multi_hop_tx.json");
+        let kv_store = Arc::new(MemoryKVStore::default());
+        let message_queue = MemoryMessageQueue::default();
+        let sink = MemorySwapSink::default();
+        let decimals_cache =
+            DecimalsCache::new(kv_store.clone(), Arc::new(NoMintFetcher));
+        let wash_trades = Mutex::new(WashTradeDetector::default());
+        let metrics = SwapMetrics::new();
+        let ctx = SwapContext {
+            message_queue: &message_queue,
+            kv_store: kv_store.as_ref(),
+            db: &sink,
+            decimals_cache: &decimals_cache,
+            wash_trades: &wash_trades,
+            metrics: &metrics,
+            sol_price: &FixedSolPrice(200.0),
+            seen_ttl_secs: 60,
+        };
+
+        // one swap instruction per hop, each records the hop of its pool
+        let pool_a = fixture_pool(&tx_meta, [1, 2]);
+        let pool_b = fixture_pool(&tx_meta, [4, 3]);
+        process_swap(&tx_meta, &ctx, pool_a).await.unwrap();
+        process_swap(&tx_meta, &ctx, pool_b).await.unwrap();
+
+        let swaps = sink.swaps().await;
+        assert_eq!(swaps.len(), 2);
+        let (a, b) = (&swaps[0], &swaps[1]);
+        assert_eq!(a.pubkey, "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon");
+        assert_eq!(a.amm_pool, pool_a.amm.to_string());
+        assert!(!a.is_buy);
+        assert!((a.sol_amount + 1.0).abs() < 1e-9);
+        assert!((a.token_amount - 1000.0).abs() < 1e-9);
+        assert_eq!(b.pubkey, "6kPvKNrLqg23mApAvHzMKWohhVdSrA54HvrpYud8pump");
+        assert_eq!(b.amm_pool, pool_b.amm.to_string());
+        assert!(b.is_buy);
+        assert!((b.sol_amount - 1.0).abs() < 1e-9);
+        assert!((b.token_amount + 500.0).abs() < 1e-9);
+        assert!(a.multi_hop && b.multi_hop);
+        assert_eq!(
+            metrics
+                .skipped_duplicate_swaps
+                .load(std::sync::atomic::Ordering::Relaxed),
+            0
+        );
+    }
+
+    #[test]
+    fn test_swap_pool_from_accounts() {
+        let accounts =
+            (0..18).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        // with the target orders
+        let pool = SwapPool::from_accounts(&accounts).unwrap();
+        assert_eq!(pool.amm, accounts[1]);
+        assert_eq!(pool.coin_vault, accounts[5]);
+        assert_eq!(pool.pc_vault, accounts[6]);
+        // without
+        let pool = SwapPool::from_accounts(&accounts[..17]).unwrap();
+        assert_eq!(pool.coin_vault, accounts[4]);
+        assert_eq!(pool.pc_vault, accounts[5]);
+        assert!(SwapPool::from_accounts(&accounts[..2]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_swap_inserted_once() {
         let diffs = vec![
             Diff {
-                mint: "G6ZaVuWEuGtFRooaiHQWjDzoCzr2f7BWr3PhsQRnjSTE"
-                    .to_string(),
-                pre_amount: 9502698.632123,
-                post_amount: 9493791.483438,
-                diff: -8907.148685000837,
-                owner: "8CNuwDVRshWyZtWRvgb31AMaBge4q6KSRHNPdJHP29HU"
+                mint: "So11111111111111111111111111111111111111112".to_string(),
+                pre_amount: 450.295597127,
+                post_amount: 450.345597127,
+                diff: 0.05000000000001137,
+                owner: "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1"
                     .to_string(),
             },
             Diff {
-                mint: "So11111111111111111111111111111111111111112".to_string(),
-                pre_amount: 145.774357667,
-                post_amount: 142.421949398,
-                diff: -3.3524082689999943,
+                mint: "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon"
+                    .to_string(),
+                pre_amount: 61602947.9232689,
+                post_amount: 61596125.50088912,
+                diff: -6822.422379776835,
                 owner: "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1"
                     .to_string(),
             },
         ];
 
-        let DiffsResult {
-            price, swap_amount, ..
-        } = process_diffs(&diffs, 201.36).unwrap();
-        let rounded_price = round_to_decimals(price, 4);
-        assert!(rounded_price == 0.0758, "price: {}", rounded_price);
-        assert!(
-            swap_amount == 3.3524082689999943 * 201.36,
-            "swap_amount: {}",
-            swap_amount
-        );
+        let sink = MemorySwapSink::default();
+        let seen_store = MemoryKVStore::default();
+        for _ in 0..2 {
+            let price_update = make_price_update(
+                process_diffs(
+                    &diffs,
+                    &FixedSolPrice(202.12),
+                    Pubkey::default(),
+                )
+                .unwrap(),
+                None,
+                None,
+                312_000_000,
+                "sig",
+                "owner",
+                false,
+            );
+            insert_swap_once(&sink, &seen_store, "sig", 60, &price_update)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(sink.swaps().await.len(), 1);
     }
 
     #[tokio::test]
-    async fn test_sol_for_token_2() {
+    async fn test_failed_insert_not_marked_seen() {
         let diffs = vec![
             Diff {
                 mint: "So11111111111111111111111111111111111111112".to_string(),
@@ -273,77 +753,97 @@ mod tests {
                     .to_string(),
             },
         ];
-
-        let DiffsResult {
-            price, swap_amount, ..
-        } = process_diffs(&diffs, 202.12).unwrap();
-        let rounded_price = round_to_decimals(price, 5);
-        assert!(rounded_price == 0.00148, "price: {}", rounded_price);
-        assert!(
-            swap_amount == 0.05000000000001137 * 202.12,
-            "swap_amount: {}",
-            swap_amount
+        let price_update = make_price_update(
+            process_diffs(&diffs, &FixedSolPrice(202.12), Pubkey::default())
+                .unwrap(),
+            None,
+            None,
+            312_000_000,
+            "sig",
+            "owner",
+            false,
         );
-    }
-
-    #[tokio::test]
-    async fn test_by_signature() {
-        let signature = "538voMuFQKp3oE6Tu598R8kJN12sum2cGMxZBxrV2Vuip1TL4qdWaXiJ8u3yRxgJy9SFX4faP2zC83oDX68D2wuW";
-        let transaction = make_rpc_client()
-            .unwrap()
-            .get_transaction_with_config(
-                &signature.parse().unwrap(),
-                solana_client::rpc_config::RpcTransactionConfig {
-                    encoding: Some(solana_transaction_status::UiTransactionEncoding::JsonParsed),
-                    max_supported_transaction_version: Some(0),
-                    ..Default::default()
-                },
-            )
-            .await
-            .unwrap();
 
-        let transaction_meta = transaction.transaction.meta.unwrap();
+        let seen_store = MemoryKVStore::default();
+        assert!(insert_swap_once(
+            &MemorySwapSink::failing(),
+            &seen_store,
+            "sig",
+            60,
+            &price_update,
+        )
+        .await
+        .is_err());
 
-        let diffs = get_token_balance_diff(
-            transaction_meta.pre_token_balances.as_ref().unwrap(),
-            transaction_meta.post_token_balances.as_ref().unwrap(),
-        );
-        println!("diffs: {:#?}", diffs);
-        let DiffsResult {
-            price, swap_amount, ..
-        } = process_diffs(&diffs, 203.67).unwrap();
-        let rounded_price = round_to_decimals(price, 5);
-        assert!(rounded_price == 0.00035, "price: {}", rounded_price);
-        let rounded_swap_amount = round_to_decimals(swap_amount, 4);
+        // the redelivery is recorded rather than skipped as a duplicate
+        let sink = MemorySwapSink::default();
         assert!(
-            rounded_swap_amount == 0.8618,
-            "swap_amount: {}",
-            rounded_swap_amount
+            insert_swap_once(&sink, &seen_store, "sig", 60, &price_update)
+                .await
+                .unwrap()
         );
+        assert_eq!(sink.swaps().await.len(), 1);
     }
 
     #[tokio::test]
-    #[ignore = "placeholder, useful for debugging"]
-    async fn test_by_signature_2() {
-        let signature = "5HdnMgQTdsuYjL8fgb6RphArEyr9qECCbDy22D8DwJA1i4mdCbhNqv645AHJMHUU4JGjsjfjPyKFfnxVZkaX8PMG";
-        let transaction = make_rpc_client()
-            .unwrap()
-            .get_transaction_with_config(
-                &signature.parse().unwrap(),
-                solana_client::rpc_config::RpcTransactionConfig {
-                    encoding: Some(solana_transaction_status::UiTransactionEncoding::JsonParsed),
-                    max_supported_transaction_version: Some(0),
-                    ..Default::default()
-                },
-            )
+    async fn test_failed_insert_dead_lettered() {
+        let diffs = vec![
+            Diff {
+                mint: "So11111111111111111111111111111111111111112".to_string(),
+                pre_amount: 450.295597127,
+                post_amount: 450.345597127,
+                diff: 0.05000000000001137,
+                owner: "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1"
+                    .to_string(),
+            },
+            Diff {
+                mint: "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon"
+                    .to_string(),
+                pre_amount: 61602947.9232689,
+                post_amount: 61596125.50088912,
+                diff: -6822.422379776835,
+                owner: "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1"
+                    .to_string(),
+            },
+        ];
+        let price_update = make_price_update(
+            process_diffs(&diffs, &FixedSolPrice(202.12), Pubkey::default())
+                .unwrap(),
+            None,
+            None,
+            312_000_000,
+            "sig",
+            "owner",
+            false,
+        );
+        let swap_data = [
+            0x09, 0x00, 0x2f, 0x68, 0x59, 0x00, 0x00, 0x00, 0x00, 0x66, 0x94,
+            0xfa, 0xe2, 0xae, 0x0a, 0x00, 0x00,
+        ];
+
+        let sink = MemorySwapSink::failing();
+        let dead_letters = MemoryMessageQueue::default();
+        let result = insert_swap_once(
+            &sink,
+            &MemoryKVStore::default(),
+            "sig",
+            60,
+            &price_update,
+        )
+        .await
+        .map(|_| ());
+        dead_letter_on_error(&dead_letters, "sig", "amm", &swap_data, result)
             .await
             .unwrap();
 
-        let transaction_meta = transaction.transaction.meta.unwrap();
-
-        let _diffs = get_token_balance_diff(
-            transaction_meta.pre_token_balances.as_ref().unwrap(),
-            transaction_meta.post_token_balances.as_ref().unwrap(),
-        );
+        let letters = dead_letters.drain_dead_letters().await.unwrap();
+        assert_eq!(letters.len(), 1);
+        assert_eq!(letters[0].signature, "sig");
+        assert_eq!(letters[0].pool, "amm");
+        assert_eq!(letters[0].instruction_data, swap_data);
+        assert!(letters[0]
+            .error
+            .starts_with("failed to insert price update"));
+        assert!(dead_letters.drain_dead_letters().await.unwrap().is_empty());
     }
 }
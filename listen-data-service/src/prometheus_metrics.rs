@@ -0,0 +1,233 @@
+use std::{
+    collections::HashMap, convert::Infallible, net::SocketAddr, sync::Mutex,
+};
+
+use carbon_core::{
+    error::{CarbonResult, Error as CarbonError},
+    metrics::Metrics,
+};
+use http_body_util::Full;
+use hyper::{
+    body::{Bytes, Incoming},
+    server::conn::http1,
+    service::service_fn,
+    Request, Response, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use prometheus::{
+    exponential_buckets, Encoder, Gauge, Histogram, HistogramOpts, IntCounter,
+    Registry, TextEncoder,
+};
+use tokio::net::TcpListener;
+use tracing::{debug, error, info};
+
+#[derive(Default)]
+struct Collectors {
+    counters: HashMap<String, IntCounter>,
+    gauges: HashMap<String, Gauge>,
+    histograms: HashMap<String, Histogram>,
+}
+
+/// PrometheusMetrics is a carbon metrics backend that exposes everything the
+/// pipeline reports (processed and failed updates, processing time
+/// histograms, queue gauges) on a `/metrics` endpoint, collectors are
+/// registered on first use under the name carbon reports them with
+pub struct PrometheusMetrics {
+    registry: Registry,
+    collectors: Mutex<Collectors>,
+    addr: SocketAddr,
+}
+
+impl PrometheusMetrics {
+    pub fn new(port: u16) -> Self {
+        Self {
+            registry: Registry::new(),
+            collectors: Mutex::new(Collectors::default()),
+            addr: SocketAddr::from(([0, 0, 0, 0], port)),
+        }
+    }
+
+    fn register<C, F>(
+        &self,
+        map: &mut HashMap<String, C>,
+        name: &str,
+        make: F,
+    ) -> CarbonResult<C>
+    where
+        C: prometheus::core::Collector + Clone + 'static,
+        F: FnOnce(String) -> prometheus::Result<C>,
+    {
+        if let Some(collector) = map.get(name) {
+            return Ok(collector.clone());
+        }
+        let collector = make(sanitize_metric_name(name))
+            .map_err(|e| CarbonError::Custom(e.to_string()))?;
+        self.registry
+            .register(Box::new(collector.clone()))
+            .map_err(|e| CarbonError::Custom(e.to_string()))?;
+        map.insert(name.to_string(), collector.clone());
+        Ok(collector)
+    }
+}
+
+#[async_trait::async_trait]
+impl Metrics for PrometheusMetrics {
+    async fn initialize(&self) -> CarbonResult<()> {
+        let listener = TcpListener::bind(self.addr)
+            .await
+            .map_err(|e| CarbonError::Custom(e.to_string()))?;
+        info!("Metrics server running on {}", self.addr);
+        tokio::spawn(serve_metrics(listener, self.registry.clone()));
+        Ok(())
+    }
+
+    async fn flush(&self) -> CarbonResult<()> {
+        Ok(())
+    }
+
+    async fn shutdown(&self) -> CarbonResult<()> {
+        Ok(())
+    }
+
+    async fn update_gauge(&self, name: &str, value: f64) -> CarbonResult<()> {
+        let mut collectors = self.collectors.lock().unwrap();
+        let gauge = self.register(&mut collectors.gauges, name, |n| {
+            Gauge::new(n.clone(), n)
+        })?;
+        gauge.set(value);
+        Ok(())
+    }
+
+    async fn increment_counter(
+        &self,
+        name: &str,
+        value: u64,
+    ) -> CarbonResult<()> {
+        let mut collectors = self.collectors.lock().unwrap();
+        let counter = self.register(&mut collectors.counters, name, |n| {
+            IntCounter::new(n.clone(), n)
+        })?;
+        counter.inc_by(value);
+        Ok(())
+    }
+
+    async fn record_histogram(
+        &self,
+        name: &str,
+        value: f64,
+    ) -> CarbonResult<()> {
+        let mut collectors = self.collectors.lock().unwrap();
+        let histogram =
+            self.register(&mut collectors.histograms, name, |n| {
+                // carbon reports durations both in ms and ns, 1..4^15
+                // covers both
+                Histogram::with_opts(
+                    HistogramOpts::new(n.clone(), n)
+                        .buckets(exponential_buckets(1.0, 4.0, 16)?),
+                )
+            })?;
+        histogram.observe(value);
+        Ok(())
+    }
+}
+
+fn sanitize_metric_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+async fn serve_metrics(listener: TcpListener, registry: Registry) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                error!(?e, "metrics server accept failed");
+                continue;
+            }
+        };
+        let registry = registry.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req: Request<Incoming>| {
+                let res = handle_request(&req, &registry);
+                async move { Ok::<_, Infallible>(res) }
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!(?e, "metrics connection closed");
+            }
+        });
+    }
+}
+
+fn handle_request(
+    req: &Request<Incoming>,
+    registry: &Registry,
+) -> Response<Full<Bytes>> {
+    if req.uri().path() != "/metrics" {
+        let mut res = Response::new(Full::new(Bytes::new()));
+        *res.status_mut() = StatusCode::NOT_FOUND;
+        return res;
+    }
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&registry.gather(), &mut buffer) {
+        error!(?e, "failed to encode metrics");
+        let mut res = Response::new(Full::new(Bytes::new()));
+        *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        return res;
+    }
+
+    let mut res = Response::new(Full::new(Bytes::from(buffer)));
+    if let Ok(content_type) = encoder.format_type().parse() {
+        res.headers_mut()
+            .insert(hyper::header::CONTENT_TYPE, content_type);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scrape_metrics_endpoint() {
+        let metrics = PrometheusMetrics::new(19091);
+        metrics.initialize().await.unwrap();
+
+        metrics
+            .increment_counter("updates_processed", 3)
+            .await
+            .unwrap();
+        metrics
+            .increment_counter("updates_failed", 1)
+            .await
+            .unwrap();
+        metrics
+            .record_histogram("updates_process_time_milliseconds", 12.0)
+            .await
+            .unwrap();
+        metrics.update_gauge("updates_queued", 5.0).await.unwrap();
+
+        let body = reqwest::get("http://127.0.0.1:19091/metrics")
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+
+        assert!(body.contains("updates_processed 3"), "{}", body);
+        assert!(body.contains("updates_failed 1"), "{}", body);
+        assert!(body.contains("updates_process_time_milliseconds_bucket"));
+        assert!(body.contains("updates_queued 5"), "{}", body);
+
+        let not_found = reqwest::get("http://127.0.0.1:19091/")
+            .await
+            .unwrap()
+            .status();
+        assert_eq!(not_found, reqwest::StatusCode::NOT_FOUND);
+    }
+}
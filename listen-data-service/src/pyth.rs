@@ -0,0 +1,184 @@
+use anyhow::{anyhow, Result};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{pubkey, pubkey::Pubkey};
+
+/// sponsored SOL/USD feed of the pyth receiver (shard 0), pyth keeps it
+/// updated so it can be read without posting updates ourselves
+pub const PYTH_SOL_USD_PRICE_ACCOUNT: Pubkey =
+    pubkey!("7UVimffxr9ow1uXYxsr4LHAcV58mLzhmwaeKvJ1pjLiE");
+
+pub const PYTH_SOL_USD_FEED_ID: [u8; 32] = [
+    0xef, 0x0d, 0x8b, 0x6f, 0xda, 0x2c, 0xeb, 0xa4, 0x1d, 0xa1, 0x5d, 0x40,
+    0x95, 0xd1, 0xda, 0x39, 0x2a, 0x0d, 0x2f, 0x8e, 0xd0, 0xc6, 0xc7, 0xbc,
+    0x0f, 0x4c, 0xfa, 0xc8, 0xc2, 0x80, 0xb5, 0x6d,
+];
+
+/// anchor discriminator of the receiver's PriceUpdateV2 account
+const PRICE_UPDATE_V2_DISCRIMINATOR: [u8; 8] =
+    [34, 241, 35, 99, 157, 126, 244, 205];
+
+/// a price whose confidence interval is wider than this fraction of the price
+/// is too uncertain to quote swaps with
+pub const PYTH_MAX_CONF_RATIO: f64 = 0.01;
+pub const PYTH_MAX_AGE_SECS: i64 = 60;
+
+/// PythPrice is the price message of a PriceUpdateV2 account, the real values
+/// are `price * 10^exponent` and `conf * 10^exponent`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PythPrice {
+    pub feed_id: [u8; 32],
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+    pub publish_time: i64,
+}
+
+impl PythPrice {
+    pub fn price(&self) -> f64 {
+        self.price as f64 * 10f64.powi(self.exponent)
+    }
+
+    pub fn conf(&self) -> f64 {
+        self.conf as f64 * 10f64.powi(self.exponent)
+    }
+
+    /// validated_price rejects prices that are stale as of `now` (unix
+    /// seconds), non-positive or with too wide a confidence interval
+    pub fn validated_price(&self, now: i64) -> Result<f64> {
+        let age = now - self.publish_time;
+        if age > PYTH_MAX_AGE_SECS {
+            return Err(anyhow!("Pyth price is stale, published {}s ago", age));
+        }
+        let price = self.price();
+        if !price.is_finite() || price <= 0.0 {
+            return Err(anyhow!("Invalid Pyth price: {}", price));
+        }
+        let conf = self.conf();
+        if conf / price > PYTH_MAX_CONF_RATIO {
+            return Err(anyhow!(
+                "Pyth price too uncertain: {} +/- {}",
+                price,
+                conf
+            ));
+        }
+        Ok(price)
+    }
+}
+
+/// decode_price_update reads the price message of a PriceUpdateV2 account,
+/// only fully verified updates are accepted
+///
+/// layout: discriminator (8), write authority (32), verification level
+/// (borsh enum, 2 bytes if partial, 1 if full), then the price message:
+/// feed id (32), price i64, conf u64, exponent i32, publish time i64, ...
+pub fn decode_price_update(data: &[u8]) -> Result<PythPrice> {
+    if data.get(..8) != Some(&PRICE_UPDATE_V2_DISCRIMINATOR[..]) {
+        return Err(anyhow!("Not a Pyth PriceUpdateV2 account"));
+    }
+    let message = match data.get(40) {
+        Some(1) => &data[41..],
+        Some(0) => {
+            return Err(anyhow!("Pyth price update is partially verified"))
+        }
+        _ => return Err(anyhow!("Invalid Pyth verification level")),
+    };
+    if message.len() < 60 {
+        return Err(anyhow!("Pyth price message too short"));
+    }
+    let feed_id: [u8; 32] = message[..32].try_into()?;
+    let price = i64::from_le_bytes(message[32..40].try_into()?);
+    let conf = u64::from_le_bytes(message[40..48].try_into()?);
+    let exponent = i32::from_le_bytes(message[48..52].try_into()?);
+    let publish_time = i64::from_le_bytes(message[52..60].try_into()?);
+    Ok(PythPrice {
+        feed_id,
+        price,
+        conf,
+        exponent,
+        publish_time,
+    })
+}
+
+/// fetch_sol_price_pyth reads the SOL/USD price straight from chain
+pub async fn fetch_sol_price_pyth(rpc: &RpcClient) -> Result<f64> {
+    let data = rpc.get_account_data(&PYTH_SOL_USD_PRICE_ACCOUNT).await?;
+    let update = decode_price_update(&data)?;
+    if update.feed_id != PYTH_SOL_USD_FEED_ID {
+        return Err(anyhow!("Pyth account is not the SOL/USD feed"));
+    }
+    update.validated_price(chrono::Utc::now().timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// PriceUpdateV2 account as written by the receiver, 134 bytes
+    fn make_price_update(price: i64, conf: u64, publish_time: i64) -> Vec<u8> {
+        let mut data = PRICE_UPDATE_V2_DISCRIMINATOR.to_vec();
+        data.extend_from_slice(&[0; 32]); // write authority
+        data.push(1); // VerificationLevel::Full
+        data.extend_from_slice(&PYTH_SOL_USD_FEED_ID);
+        data.extend_from_slice(&price.to_le_bytes());
+        data.extend_from_slice(&conf.to_le_bytes());
+        data.extend_from_slice(&(-8i32).to_le_bytes());
+        data.extend_from_slice(&publish_time.to_le_bytes());
+        data.extend_from_slice(&(publish_time - 1).to_le_bytes());
+        data.extend_from_slice(&price.to_le_bytes()); // ema price
+        data.extend_from_slice(&conf.to_le_bytes()); // ema conf
+        data.extend_from_slice(&312_000_000u64.to_le_bytes()); // posted slot
+        data.push(0); // sized for a partial level, 1 byte longer
+        data
+    }
+
+    #[test]
+    fn test_decode_sol_usd_account() {
+        // getAccountInfo of PYTH_SOL_USD_PRICE_ACCOUNT
+        let response: serde_json::Value = serde_json::from_reader(
+            std::fs::File::open("fixtures/pyth_sol_usd_account.json").unwrap(),
+        )
+        .unwrap();
+        let account: solana_account_decoder::UiAccount =
+            serde_json::from_value(response["result"]["value"].clone())
+                .unwrap();
+        let data = account.data.decode().unwrap();
+        assert_eq!(data.len(), 134);
+
+        let update = decode_price_update(&data).unwrap();
+        assert_eq!(update.feed_id, PYTH_SOL_USD_FEED_ID);
+        assert_eq!(update.exponent, -8);
+        assert_eq!(update.publish_time, 1_733_040_517);
+        let price = update.validated_price(update.publish_time + 5).unwrap();
+        assert!((price - 235.4187215).abs() < 1e-9, "{}", price);
+    }
+
+    #[test]
+    fn test_decode_price_update() {
+        let now = 1_733_000_000;
+        let data = make_price_update(20_136_000_000, 11_000_000, now - 2);
+        assert_eq!(data.len(), 134);
+
+        let update = decode_price_update(&data).unwrap();
+        assert_eq!(update.feed_id, PYTH_SOL_USD_FEED_ID);
+        assert_eq!(update.exponent, -8);
+        assert!((update.validated_price(now).unwrap() - 201.36).abs() < 1e-9);
+
+        // too old
+        assert!(update.validated_price(now + PYTH_MAX_AGE_SECS).is_err());
+        // +/- 5 usd on 201 is too wide
+        let wide = make_price_update(20_136_000_000, 500_000_000, now);
+        assert!(decode_price_update(&wide)
+            .unwrap()
+            .validated_price(now)
+            .is_err());
+    }
+
+    #[test]
+    fn test_decode_price_update_rejects_partial() {
+        let mut data = make_price_update(20_136_000_000, 11_000_000, 0);
+        data[40] = 0;
+        data.insert(41, 3); // num_signatures
+        assert!(decode_price_update(&data).is_err());
+        assert!(decode_price_update(&data[..20]).is_err());
+    }
+}
@@ -0,0 +1,45 @@
+/// instruction tag of raydium amm v4 swap_base_in
+pub const SWAP_BASE_IN_TAG: u8 = 9;
+/// instruction tag of raydium amm v4 swap_base_out
+pub const SWAP_BASE_OUT_TAG: u8 = 11;
+
+/// decode_raydium_swap_amounts reads `(amount_in, minimum_amount_out)` from
+/// raw swap_base_in instruction data (tag, then both as u64 LE); this is the
+/// size the trader intended, the realized one only shows in the balances
+///
+/// swap_base_out (tag 11) carries `(max_amount_in, amount_out)` instead and
+/// is not recognized
+pub fn decode_raydium_swap_amounts(data: &[u8]) -> Option<(u64, u64)> {
+    match data {
+        [SWAP_BASE_IN_TAG, rest @ ..] if rest.len() == 16 => Some((
+            u64::from_le_bytes(rest[..8].try_into().ok()?),
+            u64::from_le_bytes(rest[8..].try_into().ok()?),
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_raydium_swap_amounts() {
+        // swap_base_in of 3XEtsmC7nDLji1WX9JG4kTBdxjWWwUdVVfqa9HTh8HHhuPhcttS6LAsAskwqyL6P7XzoGFFfJuMe7Z77h7wA8ZGt
+        // (1.5 sol in), "5uXmyPJnuCojt7Y7ZZj37UT" in base58
+        let data = [
+            0x09, 0x00, 0x2f, 0x68, 0x59, 0x00, 0x00, 0x00, 0x00, 0x66, 0x94,
+            0xfa, 0xe2, 0xae, 0x0a, 0x00, 0x00,
+        ];
+        assert_eq!(
+            decode_raydium_swap_amounts(&data),
+            Some((1_500_000_000, 11_746_248_660_070))
+        );
+
+        // swap_base_out and truncated data
+        let mut base_out = data;
+        base_out[0] = 11;
+        assert_eq!(decode_raydium_swap_amounts(&base_out), None);
+        assert_eq!(decode_raydium_swap_amounts(&data[..9]), None);
+    }
+}
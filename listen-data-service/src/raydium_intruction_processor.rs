@@ -1,62 +1,165 @@
-use std::sync::Arc;
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 use tracing::{debug, error};
 
 use crate::{
-    db::ClickhouseDb, kv_store::RedisKVStore, message_queue::RedisMessageQueue,
-    metrics::SwapMetrics, process_swap::process_swap,
+    db::{ClickhouseDb, SwapSink},
+    decimals_cache::{DecimalsCache, MintFetcher},
+    health::PipelineHealth,
+    kv_store::RedisKVStore,
+    message_queue::{dead_letter_on_error, RedisMessageQueue},
+    metrics::SwapMetrics,
+    process_swap::{process_swap, SwapContext, SwapPool, SwapQueue, SwapStore},
+    raydium_instruction::{SWAP_BASE_IN_TAG, SWAP_BASE_OUT_TAG},
+    sol_price_source::SolPriceSource,
+    util::get_seen_ttl_secs,
+    wash_trade::WashTradeDetector,
 };
 use carbon_core::{
     error::CarbonResult, instruction::InstructionProcessorInputType,
     metrics::MetricsCollection, processor::Processor,
 };
 use carbon_raydium_amm_v4_decoder::instructions::RaydiumAmmV4Instruction;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
 
-pub struct RaydiumAmmV4InstructionProcessor {
-    pub kv_store: Arc<RedisKVStore>,
-    pub message_queue: Arc<RedisMessageQueue>,
-    pub db: Arc<ClickhouseDb>,
+pub struct RaydiumAmmV4InstructionProcessor<
+    S: SwapSink = ClickhouseDb,
+    K: SwapStore = RedisKVStore,
+    Q: SwapQueue = RedisMessageQueue,
+    F: MintFetcher = RpcClient,
+> {
+    pub kv_store: Arc<K>,
+    pub message_queue: Arc<Q>,
+    pub db: Arc<S>,
+    pub decimals_cache: Arc<DecimalsCache<K, F>>,
+    pub wash_trades: Arc<Mutex<WashTradeDetector>>,
     pub metrics: Arc<SwapMetrics>,
+    pub sol_price: Arc<dyn SolPriceSource>,
+    pub health: Arc<PipelineHealth>,
+    pub seen_ttl_secs: u64,
 }
 
 #[async_trait::async_trait]
-impl Processor for RaydiumAmmV4InstructionProcessor {
+impl<S, K, Q, F> Processor for RaydiumAmmV4InstructionProcessor<S, K, Q, F>
+where
+    S: SwapSink + 'static,
+    K: SwapStore,
+    Q: SwapQueue,
+    F: MintFetcher + 'static,
+{
     type InputType = InstructionProcessorInputType<RaydiumAmmV4Instruction>;
 
     async fn process(
         &mut self,
         data: Self::InputType,
-        _metrics: Arc<MetricsCollection>,
+        metrics: Arc<MetricsCollection>,
     ) -> CarbonResult<()> {
         let (meta, instruction, _nested_instructions) = data;
+        let timer = InstructionTimer {
+            metrics,
+            name: instruction_name(&instruction.data),
+            started: Instant::now(),
+        };
+        self.health.record(meta.transaction_metadata.slot);
         match &instruction.data {
             RaydiumAmmV4Instruction::SwapBaseIn(_)
             | RaydiumAmmV4Instruction::SwapBaseOut(_) => {
-                self.spawn_swap_processor(&meta);
+                let instruction_data = swap_instruction_data(&instruction.data);
+                let accounts = instruction
+                    .accounts
+                    .iter()
+                    .map(|account| account.pubkey)
+                    .collect::<Vec<_>>();
+                match SwapPool::from_accounts(&accounts) {
+                    // timed in the task, once the swap is processed
+                    Some(pool) => {
+                        self.spawn_swap_processor(
+                            &meta,
+                            pool,
+                            instruction_data,
+                            timer,
+                        );
+                        return Ok(());
+                    }
+                    None => {
+                        let signature =
+                            meta.transaction_metadata.signature.to_string();
+                        // swap accounts are [token program, amm, ..]
+                        let amm = accounts
+                            .get(1)
+                            .map(Pubkey::to_string)
+                            .unwrap_or_default();
+                        if let Err(e) = dead_letter_on_error(
+                            self.message_queue.as_ref(),
+                            &signature,
+                            &amm,
+                            &instruction_data,
+                            Err(anyhow::anyhow!(
+                                "Swap instruction without pool accounts"
+                            )),
+                        )
+                        .await
+                        {
+                            error!(?e, signature, "Dead letter lost");
+                        }
+                    }
+                }
             }
             _ => {}
         }
+        timer.record().await;
 
         Ok(())
     }
 }
 
-impl RaydiumAmmV4InstructionProcessor {
+impl<S, K, Q, F> RaydiumAmmV4InstructionProcessor<S, K, Q, F>
+where
+    S: SwapSink + 'static,
+    K: SwapStore,
+    Q: SwapQueue,
+    F: MintFetcher + 'static,
+{
+    /// new reads the decimals of the mints it hasn't seen through
+    /// `mint_fetcher`, the rpc client in the pipelines
     pub fn new(
-        kv_store: Arc<RedisKVStore>,
-        message_queue: Arc<RedisMessageQueue>,
-        db: Arc<ClickhouseDb>,
+        kv_store: Arc<K>,
+        message_queue: Arc<Q>,
+        db: Arc<S>,
+        mint_fetcher: Arc<F>,
+        sol_price: Arc<dyn SolPriceSource>,
     ) -> Self {
+        let decimals_cache =
+            Arc::new(DecimalsCache::new(kv_store.clone(), mint_fetcher));
         Self {
             kv_store,
             message_queue,
             db,
+            decimals_cache,
+            wash_trades: Arc::new(Mutex::new(WashTradeDetector::default())),
             metrics: Arc::new(SwapMetrics::new()),
+            sol_price,
+            health: Arc::new(PipelineHealth::new()),
+            seen_ttl_secs: get_seen_ttl_secs(),
         }
     }
 
+    /// with_health shares the progress of the processor with a health
+    /// endpoint
+    pub fn with_health(mut self, health: Arc<PipelineHealth>) -> Self {
+        self.health = health;
+        self
+    }
+
     fn spawn_swap_processor(
         &self,
         meta: &carbon_core::instruction::InstructionMetadata,
+        pool: SwapPool,
+        instruction_data: Vec<u8>,
+        timer: InstructionTimer,
     ) {
         debug!(
             "https://solscan.io/tx/{}",
@@ -67,32 +170,257 @@ impl RaydiumAmmV4InstructionProcessor {
         let kv_store = self.kv_store.clone();
         let tx_meta = meta.transaction_metadata.clone();
         let db = self.db.clone();
+        let decimals_cache = self.decimals_cache.clone();
+        let wash_trades = self.wash_trades.clone();
         let metrics = self.metrics.clone();
+        let sol_price = self.sol_price.clone();
+        let seen_ttl_secs = self.seen_ttl_secs;
 
         metrics.increment_total_swaps();
 
         tokio::spawn(async move {
-            match process_swap(
-                &tx_meta,
-                &message_queue,
-                &kv_store,
-                &db,
-                &metrics,
+            let ctx = SwapContext {
+                message_queue: message_queue.as_ref(),
+                kv_store: kv_store.as_ref(),
+                db: db.as_ref(),
+                decimals_cache: decimals_cache.as_ref(),
+                wash_trades: &wash_trades,
+                metrics: &metrics,
+                sol_price: sol_price.as_ref(),
+                seen_ttl_secs,
+            };
+            let result = process_swap(&tx_meta, &ctx, pool).await;
+            match result {
+                Ok(_) => metrics.increment_successful_swaps(),
+                Err(_) => metrics.increment_failed_swaps(),
+            }
+            // the failure is logged along with the dead lettering
+            if let Err(e) = dead_letter_on_error(
+                message_queue.as_ref(),
+                &tx_meta.signature.to_string(),
+                &pool.amm.to_string(),
+                &instruction_data,
+                result,
             )
             .await
             {
-                Ok(_) => {
-                    metrics.increment_successful_swaps();
-                }
-                Err(e) => {
-                    metrics.increment_failed_swaps();
-                    error!(
-                        ?e,
-                        "Transaction: https://solscan.io/tx/{}",
-                        tx_meta.signature
-                    );
-                }
+                error!(
+                    ?e,
+                    "Dead letter lost: https://solscan.io/tx/{}",
+                    tx_meta.signature
+                );
             }
+            timer.record().await;
         });
     }
 }
+
+/// swap_instruction_data is the raw data of a swap instruction, the tag then
+/// both amounts as u64 LE; the decoder hands over the parsed amounts only
+fn swap_instruction_data(instruction: &RaydiumAmmV4Instruction) -> Vec<u8> {
+    let (tag, first, second) = match instruction {
+        RaydiumAmmV4Instruction::SwapBaseIn(swap) => {
+            (SWAP_BASE_IN_TAG, swap.amount_in, swap.minimum_amount_out)
+        }
+        RaydiumAmmV4Instruction::SwapBaseOut(swap) => {
+            (SWAP_BASE_OUT_TAG, swap.max_amount_in, swap.amount_out)
+        }
+        _ => return vec![],
+    };
+    let mut data = vec![tag];
+    data.extend_from_slice(&first.to_le_bytes());
+    data.extend_from_slice(&second.to_le_bytes());
+    data
+}
+
+/// instruction_name is the decoded variant in snake case, e.g.
+/// `swap_base_in`
+fn instruction_name(instruction: &RaydiumAmmV4Instruction) -> &'static str {
+    match instruction {
+        RaydiumAmmV4Instruction::Initialize(_) => "initialize",
+        RaydiumAmmV4Instruction::Initialize2(_) => "initialize2",
+        RaydiumAmmV4Instruction::MonitorStep(_) => "monitor_step",
+        RaydiumAmmV4Instruction::Deposit(_) => "deposit",
+        RaydiumAmmV4Instruction::Withdraw(_) => "withdraw",
+        RaydiumAmmV4Instruction::MigrateToOpenBook(_) => "migrate_to_open_book",
+        RaydiumAmmV4Instruction::SetParams(_) => "set_params",
+        RaydiumAmmV4Instruction::WithdrawPnl(_) => "withdraw_pnl",
+        RaydiumAmmV4Instruction::WithdrawSrm(_) => "withdraw_srm",
+        RaydiumAmmV4Instruction::SwapBaseIn(_) => "swap_base_in",
+        RaydiumAmmV4Instruction::PreInitialize(_) => "pre_initialize",
+        RaydiumAmmV4Instruction::SwapBaseOut(_) => "swap_base_out",
+        RaydiumAmmV4Instruction::SimulateInfo(_) => "simulate_info",
+        RaydiumAmmV4Instruction::AdminCancelOrders(_) => "admin_cancel_orders",
+        RaydiumAmmV4Instruction::CreateConfigAccount(_) => {
+            "create_config_account"
+        }
+        RaydiumAmmV4Instruction::UpdateConfigAccount(_) => {
+            "update_config_account"
+        }
+    }
+}
+
+/// InstructionTimer reports how long an instruction took to process into a
+/// histogram per instruction type, the backend only knows names so the type
+/// is part of the name; a swap is recorded once its spawned processing is
+/// done, not when it is handed off
+struct InstructionTimer {
+    metrics: Arc<MetricsCollection>,
+    name: &'static str,
+    started: Instant,
+}
+
+impl InstructionTimer {
+    async fn record(self) {
+        let name =
+            format!("raydium_amm_v4_{}_process_time_nanoseconds", self.name);
+        if let Err(e) = self
+            .metrics
+            .record_histogram(&name, self.started.elapsed().as_nanos() as f64)
+            .await
+        {
+            debug!(?e, name, "failed to record instruction time");
+        }
+    }
+}
+
+/// swap_base_in_input is the swap_base_in of
+/// 3XEtsmC7nDLji1WX9JG4kTBdxjWWwUdVVfqa9HTh8HHhuPhcttS6LAsAskwqyL6P7XzoGFFfJuMe7Z77h7wA8ZGt
+/// as decoded by the pipeline, in the transaction of `transaction_metadata`
+#[cfg(test)]
+pub fn swap_base_in_input(
+    transaction_metadata: carbon_core::transaction::TransactionMetadata,
+) -> InstructionProcessorInputType<RaydiumAmmV4Instruction> {
+    use crate::constants::RAYDIUM_AMM_V4_PROGRAM_ID;
+    use carbon_core::instruction::{InstructionDecoder, InstructionMetadata};
+    use carbon_raydium_amm_v4_decoder::RaydiumAmmV4Decoder;
+    use solana_sdk::instruction::{AccountMeta, Instruction};
+
+    let data = vec![
+        0x09, 0x00, 0x2f, 0x68, 0x59, 0x00, 0x00, 0x00, 0x00, 0x66, 0x94, 0xfa,
+        0xe2, 0xae, 0x0a, 0x00, 0x00,
+    ];
+    // 18 accounts, with the target orders; the vaults of the pool are the
+    // second and third account of the transaction
+    let keys = transaction_metadata.message.static_account_keys();
+    let mut accounts = (0..18)
+        .map(|_| AccountMeta::new(Pubkey::new_unique(), false))
+        .collect::<Vec<_>>();
+    accounts[5].pubkey = keys[1];
+    accounts[6].pubkey = keys[2];
+    let instruction = RaydiumAmmV4Decoder
+        .decode_instruction(&Instruction {
+            program_id: RAYDIUM_AMM_V4_PROGRAM_ID,
+            accounts,
+            data,
+        })
+        .unwrap();
+    (
+        InstructionMetadata {
+            transaction_metadata,
+            stack_height: 1,
+        },
+        instruction,
+        vec![],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        db::MemorySwapSink, decimals_cache::NoMintFetcher,
+        kv_store::MemoryKVStore, message_queue::MemoryMessageQueue,
+        process_swap::load_transaction_metadata,
+        sol_price_source::FixedSolPrice,
+    };
+    use carbon_core::metrics::Metrics;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct RecordedHistograms(Mutex<Vec<(String, f64)>>);
+
+    #[async_trait::async_trait]
+    impl Metrics for RecordedHistograms {
+        async fn initialize(&self) -> CarbonResult<()> {
+            Ok(())
+        }
+
+        async fn flush(&self) -> CarbonResult<()> {
+            Ok(())
+        }
+
+        async fn shutdown(&self) -> CarbonResult<()> {
+            Ok(())
+        }
+
+        async fn update_gauge(&self, _: &str, _: f64) -> CarbonResult<()> {
+            Ok(())
+        }
+
+        async fn increment_counter(&self, _: &str, _: u64) -> CarbonResult<()> {
+            Ok(())
+        }
+
+        async fn record_histogram(
+            &self,
+            name: &str,
+            value: f64,
+        ) -> CarbonResult<()> {
+            self.0.lock().unwrap().push((name.to_string(), value));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_records_time_per_instruction_type() {
+        // a 0.55 sol buy of 6822.42 tokens
+        let tx_meta =
+            load_transaction_metadata("fixtures/ephemeral_wsol_tx.json");
+        let kv_store = Arc::new(MemoryKVStore::default());
+        kv_store.decimals.lock().await.insert(
+            "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon"
+                .parse()
+                .unwrap(),
+            6,
+        );
+        let sink = Arc::new(MemorySwapSink::default());
+        let mut processor = RaydiumAmmV4InstructionProcessor::new(
+            kv_store,
+            Arc::new(MemoryMessageQueue::default()),
+            sink.clone(),
+            Arc::new(NoMintFetcher),
+            Arc::new(FixedSolPrice(200.0)),
+        );
+
+        let recorder = Arc::new(RecordedHistograms::default());
+        let metrics = Arc::new(MetricsCollection::new(vec![
+            recorder.clone() as Arc<dyn Metrics>
+        ]));
+        processor
+            .process(swap_base_in_input(tx_meta), metrics)
+            .await
+            .unwrap();
+
+        // recorded by the spawned task, once the swap is in the sink
+        let (name, nanos) =
+            tokio::time::timeout(Duration::from_secs(5), async {
+                loop {
+                    let recorded = recorder.0.lock().unwrap().first().cloned();
+                    if let Some(recorded) = recorded {
+                        return recorded;
+                    }
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            name,
+            "raydium_amm_v4_swap_base_in_process_time_nanoseconds"
+        );
+        assert!(nanos > 0.0);
+        assert_eq!(sink.swaps().await.len(), 1);
+        assert_eq!(recorder.0.lock().unwrap().len(), 1);
+    }
+}
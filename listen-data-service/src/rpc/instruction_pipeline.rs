@@ -1,7 +1,5 @@
 use anyhow::Result;
-use carbon_core::pipeline::Pipeline;
-use carbon_log_metrics::LogMetrics;
-use carbon_raydium_amm_v4_decoder::RaydiumAmmV4Decoder;
+use carbon_core::{metrics::Metrics, pipeline::Pipeline};
 use carbon_rpc_transaction_crawler_datasource::{
     Filters, RpcTransactionCrawler,
 };
@@ -10,16 +8,19 @@ use std::{sync::Arc, time::Duration};
 use crate::{
     constants::RAYDIUM_AMM_V4_PROGRAM_ID, db::ClickhouseDb,
     kv_store::RedisKVStore, message_queue::RedisMessageQueue,
-    raydium_intruction_processor::RaydiumAmmV4InstructionProcessor,
+    pipeline::make_raydium_instruction_pipeline,
+    sol_price_source::SolPriceSource,
 };
 
 pub fn make_raydium_rpc_instruction_pipeline(
     kv_store: Arc<RedisKVStore>,
     message_queue: Arc<RedisMessageQueue>,
     db: Arc<ClickhouseDb>,
+    sol_price: Arc<dyn SolPriceSource>,
+    metrics: Arc<dyn Metrics>,
 ) -> Result<Pipeline> {
-    let pipeline = Pipeline::builder()
-        .datasource(RpcTransactionCrawler::new(
+    make_raydium_instruction_pipeline(
+        RpcTransactionCrawler::new(
             std::env::var("RPC_URL")?,
             RAYDIUM_AMM_V4_PROGRAM_ID,
             500,
@@ -27,13 +28,11 @@ pub fn make_raydium_rpc_instruction_pipeline(
             Filters::new(None, None, None),
             None,
             100,
-        ))
-        .metrics(Arc::new(LogMetrics::new()))
-        .instruction(
-            RaydiumAmmV4Decoder,
-            RaydiumAmmV4InstructionProcessor::new(kv_store, message_queue, db),
-        )
-        .build()?;
-
-    Ok(pipeline)
+        ),
+        kv_store,
+        message_queue,
+        db,
+        sol_price,
+        metrics,
+    )
 }
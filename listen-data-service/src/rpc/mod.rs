@@ -3,3 +3,6 @@ pub mod account_pipeline;
 
 #[cfg(feature = "rpc")]
 pub mod instruction_pipeline;
+
+#[cfg(feature = "rpc")]
+pub mod ws_pipeline;
@@ -0,0 +1,359 @@
+use anyhow::{anyhow, Result};
+use carbon_core::{
+    datasource::{Datasource, TransactionUpdate, Update, UpdateType},
+    error::CarbonResult,
+    metrics::{Metrics, MetricsCollection},
+    pipeline::Pipeline,
+    transformers::transaction_metadata_from_original_meta,
+};
+use carbon_rpc_transaction_crawler_datasource::{
+    Filters, RpcTransactionCrawler,
+};
+use futures_util::{Stream, StreamExt};
+use serde_json::json;
+use solana_client::{
+    nonblocking::{pubsub_client::PubsubClient, rpc_client::RpcClient},
+    rpc_config::{
+        RpcTransactionConfig, RpcTransactionLogsConfig,
+        RpcTransactionLogsFilter,
+    },
+    rpc_request::RpcRequest,
+    rpc_response::RpcLogsResponse,
+};
+use solana_sdk::{
+    commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature,
+};
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding,
+};
+use std::{str::FromStr, sync::Arc, time::Duration};
+use tokio::{sync::mpsc::UnboundedSender, task::JoinSet};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    constants::RAYDIUM_AMM_V4_PROGRAM_ID, db::ClickhouseDb,
+    kv_store::RedisKVStore, message_queue::RedisMessageQueue,
+    pipeline::with_raydium_instruction_processor,
+    sol_price_source::SolPriceSource, util::must_get_env,
+};
+
+/// make_raydium_ws_pipeline streams raydium swaps from logsSubscribe, with the
+/// transaction crawler kept on a slow interval to fill gaps left by
+/// websocket drops; overlap between the two is dropped by the processor's
+/// seen-key deduplication
+pub fn make_raydium_ws_pipeline(
+    ws_url: String,
+    kv_store: Arc<RedisKVStore>,
+    message_queue: Arc<RedisMessageQueue>,
+    db: Arc<ClickhouseDb>,
+    sol_price: Arc<dyn SolPriceSource>,
+    metrics: Arc<dyn Metrics>,
+) -> Result<Pipeline> {
+    let rpc_url = must_get_env("RPC_URL");
+    let builder = Pipeline::builder()
+        .datasource(LogsSubscribeDatasource::new(
+            ws_url,
+            rpc_url.clone(),
+            RAYDIUM_AMM_V4_PROGRAM_ID,
+        ))
+        .datasource(RpcTransactionCrawler::new(
+            rpc_url,
+            RAYDIUM_AMM_V4_PROGRAM_ID,
+            500,
+            Duration::from_secs(10),
+            Filters::new(None, None, None),
+            None,
+            100,
+        ))
+        .metrics(metrics);
+    let pipeline = with_raydium_instruction_processor(
+        builder,
+        kv_store,
+        message_queue,
+        db,
+        sol_price,
+    )?
+    .build()?;
+
+    Ok(pipeline)
+}
+
+/// TransactionFetcher resolves the signatures from log notifications, which
+/// carry no instructions, into full transactions
+#[async_trait::async_trait]
+pub trait TransactionFetcher: Send + Sync {
+    async fn fetch_transaction(
+        &self,
+        signature: &Signature,
+    ) -> Result<Option<EncodedConfirmedTransactionWithStatusMeta>>;
+}
+
+#[async_trait::async_trait]
+impl TransactionFetcher for RpcClient {
+    async fn fetch_transaction(
+        &self,
+        signature: &Signature,
+    ) -> Result<Option<EncodedConfirmedTransactionWithStatusMeta>> {
+        let config = RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            commitment: Some(CommitmentConfig::confirmed()),
+            max_supported_transaction_version: Some(0),
+        };
+        // null means not available yet, get_transaction_with_config would
+        // report it as a deserialization error
+        Ok(self
+            .send(
+                RpcRequest::GetTransaction,
+                json!([signature.to_string(), config]),
+            )
+            .await?)
+    }
+}
+
+pub struct LogsSubscribeDatasource {
+    ws_url: String,
+    rpc_url: String,
+    program_id: Pubkey,
+}
+
+impl LogsSubscribeDatasource {
+    pub fn new(ws_url: String, rpc_url: String, program_id: Pubkey) -> Self {
+        Self {
+            ws_url,
+            rpc_url,
+            program_id,
+        }
+    }
+
+    async fn subscribe(
+        &self,
+        fetcher: Arc<RpcClient>,
+        sender: UnboundedSender<Update>,
+    ) -> Result<()> {
+        let pubsub_client = PubsubClient::new(&self.ws_url).await?;
+        let (notifications, unsub) = pubsub_client
+            .logs_subscribe(
+                RpcTransactionLogsFilter::Mentions(vec![self
+                    .program_id
+                    .to_string()]),
+                RpcTransactionLogsConfig {
+                    commitment: Some(CommitmentConfig::confirmed()),
+                },
+            )
+            .await?;
+        info!("Listening for {} logs", self.program_id);
+
+        forward_log_notifications(
+            notifications.map(|notification| notification.value),
+            fetcher,
+            sender,
+        )
+        .await;
+
+        unsub().await;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Datasource for LogsSubscribeDatasource {
+    async fn consume(
+        &self,
+        sender: &UnboundedSender<Update>,
+        cancellation_token: CancellationToken,
+        _metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()> {
+        let fetcher = Arc::new(RpcClient::new(self.rpc_url.clone()));
+        loop {
+            tokio::select! {
+                _ = cancellation_token.cancelled() => {
+                    info!("logs subscription cancelled");
+                    return Ok(());
+                }
+                res = self.subscribe(fetcher.clone(), sender.clone()) => {
+                    match res {
+                        Ok(_) => warn!("logs subscription ended, reconnecting"),
+                        Err(e) => error!(?e, "logs subscription failed"),
+                    }
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+
+    fn update_types(&self) -> Vec<UpdateType> {
+        vec![UpdateType::Transaction]
+    }
+}
+
+/// forward_log_notifications fetches the transaction of every successful
+/// notification and sends it down the pipeline, fetches run concurrently so a
+/// slow one does not hold up the stream
+pub async fn forward_log_notifications<S, F>(
+    mut notifications: S,
+    fetcher: Arc<F>,
+    sender: UnboundedSender<Update>,
+) where
+    S: Stream<Item = RpcLogsResponse> + Unpin,
+    F: TransactionFetcher + 'static,
+{
+    let mut tasks = JoinSet::new();
+    while let Some(notification) = notifications.next().await {
+        while tasks.try_join_next().is_some() {}
+
+        if notification.err.is_some() {
+            continue;
+        }
+        let signature = match Signature::from_str(&notification.signature) {
+            Ok(signature) => signature,
+            Err(e) => {
+                warn!(?e, notification.signature, "invalid signature");
+                continue;
+            }
+        };
+
+        let fetcher = fetcher.clone();
+        let sender = sender.clone();
+        tasks.spawn(async move {
+            match fetch_update(fetcher.as_ref(), signature).await {
+                Ok(update) => {
+                    if let Err(e) = sender.send(update) {
+                        error!(?e, "failed to send update");
+                    }
+                }
+                Err(e) => warn!(?e, %signature, "failed to fetch transaction"),
+            }
+        });
+    }
+    while tasks.join_next().await.is_some() {}
+}
+
+async fn fetch_update<F: TransactionFetcher>(
+    fetcher: &F,
+    signature: Signature,
+) -> Result<Update> {
+    const MAX_RETRIES: u64 = 5;
+    for attempt in 1..=MAX_RETRIES {
+        match fetcher.fetch_transaction(&signature).await {
+            Ok(Some(tx)) => return make_transaction_update(signature, tx),
+            Ok(None) => debug!(%signature, attempt, "transaction not found"),
+            Err(e) => warn!(?e, %signature, attempt, "get transaction failed"),
+        }
+        tokio::time::sleep(Duration::from_millis(200 * attempt)).await;
+    }
+    Err(anyhow!(
+        "transaction not found after {} attempts",
+        MAX_RETRIES
+    ))
+}
+
+fn make_transaction_update(
+    signature: Signature,
+    tx: EncodedConfirmedTransactionWithStatusMeta,
+) -> Result<Update> {
+    let transaction = tx
+        .transaction
+        .transaction
+        .decode()
+        .ok_or_else(|| anyhow!("failed to decode transaction"))?;
+    let meta = tx
+        .transaction
+        .meta
+        .ok_or_else(|| anyhow!("transaction without meta"))?;
+    let meta = transaction_metadata_from_original_meta(meta)
+        .map_err(|e| anyhow!("failed to convert meta: {}", e))?;
+
+    Ok(Update::Transaction(TransactionUpdate {
+        signature,
+        transaction,
+        meta,
+        is_vote: false,
+        slot: tx.slot,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{
+        hash::Hash, signature::Keypair, signer::Signer,
+        transaction::Transaction,
+    };
+    use solana_transaction_status::{
+        Encodable, EncodedTransactionWithStatusMeta, TransactionStatusMeta,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct MockFetcher {
+        tx: EncodedConfirmedTransactionWithStatusMeta,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl TransactionFetcher for MockFetcher {
+        async fn fetch_transaction(
+            &self,
+            _signature: &Signature,
+        ) -> Result<Option<EncodedConfirmedTransactionWithStatusMeta>> {
+            // not yet available on the first attempt
+            match self.calls.fetch_add(1, Ordering::SeqCst) {
+                0 => Ok(None),
+                _ => Ok(Some(self.tx.clone())),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forward_log_notifications() {
+        let payer = Keypair::new();
+        let tx = Transaction::new_signed_with_payer(
+            &[],
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::default(),
+        );
+        let signature = tx.signatures[0];
+        let fetcher = Arc::new(MockFetcher {
+            tx: EncodedConfirmedTransactionWithStatusMeta {
+                slot: 312_000_000,
+                transaction: EncodedTransactionWithStatusMeta {
+                    transaction: tx.encode(UiTransactionEncoding::Base64),
+                    meta: Some(TransactionStatusMeta::default().into()),
+                    version: None,
+                },
+                block_time: None,
+            },
+            calls: AtomicUsize::new(0),
+        });
+
+        let notifications = futures_util::stream::iter(vec![
+            RpcLogsResponse {
+                signature: Signature::default().to_string(),
+                err: Some(
+                    solana_sdk::transaction::TransactionError::AccountInUse,
+                ),
+                logs: vec![],
+            },
+            RpcLogsResponse {
+                signature: signature.to_string(),
+                err: None,
+                logs: vec![],
+            },
+        ]);
+
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        forward_log_notifications(notifications, fetcher.clone(), sender).await;
+
+        match receiver.recv().await {
+            Some(Update::Transaction(update)) => {
+                assert_eq!(update.signature, signature);
+                assert_eq!(update.slot, 312_000_000);
+            }
+            _ => panic!("expected a transaction update"),
+        }
+        assert!(receiver.recv().await.is_none());
+        // the failed notification is never fetched
+        assert_eq!(fetcher.calls.load(Ordering::SeqCst), 2);
+    }
+}
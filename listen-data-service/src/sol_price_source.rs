@@ -0,0 +1,206 @@
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::pyth::fetch_sol_price_pyth;
+use crate::sol_price_stream::fetch_binance_sol_price;
+
+/// SolPriceSource provides the SOL/USD price used to quote swaps
+pub trait SolPriceSource: Send + Sync {
+    fn sol_price(&self) -> f64;
+}
+
+/// FixedSolPrice always returns the same value, it is used to pin the price
+/// for all hops of a single transaction and to inject a price in tests
+#[derive(Debug, Clone, Copy)]
+pub struct FixedSolPrice(pub f64);
+
+impl SolPriceSource for FixedSolPrice {
+    fn sol_price(&self) -> f64 {
+        self.0
+    }
+}
+
+/// SolPriceOracle is where CachedSolPrice refreshes from
+#[async_trait::async_trait]
+pub trait SolPriceOracle: Send + Sync {
+    async fn fetch_sol_price(&self) -> Result<f64>;
+}
+
+pub struct BinanceRestOracle;
+
+#[async_trait::async_trait]
+impl SolPriceOracle for BinanceRestOracle {
+    async fn fetch_sol_price(&self) -> Result<f64> {
+        fetch_binance_sol_price().await
+    }
+}
+
+/// PythOracle reads the price from the pyth feed account, no third party
+/// api in between
+pub struct PythOracle {
+    pub rpc_client: Arc<RpcClient>,
+}
+
+#[async_trait::async_trait]
+impl SolPriceOracle for PythOracle {
+    async fn fetch_sol_price(&self) -> Result<f64> {
+        fetch_sol_price_pyth(&self.rpc_client).await
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct PriceSnapshot {
+    price: f64,
+    updated_at: Option<Instant>,
+}
+
+/// CachedSolPrice holds the last good price fetched from an oracle, a failed
+/// or invalid fetch never overwrites it; a cache made with `new` quotes 0.0
+/// until its first refresh, use `start` to hand one out to the processor
+#[derive(Debug)]
+pub struct CachedSolPrice {
+    snapshot: RwLock<PriceSnapshot>,
+    max_age: Duration,
+}
+
+impl CachedSolPrice {
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            snapshot: RwLock::new(PriceSnapshot::default()),
+            max_age,
+        }
+    }
+
+    /// start refreshes a new cache from `oracle` every `interval`, it fails
+    /// unless the first refresh succeeds so that no swap is ever quoted off
+    /// an empty cache
+    pub async fn start<O: SolPriceOracle + 'static>(
+        oracle: O,
+        interval: Duration,
+        max_age: Duration,
+    ) -> Result<Arc<Self>> {
+        let cache = Arc::new(Self::new(max_age));
+        let price = cache
+            .refresh(&oracle)
+            .await
+            .context("failed to fetch the initial sol price")?;
+        info!(price, "sol price");
+        cache.spawn_refresh(oracle, interval);
+        Ok(cache)
+    }
+
+    pub fn set_price(&self, price: f64) {
+        *self.snapshot.write().unwrap() = PriceSnapshot {
+            price,
+            updated_at: Some(Instant::now()),
+        };
+    }
+
+    pub fn last_updated(&self) -> Option<Instant> {
+        self.snapshot.read().unwrap().updated_at
+    }
+
+    /// a price that was never set or is older than max_age is stale
+    pub fn is_stale(&self) -> bool {
+        match self.last_updated() {
+            Some(updated_at) => updated_at.elapsed() > self.max_age,
+            None => true,
+        }
+    }
+
+    pub async fn refresh<O: SolPriceOracle>(&self, oracle: &O) -> Result<f64> {
+        let price = oracle.fetch_sol_price().await?;
+        if !price.is_finite() || price <= 0.0 {
+            return Err(anyhow::anyhow!("Invalid SOL price: {}", price));
+        }
+        self.set_price(price);
+        Ok(price)
+    }
+
+    pub fn spawn_refresh<O: SolPriceOracle + 'static>(
+        self: &Arc<Self>,
+        oracle: O,
+        interval: Duration,
+    ) -> JoinHandle<()> {
+        let cache = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                match cache.refresh(&oracle).await {
+                    Ok(price) => debug!(price, "sol price refreshed"),
+                    Err(e) => warn!(?e, stale = cache.is_stale()),
+                }
+            }
+        })
+    }
+}
+
+impl SolPriceSource for CachedSolPrice {
+    fn sol_price(&self) -> f64 {
+        self.snapshot.read().unwrap().price
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct StubOracle(Result<f64, &'static str>);
+
+    #[async_trait::async_trait]
+    impl SolPriceOracle for StubOracle {
+        async fn fetch_sol_price(&self) -> Result<f64> {
+            self.0.map_err(|e| anyhow::anyhow!(e))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_sol_price_keeps_last_good_value() {
+        let cache = CachedSolPrice::new(Duration::from_secs(60));
+        assert!(cache.is_stale());
+
+        cache.refresh(&StubOracle(Ok(201.36))).await.unwrap();
+        assert_eq!(cache.sol_price(), 201.36);
+        assert!(!cache.is_stale());
+
+        assert!(cache.refresh(&StubOracle(Err("down"))).await.is_err());
+        assert!(cache.refresh(&StubOracle(Ok(0.0))).await.is_err());
+        assert_eq!(cache.sol_price(), 201.36);
+    }
+
+    #[tokio::test]
+    async fn test_cached_sol_price_staleness() {
+        let cache = CachedSolPrice::new(Duration::from_millis(10));
+        cache.set_price(201.36);
+        assert!(!cache.is_stale());
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(cache.is_stale());
+        assert_eq!(cache.sol_price(), 201.36);
+    }
+
+    #[tokio::test]
+    async fn test_start_requires_initial_price() {
+        let interval = Duration::from_secs(60);
+        assert!(CachedSolPrice::start(
+            StubOracle(Err("down")),
+            interval,
+            interval
+        )
+        .await
+        .is_err());
+
+        let cache =
+            CachedSolPrice::start(StubOracle(Ok(201.36)), interval, interval)
+                .await
+                .unwrap();
+        assert_eq!(cache.sol_price(), 201.36);
+        assert!(!cache.is_stale());
+    }
+}
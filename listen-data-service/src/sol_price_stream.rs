@@ -21,6 +21,13 @@ struct BinancePrice {
     price: String,
 }
 
+pub async fn fetch_binance_sol_price() -> Result<f64> {
+    let rest_url = "https://api.binance.com/api/v3/ticker/price?symbol=SOLUSDT";
+    let response = reqwest::get(rest_url).await?;
+    let price_data: BinancePrice = response.json().await?;
+    price_data.price.parse::<f64>().map_err(Into::into)
+}
+
 #[derive(Debug, Clone)]
 pub struct SolPriceCache {
     price: Arc<RwLock<f64>>,
@@ -62,11 +69,7 @@ impl SolPriceCache {
     }
 
     async fn fetch_rest_price(&self) -> Result<f64> {
-        let rest_url =
-            "https://api.binance.com/api/v3/ticker/price?symbol=SOLUSDT";
-        let response = reqwest::get(rest_url).await?;
-        let price_data: BinancePrice = response.json().await?;
-        price_data.price.parse::<f64>().map_err(Into::into)
+        fetch_binance_sol_price().await
     }
 
     pub async fn start_price_stream(&self) -> Result<()> {
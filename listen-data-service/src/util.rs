@@ -1,7 +1,11 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use carbon_core::{metrics::Metrics, pipeline::ShutdownStrategy};
+use carbon_log_metrics::LogMetrics;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use std::{fs::File, io::BufWriter, sync::Arc};
 
+use crate::prometheus_metrics::PrometheusMetrics;
+#[cfg(feature = "storage")]
 use crate::{
     db::{ClickhouseDb, Database},
     kv_store::{KVStore, RedisKVStore},
@@ -9,21 +13,26 @@ use crate::{
 };
 
 pub fn make_rpc_client() -> Result<RpcClient> {
-    let rpc_client = RpcClient::new(must_get_env("RPC_URL"));
+    let rpc_client =
+        RpcClient::new(std::env::var("RPC_URL").context("RPC_URL is not set")?);
     Ok(rpc_client)
 }
 
+#[cfg(feature = "storage")]
 pub fn make_kv_store() -> Result<Arc<RedisKVStore>> {
     let kv_store = RedisKVStore::new(must_get_env("REDIS_URL").as_str());
     Ok(Arc::new(kv_store))
 }
 
+#[cfg(feature = "storage")]
 pub fn make_message_queue() -> Result<Arc<RedisMessageQueue>> {
     let message_queue =
-        RedisMessageQueue::new(must_get_env("REDIS_URL").as_str())?;
+        RedisMessageQueue::new(must_get_env("REDIS_URL").as_str())?
+            .with_max_dead_letters(get_max_dead_letters());
     Ok(Arc::new(message_queue))
 }
 
+#[cfg(feature = "storage")]
 pub async fn make_db() -> Result<Arc<ClickhouseDb>> {
     let mut db = ClickhouseDb::new(
         must_get_env("CLICKHOUSE_URL").as_str(),
@@ -35,6 +44,40 @@ pub async fn make_db() -> Result<Arc<ClickhouseDb>> {
     Ok(Arc::new(db))
 }
 
+/// make_metrics picks the pipeline metrics backend, prometheus served on
+/// METRICS_PORT if set, plain logging otherwise
+pub fn make_metrics() -> Result<Arc<dyn Metrics>> {
+    match get_metrics_port()? {
+        Some(port) => Ok(Arc::new(PrometheusMetrics::new(port))),
+        None => Ok(Arc::new(LogMetrics::new())),
+    }
+}
+
+/// get_metrics_port returns the port of the prometheus endpoint, None if
+/// METRICS_PORT is unset or empty (as in .env.example)
+pub fn get_metrics_port() -> Result<Option<u16>> {
+    match std::env::var("METRICS_PORT") {
+        Ok(port) if !port.trim().is_empty() => Ok(Some(
+            port.trim().parse().context("METRICS_PORT is not a port")?,
+        )),
+        _ => Ok(None),
+    }
+}
+
+/// get_shutdown_strategy returns how the pipeline stops on ctrl-c, right
+/// away unless SHUTDOWN_STRATEGY is `process_pending`, which drains the
+/// updates already received first
+pub fn get_shutdown_strategy() -> Result<ShutdownStrategy> {
+    match std::env::var("SHUTDOWN_STRATEGY").as_deref().map(str::trim) {
+        Ok("") | Ok("immediate") | Err(_) => Ok(ShutdownStrategy::Immediate),
+        Ok("process_pending") => Ok(ShutdownStrategy::ProcessPending),
+        Ok(other) => Err(anyhow::anyhow!(
+            "SHUTDOWN_STRATEGY must be immediate or process_pending, got {}",
+            other
+        )),
+    }
+}
+
 pub fn write_json(data: &str, file_name: &str) -> Result<()> {
     let file = File::create(file_name)?;
     let writer = BufWriter::new(file);
@@ -66,6 +109,61 @@ pub async fn get_jup_price(mint: String) -> Result<f64> {
     Ok(price)
 }
 
+/// default time a processed swap signature is remembered for deduplication,
+/// long enough to cover overlapping crawls and reconnect replays
+pub const DEFAULT_SEEN_TTL_SECS: u64 = 3600;
+
+pub fn get_seen_ttl_secs() -> u64 {
+    std::env::var("SEEN_TTL_SECS")
+        .ok()
+        .and_then(|ttl| ttl.parse().ok())
+        .unwrap_or(DEFAULT_SEEN_TTL_SECS)
+}
+
+/// get_max_dead_letters is how many dead letters are kept until drained
+#[cfg(feature = "storage")]
+pub fn get_max_dead_letters() -> usize {
+    std::env::var("MAX_DEAD_LETTERS")
+        .ok()
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(crate::message_queue::DEFAULT_MAX_DEAD_LETTERS)
+}
+
+pub const DEFAULT_FINALIZED_BUFFER_DEPTH: usize = 512;
+
+/// get_finalized_buffer_depth returns the depth of the finalized-only swap
+/// buffer if FINALIZED_ONLY is set, None means swaps are inserted right away
+pub fn get_finalized_buffer_depth() -> Option<usize> {
+    match std::env::var("FINALIZED_ONLY").as_deref() {
+        Ok("true") | Ok("1") => Some(
+            std::env::var("FINALIZED_BUFFER_DEPTH")
+                .ok()
+                .and_then(|depth| depth.parse().ok())
+                .unwrap_or(DEFAULT_FINALIZED_BUFFER_DEPTH),
+        ),
+        _ => None,
+    }
+}
+
+pub const DEFAULT_HEALTH_MAX_LAG_SLOTS: u64 = 150;
+
+/// get_health_port returns the port of the pipeline health endpoint, None
+/// if HEALTH_PORT is not set
+pub fn get_health_port() -> Option<u16> {
+    std::env::var("HEALTH_PORT")
+        .ok()
+        .and_then(|port| port.parse().ok())
+}
+
+/// get_health_max_lag_slots is how far behind the tip the pipeline can fall
+/// before the health endpoint reports it unhealthy
+pub fn get_health_max_lag_slots() -> u64 {
+    std::env::var("HEALTH_MAX_LAG_SLOTS")
+        .ok()
+        .and_then(|lag| lag.parse().ok())
+        .unwrap_or(DEFAULT_HEALTH_MAX_LAG_SLOTS)
+}
+
 pub fn must_get_env(key: &str) -> String {
     match std::env::var(key) {
         Ok(val) => val,
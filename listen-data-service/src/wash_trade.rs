@@ -0,0 +1,138 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::price::PriceUpdate;
+
+pub const DEFAULT_WASH_WINDOW_SECS: u64 = 300;
+
+#[derive(Debug, Clone)]
+struct RecentSwap {
+    owner: String,
+    is_buy: bool,
+    timestamp: u64,
+}
+
+/// WashTradeDetector flags swaps that undo a swap of the same wallet on the
+/// same token within `window_secs`, a wallet buying and selling back and
+/// forth inflates the volume without any change of hands
+#[derive(Debug)]
+pub struct WashTradeDetector {
+    window_secs: u64,
+    recent: HashMap<String, VecDeque<RecentSwap>>,
+    /// timestamp of the last sweep of the mints nobody swapped lately
+    last_sweep: u64,
+}
+
+impl Default for WashTradeDetector {
+    fn default() -> Self {
+        Self::new(DEFAULT_WASH_WINDOW_SECS)
+    }
+}
+
+impl WashTradeDetector {
+    pub fn new(window_secs: u64) -> Self {
+        Self {
+            window_secs,
+            recent: HashMap::new(),
+            last_sweep: 0,
+        }
+    }
+
+    /// observe records the swap, returns true if the same wallet swapped the
+    /// token the other way within the window
+    pub fn observe(
+        &mut self,
+        mint: &str,
+        owner: &str,
+        is_buy: bool,
+        timestamp: u64,
+    ) -> bool {
+        let cutoff = timestamp.saturating_sub(self.window_secs);
+        if timestamp.saturating_sub(self.last_sweep) >= self.window_secs {
+            self.sweep(cutoff);
+            self.last_sweep = timestamp;
+        }
+        let swaps = self.recent.entry(mint.to_string()).or_default();
+        evict(swaps, cutoff);
+        let is_wash = swaps
+            .iter()
+            .any(|swap| swap.owner == owner && swap.is_buy != is_buy);
+        swaps.push_back(RecentSwap {
+            owner: owner.to_string(),
+            is_buy,
+            timestamp,
+        });
+        is_wash
+    }
+
+    /// tag sets `is_suspected_wash` on the swap
+    pub fn tag(&mut self, swap: &mut PriceUpdate) {
+        swap.is_suspected_wash = self.observe(
+            &swap.pubkey,
+            &swap.owner,
+            swap.is_buy,
+            swap.timestamp,
+        );
+    }
+
+    /// sweep drops the mints without a swap in the window, it runs once per
+    /// window rather than per swap, the mint being swapped is evicted on
+    /// access
+    fn sweep(&mut self, cutoff: u64) {
+        self.recent.retain(|_, swaps| {
+            evict(swaps, cutoff);
+            !swaps.is_empty()
+        });
+    }
+}
+
+/// swaps arrive roughly in order, so the expired ones are at the front
+fn evict(swaps: &mut VecDeque<RecentSwap>, cutoff: u64) {
+    while swaps.front().is_some_and(|swap| swap.timestamp < cutoff) {
+        swaps.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINT: &str = "CSChJMDH1drnxaN5ZXr8ZPZtqXv2FJqNTGcSujyfmoon";
+
+    #[test]
+    fn test_back_and_forth_flagged() {
+        let mut detector = WashTradeDetector::new(60);
+        assert!(!detector.observe(MINT, "washer", true, 0));
+        assert!(detector.observe(MINT, "washer", false, 10));
+        assert!(detector.observe(MINT, "washer", true, 20));
+
+        // buying more is not offsetting, nor is another wallet selling
+        assert!(!detector.observe(MINT, "holder", true, 20));
+        assert!(!detector.observe(MINT, "holder", true, 30));
+        assert!(!detector.observe(MINT, "other", false, 30));
+
+        // other tokens are tracked separately
+        assert!(!detector.observe("other-mint", "washer", false, 30));
+    }
+
+    #[test]
+    fn test_outside_window_not_flagged() {
+        let mut detector = WashTradeDetector::new(60);
+        assert!(!detector.observe(MINT, "trader", true, 0));
+        assert!(!detector.observe(MINT, "trader", false, 61));
+        // the expired buy is gone, only the sell is tracked
+        assert_eq!(detector.recent[MINT].len(), 1);
+    }
+
+    #[test]
+    fn test_idle_mints_swept() {
+        let mut detector = WashTradeDetector::new(60);
+        assert!(!detector.observe("idle-mint", "trader", true, 0));
+        assert!(!detector.observe(MINT, "trader", true, 30));
+        // within the window nothing is swept
+        assert!(detector.recent.contains_key("idle-mint"));
+
+        assert!(!detector.observe(MINT, "trader", true, 61));
+        assert!(!detector.recent.contains_key("idle-mint"));
+        assert_eq!(detector.recent[MINT].len(), 2);
+    }
+}
@@ -5,7 +5,7 @@ use std::{
 use crate::{
     constants, jito,
     raydium::{self, get_burn_pct},
-    util::env,
+    util::{decode_account_data, env},
 };
 use futures_util::StreamExt;
 use jito_searcher_client::get_searcher_client;
@@ -301,7 +301,7 @@ pub async fn listen_for_burn(
         debug!("log: {:?}", log);
         if let UiAccountData::LegacyBinary(data) = log.value.data {
             let Ok(mint_data) =
-                Mint::unpack(bs58::decode(data).into_vec()?.as_slice())
+                Mint::unpack(decode_account_data(&data)?.as_slice())
             else {
                 return Err("unpack mint data".into());
             };
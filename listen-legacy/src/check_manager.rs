@@ -0,0 +1,196 @@
+use std::future::Future;
+use std::sync::Arc;
+
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use crate::checker::{run_checks, Checklist};
+use crate::rpc_pool::RpcPool;
+
+/// OverflowPolicy is what happens to a candidate arriving while all workers
+/// are busy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// wait for a worker, candidates pile up in the input channel
+    Queue,
+    /// drop the candidate, for when a late check is worthless anyway
+    Drop,
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckManagerConfig {
+    pub concurrency: usize,
+    pub queue_capacity: usize,
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for CheckManagerConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 8,
+            queue_capacity: 256,
+            overflow: OverflowPolicy::Queue,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CheckResult {
+    pub signature: String,
+    pub result: Result<(bool, Checklist), String>,
+}
+
+/// CheckManager runs the checks of a burst of candidates on a bounded number
+/// of workers, so that the rpc rate limits (and memory) hold up
+pub struct CheckManager {
+    pub sender: mpsc::Sender<String>,
+    pub results: mpsc::UnboundedReceiver<CheckResult>,
+    pub handle: JoinHandle<()>,
+}
+
+impl CheckManager {
+    /// spawn starts the dispatcher, it runs until all senders are dropped
+    pub fn spawn<F, Fut>(config: CheckManagerConfig, check: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(bool, Checklist), String>>
+            + Send
+            + 'static,
+    {
+        let (sender, mut receiver) =
+            mpsc::channel::<String>(config.queue_capacity);
+        let (results_sender, results) = mpsc::unbounded_channel();
+        let workers = Arc::new(Semaphore::new(config.concurrency));
+
+        let handle = tokio::spawn(async move {
+            while let Some(signature) = receiver.recv().await {
+                let permit = match config.overflow {
+                    OverflowPolicy::Queue => {
+                        workers.clone().acquire_owned().await.unwrap()
+                    }
+                    OverflowPolicy::Drop => {
+                        match workers.clone().try_acquire_owned() {
+                            Ok(permit) => permit,
+                            Err(_) => {
+                                warn!(signature, "all workers busy, dropping");
+                                continue;
+                            }
+                        }
+                    }
+                };
+                debug!(signature, "checking");
+                let fut = check(signature.clone());
+                let results_sender = results_sender.clone();
+                tokio::spawn(async move {
+                    let result = fut.await;
+                    drop(permit);
+                    let _ =
+                        results_sender.send(CheckResult { signature, result });
+                });
+            }
+        });
+
+        Self {
+            sender,
+            results,
+            handle,
+        }
+    }
+
+    /// with_rpc_pool runs the regular `run_checks` on each candidate
+    pub fn with_rpc_pool(
+        config: CheckManagerConfig,
+        rpc_pool: Arc<RpcPool>,
+    ) -> Self {
+        Self::spawn(config, move |signature| {
+            let rpc_pool = rpc_pool.clone();
+            async move {
+                run_checks(signature, &rpc_pool)
+                    .await
+                    .map_err(|e| e.to_string())
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::future::BoxFuture;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// check that takes a while and records how many run at the same time
+    fn tracked_check(
+        running: Arc<AtomicUsize>,
+        max_running: Arc<AtomicUsize>,
+    ) -> impl Fn(String) -> BoxFuture<'static, Result<(bool, Checklist), String>>
+           + Send
+           + Sync
+           + 'static {
+        move |_| {
+            let running = running.clone();
+            let max_running = max_running.clone();
+            Box::pin(async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                Ok((true, Checklist::default()))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_manager_bounds_concurrency() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let mut manager = CheckManager::spawn(
+            CheckManagerConfig {
+                concurrency: 3,
+                ..Default::default()
+            },
+            tracked_check(running.clone(), max_running.clone()),
+        );
+
+        for i in 0..10 {
+            manager.sender.send(i.to_string()).await.unwrap();
+        }
+        for _ in 0..10 {
+            let result = manager.results.recv().await.unwrap();
+            assert!(result.result.unwrap().0);
+        }
+        assert_eq!(max_running.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_check_manager_drops_overflow() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let CheckManager {
+            sender,
+            mut results,
+            handle,
+        } = CheckManager::spawn(
+            CheckManagerConfig {
+                concurrency: 1,
+                overflow: OverflowPolicy::Drop,
+                ..Default::default()
+            },
+            tracked_check(running, max_running.clone()),
+        );
+
+        for i in 0..5 {
+            sender.send(i.to_string()).await.unwrap();
+        }
+        drop(sender);
+        handle.await.unwrap();
+
+        // the first one takes the only worker, the rest arrive while it runs
+        let result = results.recv().await.unwrap();
+        assert_eq!(result.signature, "0");
+        assert!(results.recv().await.is_none());
+        assert_eq!(max_running.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,527 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::future::Future;
+use std::time::Duration;
+
+use futures_util::stream::SelectAll;
+use futures_util::{Stream, StreamExt};
+use solana_client::client_error::Result as ClientResult;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::account::Account;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::mpsc;
+use tokio::time::{Instant, MissedTickBehavior};
+use tracing::{debug, info, instrument, warn};
+
+use crate::checker::{
+    checked_quote_side, on_vault_update, unpack_mint_info, CheckConfig,
+    Checklist, PoolAccounts,
+};
+use crate::constants::QuoteKind;
+use crate::util::env;
+
+/// Watched is the role of a subscribed account in the checks of its pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watched {
+    LpToken,
+    SolVault,
+    Mint,
+}
+
+/// CandidateChecks is the state machine of a single candidate, it is fed the
+/// account updates of its pool and returns the decision once one is reached
+#[derive(Debug)]
+pub struct CandidateChecks {
+    pub checklist: Checklist,
+    quote: QuoteKind,
+    sol_vault: Pubkey,
+    deadline: Instant,
+}
+
+impl CandidateChecks {
+    /// new starts the checks of the pool, fails if neither side is one of
+    /// the QUOTE_MINTS or the quote can't be valued
+    pub fn new(
+        accounts: PoolAccounts,
+        slot: u64,
+        config: &CheckConfig,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let (sol_vault, mint, quote) = checked_quote_side(&accounts, config)?;
+        Ok(Self {
+            checklist: Checklist {
+                slot,
+                accounts,
+                mint,
+                ..Default::default()
+            },
+            quote,
+            sol_vault,
+            deadline: Instant::now()
+                + Duration::from_secs(config.timeout_secs),
+        })
+    }
+
+    pub fn mint(&self) -> Pubkey {
+        self.checklist.mint
+    }
+
+    /// watched are the accounts the checks are driven by
+    pub fn watched(&self) -> [(Pubkey, Watched); 3] {
+        [
+            (self.checklist.accounts.user_lp_token, Watched::LpToken),
+            (self.sol_vault, Watched::SolVault),
+            (self.checklist.mint, Watched::Mint),
+        ]
+    }
+
+    /// on_account applies an update of one of the watched accounts, same as
+    /// the branches of `watch_checks` except that every update re-evaluates
+    /// the decision, the lp burn or the renounce can be the last one missing
+    pub fn on_account(
+        &mut self,
+        watched: Watched,
+        account: &Account,
+        config: &CheckConfig,
+    ) -> Option<bool> {
+        match watched {
+            Watched::LpToken => {
+                match spl_token::state::Account::unpack(&account.data) {
+                    Ok(lp_account) if lp_account.amount == 0 => {
+                        self.checklist.lp_burnt = true;
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!(?e, "failed to unpack lp token"),
+                }
+            }
+            Watched::SolVault => {
+                return on_vault_update(
+                    &mut self.checklist,
+                    config,
+                    self.quote,
+                    account.lamports,
+                    &account.data,
+                );
+            }
+            Watched::Mint => {
+                match unpack_mint_info(&account.owner, &account.data) {
+                    Ok(info) => self.checklist.apply_mint_info(&info),
+                    Err(e) => warn!(?e, "failed to unpack mint"),
+                }
+                // extensions can only be set at mint initialization
+                if config.reject_token_2022_extensions
+                    && self.checklist.has_dangerous_extensions()
+                {
+                    return Some(false);
+                }
+            }
+        }
+        self.checklist.is_safe(config).then_some(true)
+    }
+}
+
+/// CheckDecision is the outcome of the checks of one candidate
+#[derive(Debug)]
+pub struct CheckDecision {
+    pub mint: Pubkey,
+    pub ok: bool,
+    pub checklist: Checklist,
+}
+
+/// CheckMultiplexer holds the state machines of many candidates and routes
+/// the updates of the watched accounts to the candidate they belong to
+#[derive(Debug)]
+pub struct CheckMultiplexer {
+    config: CheckConfig,
+    candidates: HashMap<Pubkey, CandidateChecks>,
+    routes: HashMap<Pubkey, (Pubkey, Watched)>,
+}
+
+impl CheckMultiplexer {
+    pub fn new(config: CheckConfig) -> Self {
+        Self {
+            config,
+            candidates: HashMap::new(),
+            routes: HashMap::new(),
+        }
+    }
+
+    /// add registers the candidate, a mint that is already watched is
+    /// replaced
+    pub fn add(&mut self, candidate: CandidateChecks) {
+        let mint = candidate.mint();
+        self.remove(&mint);
+        for (pubkey, watched) in candidate.watched() {
+            self.routes.insert(pubkey, (mint, watched));
+        }
+        self.candidates.insert(mint, candidate);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    /// watched_pubkeys are the accounts of the undecided candidates
+    pub fn watched_pubkeys(&self) -> Vec<Pubkey> {
+        self.routes.keys().copied().collect()
+    }
+
+    /// on_account routes the update to its candidate, updates of accounts
+    /// no longer watched (decided candidates) are ignored
+    pub fn on_account(
+        &mut self,
+        pubkey: &Pubkey,
+        account: &Account,
+    ) -> Option<CheckDecision> {
+        let (mint, watched) = *self.routes.get(pubkey)?;
+        let candidate = self.candidates.get_mut(&mint)?;
+        let ok = candidate.on_account(watched, account, &self.config)?;
+        self.decide(&mint, ok)
+    }
+
+    /// expire times out the candidates past their deadline
+    pub fn expire(&mut self, now: Instant) -> Vec<CheckDecision> {
+        let expired = self
+            .candidates
+            .iter()
+            .filter(|(_, candidate)| candidate.deadline <= now)
+            .map(|(mint, _)| *mint)
+            .collect::<Vec<_>>();
+        expired
+            .iter()
+            .filter_map(|mint| {
+                info!(%mint, "timeout");
+                let candidate = self.candidates.get_mut(mint)?;
+                candidate.checklist.timeout = true;
+                self.decide(mint, false)
+            })
+            .collect()
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.candidates
+            .values()
+            .map(|candidate| candidate.deadline)
+            .min()
+    }
+
+    fn decide(&mut self, mint: &Pubkey, ok: bool) -> Option<CheckDecision> {
+        let candidate = self.remove(mint)?;
+        Some(CheckDecision {
+            mint: *mint,
+            ok,
+            checklist: candidate.checklist,
+        })
+    }
+
+    fn remove(&mut self, mint: &Pubkey) -> Option<CandidateChecks> {
+        let candidate = self.candidates.remove(mint)?;
+        for (pubkey, _) in candidate.watched() {
+            self.routes.remove(&pubkey);
+        }
+        Some(candidate)
+    }
+}
+
+/// watch_candidates checks many pools over a single websocket, every watched
+/// account of every candidate goes into one shared stream and the decisions
+/// are sent out per mint as they are reached; returns once all candidates
+/// are decided
+///
+/// the subscriptions of decided candidates are kept until the end, their
+/// notifications are dropped by the router
+#[instrument(skip_all, fields(candidates = candidates.len()))]
+pub async fn watch_candidates(
+    rpc_client: &RpcClient,
+    candidates: Vec<(PoolAccounts, u64)>,
+    config: CheckConfig,
+    decisions: mpsc::Sender<CheckDecision>,
+) -> Result<(), Box<dyn Error>> {
+    let subscription_configs = config.subscription_configs();
+    let mut multiplexer = CheckMultiplexer::new(config);
+    for (accounts, slot) in candidates {
+        match CandidateChecks::new(accounts, slot, &multiplexer.config) {
+            Ok(candidate) => multiplexer.add(candidate),
+            Err(e) => warn!(?e, ?accounts.amm_pool, "can't check pool"),
+        }
+    }
+
+    let pubsub_client = PubsubClient::new(&env("WS_URL")).await?;
+    let mut events = SelectAll::new();
+    let mut unsubs = vec![];
+    for (pubkey, (_, watched)) in multiplexer.routes.clone() {
+        let config = match watched {
+            Watched::LpToken => subscription_configs.lp.clone(),
+            Watched::SolVault => subscription_configs.vault.clone(),
+            Watched::Mint => subscription_configs.mint.clone(),
+        };
+        let (stream, unsub) = pubsub_client
+            .account_subscribe(&pubkey, Some(config))
+            .await?;
+        events.push(stream.filter_map(move |response| async move {
+            Some((pubkey, response.value.decode::<Account>()?))
+        }));
+        unsubs.push(unsub);
+    }
+
+    // one round trip per chunk of candidates per poll
+    let poll = |pubkeys: Vec<Pubkey>| async move {
+        rpc_client.get_multiple_accounts(&pubkeys).await
+    };
+    drive_checks(&mut multiplexer, events, poll, &decisions).await;
+
+    for unsub in unsubs {
+        unsub().await;
+    }
+    Ok(())
+}
+
+/// the most accounts the rpc returns for a single getMultipleAccounts
+pub const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// drive_checks feeds the shared event stream and the polls to the
+/// multiplexer until every candidate is decided or the decisions receiver
+/// is gone; the poll runs right away too, so the candidates start from the
+/// current state instead of waiting for the first notification, in chunks of
+/// MAX_MULTIPLE_ACCOUNTS
+async fn drive_checks<E, P, Fut>(
+    multiplexer: &mut CheckMultiplexer,
+    mut events: E,
+    mut poll: P,
+    decisions: &mpsc::Sender<CheckDecision>,
+) where
+    E: Stream<Item = (Pubkey, Account)> + Unpin,
+    P: FnMut(Vec<Pubkey>) -> Fut,
+    Fut: Future<Output = ClientResult<Vec<Option<Account>>>>,
+{
+    let poll_period =
+        Duration::from_secs(multiplexer.config.poll_interval_secs.max(1));
+    let mut poll_interval = tokio::time::interval(poll_period);
+    poll_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    while let Some(deadline) = multiplexer.next_deadline() {
+        let mut decided = vec![];
+        tokio::select! {
+            event = events.next() => {
+                let Some((pubkey, account)) = event else {
+                    warn!("account streams ended");
+                    return;
+                };
+                debug!(%pubkey, "account update received");
+                decided.extend(multiplexer.on_account(&pubkey, &account));
+            }
+            _ = poll_interval.tick() => {
+                let pubkeys = multiplexer.watched_pubkeys();
+                // the chunks are requested at once, a failed one only loses
+                // its own accounts
+                let chunks = pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS);
+                let polls = futures_util::future::join_all(
+                    chunks.clone().map(|chunk| poll(chunk.to_vec())),
+                )
+                .await;
+                for (chunk, polled) in chunks.zip(polls) {
+                    match polled {
+                        Ok(accounts) => {
+                            debug!(
+                                accounts = chunk.len(),
+                                "watched accounts polled"
+                            );
+                            for (pubkey, account) in chunk.iter().zip(accounts)
+                            {
+                                let Some(account) = account else { continue };
+                                decided.extend(
+                                    multiplexer.on_account(pubkey, &account),
+                                );
+                            }
+                        }
+                        Err(e) => warn!(?e, "watched accounts poll failed"),
+                    }
+                }
+            }
+            _ = tokio::time::sleep_until(deadline) => {
+                decided.extend(multiplexer.expire(Instant::now()));
+            }
+        }
+        for decision in decided {
+            info!(mint = %decision.mint, ok = decision.ok, "checks decided");
+            if decisions.send(decision).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::native_token::LAMPORTS_PER_SOL;
+
+    use crate::constants;
+
+    fn make_pool(mint: Pubkey) -> PoolAccounts {
+        PoolAccounts {
+            amm_pool: Pubkey::new_unique(),
+            coin_mint: mint,
+            pc_mint: constants::SOLANA_PROGRAM_ID,
+            pool_coin_token_account: Pubkey::new_unique(),
+            pool_pc_token_account: Pubkey::new_unique(),
+            user_lp_token: Pubkey::new_unique(),
+            ..Default::default()
+        }
+    }
+
+    fn renounced_mint() -> Account {
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            supply: 1_000_000_000,
+            decimals: 6,
+            is_initialized: true,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        Account {
+            owner: spl_token::ID,
+            data,
+            ..Default::default()
+        }
+    }
+
+    fn burnt_lp() -> Account {
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            amount: 0,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        Account {
+            owner: spl_token::ID,
+            data,
+            ..Default::default()
+        }
+    }
+
+    fn vault(lamports: u64) -> Account {
+        Account {
+            lamports,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_multiplexer_decides_each_candidate() {
+        let config = CheckConfig {
+            poll_interval_secs: 60,
+            timeout_secs: 5,
+            ..Default::default()
+        };
+        let (safe_mint, rugged_mint) =
+            (Pubkey::new_unique(), Pubkey::new_unique());
+        let (safe_pool, rugged_pool) =
+            (make_pool(safe_mint), make_pool(rugged_mint));
+        let mut multiplexer = CheckMultiplexer::new(config.clone());
+        for pool in [safe_pool, rugged_pool] {
+            multiplexer.add(CandidateChecks::new(pool, 1, &config).unwrap());
+        }
+
+        // the notifications of both candidates interleave on the one stream
+        let events = futures_util::stream::iter([
+            (
+                safe_pool.pool_pc_token_account,
+                vault(10 * LAMPORTS_PER_SOL),
+            ),
+            (
+                rugged_pool.pool_pc_token_account,
+                vault(10 * LAMPORTS_PER_SOL),
+            ),
+            (safe_pool.user_lp_token, burnt_lp()),
+            (rugged_pool.user_lp_token, burnt_lp()),
+            // dropping from 10 sol to 2 is a drain
+            (
+                rugged_pool.pool_pc_token_account,
+                vault(2 * LAMPORTS_PER_SOL),
+            ),
+            (safe_mint, renounced_mint()),
+        ])
+        .chain(futures_util::stream::pending());
+
+        let (decisions, mut results) = mpsc::channel(4);
+        // the initial poll sees nothing yet
+        let poll = |pubkeys: Vec<Pubkey>| async move {
+            Ok(vec![None; pubkeys.len()])
+        };
+        drive_checks(&mut multiplexer, events, poll, &decisions).await;
+        drop(decisions);
+        assert!(multiplexer.is_empty());
+
+        let mut outcomes = HashMap::new();
+        while let Some(decision) = results.recv().await {
+            outcomes.insert(decision.mint, decision);
+        }
+        assert_eq!(outcomes.len(), 2);
+
+        let safe = &outcomes[&safe_mint];
+        assert!(safe.ok);
+        assert!(safe.checklist.lp_burnt);
+        assert!(safe.checklist.mint_authority_renounced);
+        assert_eq!(safe.checklist.sol_pooled, 10.0);
+
+        let rugged = &outcomes[&rugged_mint];
+        assert!(!rugged.ok);
+        assert!(!rugged.checklist.timeout);
+        assert!(rugged.checklist.is_drained(&config));
+        assert_eq!(rugged.checklist.sol_pooled, 2.0);
+    }
+
+    #[tokio::test]
+    async fn test_poll_chunked() {
+        let config = CheckConfig {
+            poll_interval_secs: 60,
+            timeout_secs: 5,
+            ..Default::default()
+        };
+        let mut multiplexer = CheckMultiplexer::new(config.clone());
+        let mut state = HashMap::new();
+        for _ in 0..40 {
+            let mint = Pubkey::new_unique();
+            let pool = make_pool(mint);
+            multiplexer.add(CandidateChecks::new(pool, 1, &config).unwrap());
+            state.insert(mint, renounced_mint());
+            state.insert(pool.user_lp_token, burnt_lp());
+            state.insert(
+                pool.pool_pc_token_account,
+                vault(10 * LAMPORTS_PER_SOL),
+            );
+        }
+        assert_eq!(multiplexer.watched_pubkeys().len(), 120);
+
+        // no notifications, the first poll decides all of them
+        let polled = std::sync::Mutex::new(vec![]);
+        let poll = |pubkeys: Vec<Pubkey>| {
+            polled.lock().unwrap().push(pubkeys.len());
+            let accounts = pubkeys
+                .iter()
+                .map(|pubkey| state.get(pubkey).cloned())
+                .collect();
+            async move { Ok(accounts) }
+        };
+        let (decisions, mut results) = mpsc::channel(40);
+        drive_checks(
+            &mut multiplexer,
+            futures_util::stream::pending(),
+            poll,
+            &decisions,
+        )
+        .await;
+        drop(decisions);
+
+        assert_eq!(*polled.lock().unwrap(), vec![100, 20]);
+        let mut decided = 0;
+        while let Some(decision) = results.recv().await {
+            assert!(decision.ok);
+            decided += 1;
+        }
+        assert_eq!(decided, 40);
+    }
+}
@@ -1,28 +1,42 @@
-use std::str::FromStr;
+use std::{future::Future, str::FromStr, time::Duration};
 
 use base64::Engine;
-use futures_util::StreamExt;
-use log::{debug, info, warn};
+use futures_util::{FutureExt, Stream, StreamExt};
+use raydium_amm::state::AmmInfo;
 use serde::{Deserialize, Serialize};
-use solana_account_decoder::{UiAccountData, UiAccountEncoding};
+use solana_account_decoder::{UiAccount, UiAccountData, UiAccountEncoding};
 use solana_client::{
-    nonblocking::{pubsub_client::PubsubClient, rpc_client::RpcClient},
+    client_error::Result as ClientResult,
+    nonblocking::rpc_client::RpcClient,
     rpc_config::{RpcAccountInfoConfig, RpcTransactionConfig},
+    rpc_response::Response,
 };
 use solana_sdk::{
-    commitment_config::CommitmentConfig, program_pack::Pack, pubkey::Pubkey,
-    signature::Signature,
+    account::Account, commitment_config::CommitmentConfig, program_pack::Pack,
+    pubkey::Pubkey, signature::Signature,
 };
 use solana_transaction_status::{
+    option_serializer::OptionSerializer,
     EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction,
     UiInstruction, UiMessage, UiParsedInstruction, UiParsedMessage,
-    UiPartiallyDecodedInstruction, UiTransactionEncoding,
+    UiPartiallyDecodedInstruction, UiRawMessage, UiTransactionEncoding,
 };
 use spl_token::state::Mint;
+use spl_token_2022::extension::{
+    BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+};
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, field, info, instrument, warn, Span};
 
 use crate::{
     buyer::check_if_pump_fun,
-    constants,
+    constants::{self, classify_quote, QuoteKind},
+    honeypot::simulate_round_trip,
+    pubsub::ReconnectingPubsub,
+    pump::{derive_pump_accounts, PUMP_FUN_PROGRAM},
+    rpc_pool::RpcPool,
+    seller::unpack,
+    units::{lamports_to_sol, ui_amount},
     util::{env, pubkey_to_string, string_to_pubkey},
 };
 
@@ -34,7 +48,26 @@ pub struct Checklist {
     pub mint_authority_renounced: bool,
     pub freeze_authority_renounced: bool,
     pub sol_pooled: f64,
+    /// highest sol_pooled seen during the checks
+    pub peak_sol_pooled: f64,
+    /// largest drop from peak_sol_pooled seen during the checks, in percent
+    pub max_drawdown_pct: f64,
     pub timeout: bool,
+    pub has_transfer_fee: bool,
+    pub has_transfer_hook: bool,
+    pub has_permanent_delegate: bool,
+    /// a buy then sell round trip against the pool simulated fine, only set
+    /// when `simulate_round_trip` is on and the other checks passed
+    pub sell_simulated_ok: bool,
+    /// the round trip couldn't be simulated, the rpc or the buy leg failed;
+    /// it is not known to be a honeypot and doesn't fail the checks
+    pub sell_simulation_unknown: bool,
+    /// the openbook market backing the pool was disabled by its authority,
+    /// orders can't be placed against it anymore
+    pub market_disabled: bool,
+    /// the market couldn't be fetched or read, it is not known to be
+    /// disabled and doesn't fail the checks
+    pub market_unknown: bool,
     pub accounts: PoolAccounts,
     #[serde(
         serialize_with = "pubkey_to_string",
@@ -43,17 +76,219 @@ pub struct Checklist {
     pub mint: Pubkey,
 }
 
+/// CheckConfig holds the thresholds a pool has to meet to be considered safe
+///
+/// the commitments are per subscription: `processed` is the fastest but the
+/// state it reports can still be dropped on a fork, e.g. a vault deposit that
+/// never lands; `finalized` can't be reverted but lags ~32 slots behind; the
+/// vault stream is the latency sensitive one, while the authorities are
+/// worth waiting for since acting on a reverted renounce is the costly error
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckConfig {
+    pub min_sol_pooled: f64,
+    /// dollar liquidity the pool has to hold, evaluated as
+    /// `sol_pooled * sol_price`; takes the place of `min_sol_pooled` when
+    /// set, and fails the check when no `sol_price` is supplied
+    pub min_liquidity_usd: Option<f64>,
+    pub sol_price: Option<f64>,
+    pub allow_pump_fun: bool,
+    /// pass pump fun tokens right away, before any pool state is read, they
+    /// are sniped off the bonding curve rather than checked; when off,
+    /// `is_pump_fun` is only recorded and the pool goes through the full
+    /// checks (with `allow_pump_fun` deciding whether it can pass), e.g. for
+    /// graduated tokens
+    pub decide_pump_fun_early: bool,
+    pub require_lp_burnt: bool,
+    pub require_mint_renounced: bool,
+    pub require_freeze_renounced: bool,
+    pub timeout_secs: u64,
+    /// fail a pool whose liquidity drops by more than this percentage from
+    /// its peak during the checks, a slow drain can stay above the minimum
+    /// liquidity for long enough to lure buyers in
+    pub max_sol_drawdown_pct: f64,
+    /// how often the vault and mint are polled directly, on top of the
+    /// subscriptions
+    pub poll_interval_secs: u64,
+    /// fail Token-2022 mints with a transfer fee (can tax sells to zero), a
+    /// transfer hook (can block transfers) or a permanent delegate (can
+    /// seize or burn any holder's tokens)
+    pub reject_token_2022_extensions: bool,
+    /// simulate a small buy and sell of pools that pass the checks and fail
+    /// them if the sell doesn't go through, catches honeypots that block
+    /// sells in ways the account state doesn't show; the simulation is paid
+    /// by the payer passed to the checks, it is never sent
+    pub simulate_round_trip: bool,
+    /// how many times the initial snapshot is fetched until all the pool
+    /// accounts are visible, a new pool can lag behind at the rpc
+    pub snapshot_attempts: usize,
+    pub snapshot_retry_delay_ms: u64,
+    pub lp_commitment: CommitmentConfig,
+    pub vault_commitment: CommitmentConfig,
+    pub mint_commitment: CommitmentConfig,
+}
+
+/// SubscriptionConfigs are the account_subscribe configs of the checker
+/// streams
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriptionConfigs {
+    pub lp: RpcAccountInfoConfig,
+    pub vault: RpcAccountInfoConfig,
+    pub mint: RpcAccountInfoConfig,
+}
+
+impl CheckConfig {
+    /// has_min_liquidity compares the pooled sol against the usd threshold if
+    /// one is set, otherwise against the sol one
+    pub fn has_min_liquidity(&self, sol_pooled: f64) -> bool {
+        match (self.min_liquidity_usd, self.sol_price) {
+            (Some(min_usd), Some(sol_price)) => {
+                sol_pooled * sol_price >= min_usd
+            }
+            (Some(_), None) => false,
+            (None, _) => sol_pooled >= self.min_sol_pooled,
+        }
+    }
+
+    pub fn subscription_configs(&self) -> SubscriptionConfigs {
+        SubscriptionConfigs {
+            lp: RpcAccountInfoConfig {
+                commitment: Some(self.lp_commitment),
+                encoding: Some(UiAccountEncoding::Base64),
+                ..Default::default()
+            },
+            // the lamports are enough for wsol vaults, stablecoin vaults need
+            // the token amount from the data
+            vault: RpcAccountInfoConfig {
+                commitment: Some(self.vault_commitment),
+                encoding: Some(UiAccountEncoding::Base64),
+                ..Default::default()
+            },
+            mint: RpcAccountInfoConfig {
+                commitment: Some(self.mint_commitment),
+                encoding: Some(UiAccountEncoding::Base64),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl Default for CheckConfig {
+    fn default() -> Self {
+        Self {
+            min_sol_pooled: 6.9,
+            min_liquidity_usd: None,
+            sol_price: None,
+            allow_pump_fun: false,
+            decide_pump_fun_early: true,
+            require_lp_burnt: true,
+            require_mint_renounced: true,
+            require_freeze_renounced: true,
+            timeout_secs: 900,
+            max_sol_drawdown_pct: 30.0,
+            poll_interval_secs: 10,
+            reject_token_2022_extensions: true,
+            simulate_round_trip: false,
+            snapshot_attempts: 3,
+            snapshot_retry_delay_ms: 400,
+            lp_commitment: CommitmentConfig::processed(),
+            vault_commitment: CommitmentConfig::processed(),
+            mint_commitment: CommitmentConfig::processed(),
+        }
+    }
+}
+
 impl Checklist {
-    pub fn all_clear(&self) -> bool {
-        !self.is_pump_fun
-            && self.lp_burnt
-            && self.mint_authority_renounced
-            && self.freeze_authority_renounced
+    /// is_safe is the pass/fail decision of the checks, it can be
+    /// re-evaluated on a stored checklist with a different config
+    pub fn is_safe(&self, config: &CheckConfig) -> bool {
+        (config.allow_pump_fun || !self.is_pump_fun)
+            && (!config.require_lp_burnt || self.lp_burnt)
+            && (!config.require_mint_renounced
+                || self.mint_authority_renounced)
+            && (!config.require_freeze_renounced
+                || self.freeze_authority_renounced)
             && !self.timeout
-            && self.sol_pooled >= 6.9
+            && config.has_min_liquidity(self.sol_pooled)
+            && !self.is_drained(config)
+            && (!config.reject_token_2022_extensions
+                || !self.has_dangerous_extensions())
+            && !self.market_disabled
+    }
+
+    pub fn is_drained(&self, config: &CheckConfig) -> bool {
+        self.max_drawdown_pct > config.max_sol_drawdown_pct
+    }
+
+    /// record_sol_pooled sets the current liquidity and keeps track of its
+    /// peak and of the largest drop from that peak
+    pub fn record_sol_pooled(&mut self, sol_pooled: f64) {
+        self.sol_pooled = sol_pooled;
+        self.peak_sol_pooled = self.peak_sol_pooled.max(sol_pooled);
+        if self.peak_sol_pooled > 0.0 {
+            let drawdown_pct = (self.peak_sol_pooled - sol_pooled)
+                / self.peak_sol_pooled
+                * 100.0;
+            self.max_drawdown_pct = self.max_drawdown_pct.max(drawdown_pct);
+        }
+    }
+
+    pub fn has_dangerous_extensions(&self) -> bool {
+        self.has_transfer_fee
+            || self.has_transfer_hook
+            || self.has_permanent_delegate
+    }
+
+    /// apply_mint_info records what was read from the mint, a renounced
+    /// authority can't be taken back so those only ever go to true
+    pub fn apply_mint_info(&mut self, info: &MintInfo) {
+        self.mint_authority_renounced |= info.mint_authority_renounced;
+        self.freeze_authority_renounced |= info.freeze_authority_renounced;
+        self.has_transfer_fee = info.has_transfer_fee;
+        self.has_transfer_hook = info.has_transfer_hook;
+        self.has_permanent_delegate = info.has_permanent_delegate;
     }
 }
 
+/// MintInfo is what the checks read from a mint account
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MintInfo {
+    pub mint_authority_renounced: bool,
+    pub freeze_authority_renounced: bool,
+    pub has_transfer_fee: bool,
+    pub has_transfer_hook: bool,
+    pub has_permanent_delegate: bool,
+}
+
+/// unpack_mint_info decodes a mint of either token program, spl_token's
+/// Mint::unpack rejects Token-2022 mints with extensions since their data is
+/// longer than Mint::LEN, so those are decoded along with their extensions
+pub fn unpack_mint_info(
+    owner: &Pubkey,
+    data: &[u8],
+) -> Result<MintInfo, Box<dyn std::error::Error>> {
+    if *owner != spl_token_2022::ID {
+        let mint = Mint::unpack(data)?;
+        return Ok(MintInfo {
+            mint_authority_renounced: mint.mint_authority.is_none(),
+            freeze_authority_renounced: mint.freeze_authority.is_none(),
+            ..Default::default()
+        });
+    }
+
+    let state =
+        StateWithExtensions::<spl_token_2022::state::Mint>::unpack(data)?;
+    let extensions = state.get_extension_types()?;
+    Ok(MintInfo {
+        mint_authority_renounced: state.base.mint_authority.is_none(),
+        freeze_authority_renounced: state.base.freeze_authority.is_none(),
+        has_transfer_fee: extensions
+            .contains(&ExtensionType::TransferFeeConfig),
+        has_transfer_hook: extensions.contains(&ExtensionType::TransferHook),
+        has_permanent_delegate: extensions
+            .contains(&ExtensionType::PermanentDelegate),
+    })
+}
+
 #[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
 pub struct PoolAccounts {
     #[serde(
@@ -108,6 +343,83 @@ pub struct PoolAccounts {
     pub user_lp_token: Pubkey,
 }
 
+/// load_raydium_pool reads the pool accounts from the on-chain state of an
+/// amm v4 pool, for when only the pool address is known; the user accounts
+/// belong to the pool creator, who is only known from the init transaction,
+/// so those are left default
+pub async fn load_raydium_pool(
+    rpc_client: &RpcClient,
+    amm_pool: &Pubkey,
+) -> Result<PoolAccounts, Box<dyn std::error::Error>> {
+    let account = rpc_client.get_account(amm_pool).await?;
+    if account.owner != constants::RAYDIUM_LIQUIDITY_POOL_V4_PUBKEY {
+        return Err(
+            format!("{} is not a raydium amm v4 pool", amm_pool).into()
+        );
+    }
+    pool_accounts_from_amm(amm_pool, &account.data)
+}
+
+/// unpack_amm reads an amm pool account, unpack reads the struct in place so
+/// it has to be all there
+pub fn unpack_amm(data: &[u8]) -> Result<AmmInfo, Box<dyn std::error::Error>> {
+    if data.len() != std::mem::size_of::<AmmInfo>() {
+        return Err(
+            format!("Invalid amm pool data length {}", data.len()).into()
+        );
+    }
+    Ok(unpack::<AmmInfo>(data).ok_or("Failed to unpack amm pool")?)
+}
+
+pub fn pool_accounts_from_amm(
+    amm_pool: &Pubkey,
+    data: &[u8],
+) -> Result<PoolAccounts, Box<dyn std::error::Error>> {
+    let amm = unpack_amm(data)?;
+    Ok(PoolAccounts {
+        amm_pool: *amm_pool,
+        lp_mint: amm.lp_mint,
+        coin_mint: amm.coin_vault_mint,
+        pc_mint: amm.pc_vault_mint,
+        pool_coin_token_account: amm.coin_vault,
+        pool_pc_token_account: amm.pc_vault,
+        ..Default::default()
+    })
+}
+
+const MARKET_HEAD_PADDING: &[u8; 5] = b"serum";
+const MARKET_FLAG_INITIALIZED: u64 = 1 << 0;
+const MARKET_FLAG_MARKET: u64 = 1 << 1;
+const MARKET_FLAG_DISABLED: u64 = 1 << 7;
+
+/// market_disabled reads the market of the amm pool account and whether it
+/// is disabled
+async fn market_disabled(
+    rpc_client: &RpcClient,
+    amm_pool_data: &[u8],
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let amm = unpack_amm(amm_pool_data)?;
+    is_market_disabled(&rpc_client.get_account_data(&amm.market).await?)
+}
+
+/// is_market_disabled reads the account flags of a serum/openbook market,
+/// they follow the 5 bytes of head padding
+pub fn is_market_disabled(
+    data: &[u8],
+) -> Result<bool, Box<dyn std::error::Error>> {
+    if data.len() < 13 || &data[..5] != MARKET_HEAD_PADDING {
+        return Err("Not a market account".into());
+    }
+    let flags = u64::from_le_bytes(data[5..13].try_into()?);
+    let market = MARKET_FLAG_INITIALIZED | MARKET_FLAG_MARKET;
+    if flags & market != market {
+        return Err(
+            format!("Not an initialized market, flags {:#x}", flags).into()
+        );
+    }
+    Ok(flags & MARKET_FLAG_DISABLED != 0)
+}
+
 /// run_checks checks if:
 /// 1. the token is a pump fun
 /// 2. the pool has enough sol pooled
@@ -117,31 +429,38 @@ pub struct PoolAccounts {
 ///     checking top holders, but this is not relevant the top holders ratio
 ///     right after creation does not matter as much, as long as it is not
 ///     a pump fun
+#[instrument(skip_all, fields(signature = %signature))]
 pub async fn run_checks(
     signature: String,
+    rpc_pool: &RpcPool,
 ) -> Result<(bool, Checklist), Box<dyn std::error::Error>> {
-    let rpc_client = RpcClient::new_with_commitment(
-        env("RPC_URL"),
-        CommitmentConfig::processed(),
-    );
-    let tx = rpc_client
-        .get_transaction_with_config(
-            &Signature::from_str(&signature)?,
-            RpcTransactionConfig {
-                encoding: Some(UiTransactionEncoding::JsonParsed),
-                commitment: Some(CommitmentConfig::confirmed()),
-                max_supported_transaction_version: Some(1),
-            },
-        )
-        .await?;
+    let rpc_client = rpc_pool.get();
+    let signature = Signature::from_str(&signature)?;
+    // a transaction that isn't found (yet) is not the endpoint's fault
+    let tx = rpc_client.track_transport(
+        rpc_client
+            .get_transaction_with_config(
+                &signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::JsonParsed),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(1),
+                },
+            )
+            .await
+            .map_err(Into::into),
+    )?;
     let accounts = parse_accounts(&tx)?;
-    info!(
-        "{}: {}",
-        signature,
-        serde_json::to_string_pretty(&accounts).unwrap()
-    );
-    let (ok, checklist) =
-        _run_checks(&rpc_client, accounts, tx.slot, true).await?;
+    info!("{}", serde_json::to_string_pretty(&accounts).unwrap());
+    let (ok, checklist) = _run_checks(
+        &rpc_client,
+        accounts,
+        tx.slot,
+        true,
+        &CheckConfig::default(),
+        None,
+    )
+    .await?;
     Ok((ok, checklist))
 }
 
@@ -150,13 +469,54 @@ pub async fn _run_checks(
     accounts: PoolAccounts,
     slot: u64,
     ignore_non_pump_funs: bool,
+    config: &CheckConfig,
+    payer: Option<&Pubkey>,
 ) -> Result<(bool, Checklist), Box<dyn std::error::Error>> {
-    let (sol_vault, mint) =
-        if accounts.coin_mint.eq(&constants::SOLANA_PROGRAM_ID) {
-            (accounts.pool_coin_token_account, accounts.pc_mint)
-        } else {
-            (accounts.pool_pc_token_account, accounts.coin_mint)
-        };
+    let (ok, mut checklist) = run_pool_checks(
+        rpc_client,
+        accounts,
+        slot,
+        ignore_non_pump_funs,
+        config,
+    )
+    .await?;
+    // pump funs are bought off the bonding curve, there is no pool yet
+    if !ok || !config.simulate_round_trip || checklist.is_pump_fun {
+        return Ok((ok, checklist));
+    }
+
+    let payer = payer.ok_or("simulate_round_trip needs a payer")?;
+    match simulate_round_trip(
+        rpc_client,
+        &checklist.mint,
+        &checklist.accounts.amm_pool,
+        payer,
+    )
+    .await
+    {
+        Ok(round_trip) => {
+            info!(?round_trip, "round trip simulated");
+            checklist.sell_simulated_ok = round_trip.is_sellable();
+            Ok((checklist.sell_simulated_ok, checklist))
+        }
+        Err(e) => {
+            warn!(?e, "round trip not simulated, honeypot check unknown");
+            checklist.sell_simulation_unknown = true;
+            Ok((ok, checklist))
+        }
+    }
+}
+
+#[instrument(skip_all, fields(slot = slot, mint = field::Empty))]
+async fn run_pool_checks(
+    rpc_client: &RpcClient,
+    accounts: PoolAccounts,
+    slot: u64,
+    ignore_non_pump_funs: bool,
+    config: &CheckConfig,
+) -> Result<(bool, Checklist), Box<dyn std::error::Error>> {
+    let (sol_vault, mint, quote) = checked_quote_side(&accounts, config)?;
+    Span::current().record("mint", field::display(mint));
 
     let mut checklist = Checklist {
         slot,
@@ -166,240 +526,691 @@ pub async fn _run_checks(
     };
 
     // could be insta-sniping the pump fun launches, generally I am pretty fast
-    // (~10 slots) so sniping pumpfuns since they pass all checks is ok
-    let is_pump_fun = check_if_pump_fun(&mint).await?;
-    checklist.is_pump_fun = is_pump_fun;
-    if is_pump_fun {
-        return Ok((true, checklist));
-    }
-    if ignore_non_pump_funs {
-        // ignoring any other tokens, way too many scams (noise to profit ratio
-        // is too low), even with higher, centralized supply
-        // only profit opp is a fair launch of a larger token, but this happens rarely
-        // current strat is to flip pumps for 30-50% profit
-        return Ok((false, checklist));
+    // (~10 slots) so sniping pumpfuns since they pass all checks is ok; most
+    // candidates are decided by this alone, before anything is subscribed to
+    checklist.is_pump_fun = is_pump_fun(rpc_client, &mint).await?;
+    if let Some(ok) = early_decision(&checklist, config, ignore_non_pump_funs)
+    {
+        return Ok((ok, checklist));
     }
 
-    let pubsub_client = PubsubClient::new(&env("WS_URL")).await?;
+    // subscribed before the snapshot is taken, so that no change between
+    // the two is missed
+    let pubsub = ReconnectingPubsub::new(&env("WS_URL"));
+    let subscription_configs = config.subscription_configs();
 
-    let (mut lp_stream, lp_unsub) = pubsub_client
-        .account_subscribe(
-            &accounts.user_lp_token,
-            Some(RpcAccountInfoConfig {
-                commitment: Some(CommitmentConfig::processed()),
-                encoding: Some(UiAccountEncoding::Base64),
-                ..Default::default()
-            }),
-        )
+    let mut lp_stream = pubsub
+        .account_subscribe(accounts.user_lp_token, subscription_configs.lp)
         .await?;
 
-    let (mut sol_vault_stream, sol_vault_unsub) = pubsub_client
-        .account_subscribe(
-            &sol_vault,
-            Some(RpcAccountInfoConfig {
-                commitment: Some(CommitmentConfig::processed()),
-                ..Default::default()
-            }),
-        )
+    let mut sol_vault_stream = pubsub
+        .account_subscribe(sol_vault, subscription_configs.vault)
         .await?;
 
     // stream to check total supply, mint authority, freeze authority generally,
     // will run a check if LP burnt, but mint renounce happens sometimes after a
     // delay (user decision)
-    let (mut mint_stream, mint_unsub) = pubsub_client
-        .account_subscribe(
-            &mint,
-            Some(RpcAccountInfoConfig {
-                commitment: Some(CommitmentConfig::processed()),
-                encoding: Some(UiAccountEncoding::Base64),
-                ..Default::default()
-            }),
-        )
+    let mut mint_stream = pubsub
+        .account_subscribe(mint, subscription_configs.mint)
         .await?;
 
-    let accounts = &rpc_client
-        .get_multiple_accounts(&[accounts.user_lp_token, mint, sol_vault])
-        .await?[..];
-    if accounts.iter().all(|x| x.is_some()) {
-        let account = match accounts[0].clone() {
-            Some(account) => account,
-            None => {
-                return Err("Could not get account user lp account".into());
-            }
-        };
-        let lp_account =
-            spl_token::state::Account::unpack(&account.data).unwrap();
-        if lp_account.amount == 0 {
-            checklist.lp_burnt = true;
-        }
+    // single round trip for the initial state, decoded in place
+    let snapshot_keys = snapshot_pubkeys(&accounts, &mint)?;
+    let snapshot = fetch_snapshot(
+        || rpc_client.get_multiple_accounts(&snapshot_keys),
+        config.snapshot_attempts,
+        Duration::from_millis(config.snapshot_retry_delay_ms),
+    )
+    .await?;
 
-        // generally, if checks pass might skip subbing to the mint stream, same with lp stream
-        let account = match accounts[1].clone() {
-            Some(account) => account,
-            None => {
-                return Err("Could not get account mint".into());
-            }
-        };
-        let mint_account = Mint::unpack(&account.data).unwrap();
-        if mint_account.mint_authority.is_none() {
-            checklist.mint_authority_renounced = true;
-        }
-        if mint_account.freeze_authority.is_none() {
-            checklist.freeze_authority_renounced = true;
+    // the market can't be re-enabled by the pool, no point waiting
+    let market_disabled = match &snapshot[SNAPSHOT_AMM_POOL] {
+        Some(amm_pool) => market_disabled(rpc_client, &amm_pool.data).await,
+        None => Err("No amm pool account".into()),
+    };
+    match market_disabled {
+        Ok(disabled) => checklist.market_disabled = disabled,
+        Err(e) => {
+            warn!(?e, "market state unknown");
+            checklist.market_unknown = true;
         }
-        if checklist.all_clear() {
-            return Ok((true, checklist));
+    }
+    if checklist.market_disabled {
+        return Ok((false, checklist));
+    }
+
+    // a closed lp token account holds nothing, same as an emptied one
+    checklist.lp_burnt = match &snapshot[SNAPSHOT_USER_LP_TOKEN] {
+        Some(lp) => spl_token::state::Account::unpack(&lp.data)?.amount == 0,
+        None => true,
+    };
+
+    // fetch_snapshot only returns with the mint and the vault
+    let mint_account = snapshot[SNAPSHOT_MINT].as_ref().ok_or("No mint")?;
+    let vault = snapshot[SNAPSHOT_SOL_VAULT]
+        .as_ref()
+        .ok_or("No sol vault")?;
+
+    // generally, if checks pass might skip subbing to the mint stream, same with lp stream
+    checklist.apply_mint_info(&unpack_mint_info(
+        &mint_account.owner,
+        &mint_account.data,
+    )?);
+    // extensions can only be set at mint initialization, no point waiting
+    if config.reject_token_2022_extensions
+        && checklist.has_dangerous_extensions()
+    {
+        return Ok((false, checklist));
+    }
+
+    let sol_pooled =
+        quote_pooled(quote, vault.lamports, &vault.data, config.sol_price)?;
+    checklist.record_sol_pooled(sol_pooled);
+
+    // notifications received while the snapshot was in flight are at least
+    // as new as it, they win over it
+    if let Some(ok) = apply_pending_notifications(
+        &mut checklist,
+        config,
+        quote,
+        &mut lp_stream,
+        &mut sol_vault_stream,
+        &mut mint_stream,
+    ) {
+        return Ok((ok, checklist));
+    }
+
+    if checklist.is_safe(config) {
+        return Ok((true, checklist));
+    }
+    // this is the only check that can terminate prematurely
+    if !config.has_min_liquidity(checklist.sol_pooled) {
+        return Ok((false, checklist));
+    }
+
+    // one round trip per poll, cheap to repeat while the streams are quiet
+    let poll = move || async move {
+        rpc_client.get_multiple_accounts(&[sol_vault, mint]).await
+    };
+    let ok = watch_checks(
+        &mut checklist,
+        config,
+        quote,
+        lp_stream,
+        sol_vault_stream,
+        mint_stream,
+        poll,
+    )
+    .await;
+
+    Ok((ok, checklist))
+}
+
+/// apply_pending_notifications applies the notifications already received
+/// without waiting for more, returns the decision if a vault update made it
+fn apply_pending_notifications<L, V, M>(
+    checklist: &mut Checklist,
+    config: &CheckConfig,
+    quote: QuoteKind,
+    lp_stream: &mut L,
+    sol_vault_stream: &mut V,
+    mint_stream: &mut M,
+) -> Option<bool>
+where
+    L: Stream<Item = Response<UiAccount>> + Unpin,
+    V: Stream<Item = Response<UiAccount>> + Unpin,
+    M: Stream<Item = Response<UiAccount>> + Unpin,
+{
+    while let Some(Some(lp_log)) = lp_stream.next().now_or_never() {
+        on_lp_notification(checklist, &lp_log.value);
+    }
+    while let Some(Some(mint_log)) = mint_stream.next().now_or_never() {
+        on_mint_notification(checklist, &mint_log.value);
+    }
+    while let Some(Some(vault_log)) = sol_vault_stream.next().now_or_never() {
+        if let Some(ok) =
+            on_vault_notification(checklist, config, quote, &vault_log.value)
+        {
+            return Some(ok);
         }
+    }
+    None
+}
 
-        let account = match accounts[2].clone() {
-            Some(account) => account,
-            None => {
-                return Err("Could not get account sol vault".into());
-            }
-        };
-        let sol_pooled = account.lamports as f64 / 10u64.pow(9) as f64;
-        checklist.sol_pooled = sol_pooled;
-        // this is the only check that can terminate prematurely
-        if sol_pooled < 6.9 {
-            return Ok((false, checklist));
+/// ui_account_data decodes the data of a base64 notification, empty for any
+/// other encoding
+fn ui_account_data(account: &UiAccount) -> Vec<u8> {
+    match &account.data {
+        UiAccountData::Binary(data, UiAccountEncoding::Base64) => {
+            base64::prelude::BASE64_STANDARD
+                .decode(data)
+                .unwrap_or_default()
         }
+        _ => vec![],
+    }
+}
+
+/// on_lp_notification marks the lp burnt once the lp token account is empty
+fn on_lp_notification(checklist: &mut Checklist, account: &UiAccount) {
+    let data = ui_account_data(account);
+    if data.is_empty() {
+        warn!("empty log data");
+        return;
+    }
+    match spl_token::state::Account::unpack(&data) {
+        Ok(lp_account) if lp_account.amount == 0 => checklist.lp_burnt = true,
+        Ok(_) => {}
+        Err(e) => warn!(?e, "failed to unpack lp token"),
+    }
+}
+
+/// on_mint_notification records the authorities and extensions of the mint
+fn on_mint_notification(checklist: &mut Checklist, account: &UiAccount) {
+    let data = ui_account_data(account);
+    let info = Pubkey::from_str(&account.owner)
+        .map_err(Into::into)
+        .and_then(|owner| unpack_mint_info(&owner, &data));
+    match info {
+        Ok(info) => checklist.apply_mint_info(&info),
+        Err(e) => warn!(?e, "failed to unpack mint"),
     }
+}
 
-    let ok = loop {
+/// on_vault_notification is on_vault_update for a vault notification
+fn on_vault_notification(
+    checklist: &mut Checklist,
+    config: &CheckConfig,
+    quote: QuoteKind,
+    account: &UiAccount,
+) -> Option<bool> {
+    let data = ui_account_data(account);
+    on_vault_update(checklist, config, quote, account.lamports, &data)
+}
+
+/// watch_checks waits on the account streams until the checks reach a
+/// decision; the vault and mint are also polled every `poll_interval_secs`,
+/// providers can silently stop delivering notifications and the decision
+/// would otherwise wait for the timeout on stale state
+#[allow(clippy::too_many_arguments)]
+async fn watch_checks<L, V, M, P, Fut>(
+    checklist: &mut Checklist,
+    config: &CheckConfig,
+    quote: QuoteKind,
+    mut lp_stream: L,
+    mut sol_vault_stream: V,
+    mut mint_stream: M,
+    mut poll: P,
+) -> bool
+where
+    L: Stream<Item = Response<UiAccount>> + Unpin,
+    V: Stream<Item = Response<UiAccount>> + Unpin,
+    M: Stream<Item = Response<UiAccount>> + Unpin,
+    P: FnMut() -> Fut,
+    Fut: Future<Output = ClientResult<Vec<Option<Account>>>>,
+{
+    // a deadline, not re-created per iteration or every notification would
+    // push it back
+    let timeout = tokio::time::sleep(Duration::from_secs(config.timeout_secs));
+    tokio::pin!(timeout);
+    let poll_period = Duration::from_secs(config.poll_interval_secs.max(1));
+    let mut poll_interval = tokio::time::interval_at(
+        tokio::time::Instant::now() + poll_period,
+        poll_period,
+    );
+    poll_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
         tokio::select! {
             lp_log = lp_stream.next(), if !checklist.lp_burnt => {
                 let lp_log = lp_log.unwrap();
-                debug!("{} {} lp log received", lp_log.context.slot, &mint);
-                if let UiAccountData::Binary(data, UiAccountEncoding::Base64) = lp_log.value.data {
-                    let log_data = base64::prelude::BASE64_STANDARD.decode(data).unwrap();
-                    if log_data.is_empty() {
-                        warn!("empty log data");
-                        continue;
-                    }
-                    let lp_account = spl_token::state::Account::unpack(&log_data).unwrap();
-                    if lp_account.amount == 0 {
-                        checklist.lp_burnt = true;
-                    };
-                }
+                debug!(slot = lp_log.context.slot, "lp log received");
+                on_lp_notification(checklist, &lp_log.value);
             }
             vault_log = sol_vault_stream.next() => {
-                // the amount of sol is there as lamports straight in the log
                 let vault_log = vault_log.unwrap();
-                debug!("{} {} vault log received", vault_log.context.slot, &mint);
-                let sol_pooled = vault_log.value.lamports as f64 / 10u64.pow(9) as f64;
-                checklist.sol_pooled = sol_pooled;
-                if sol_pooled < 6.9 {
-                    break false;
-                }
+                debug!(slot = vault_log.context.slot, "vault log received");
                 // this might run for a long time, if no rugpull happens but the
                 // mint authority is not renounced, worth adding a timeout
-                if checklist.all_clear() {
-                    break true;
+                if let Some(ok) = on_vault_notification(checklist, config, quote, &vault_log.value) {
+                    return ok;
                 }
             }
             mint_log = mint_stream.next(), if !checklist.freeze_authority_renounced || !checklist.mint_authority_renounced => {
                 let mint_log = mint_log.unwrap();
-                debug!("{} {} mint log received", mint_log.context.slot, &mint);
-                if let UiAccountData::Binary(data, UiAccountEncoding::Base64) = mint_log.value.data {
-                    let log_data = base64::prelude::BASE64_STANDARD.decode(data).unwrap();
-                    let mint_data = Mint::unpack(&log_data).unwrap();
-                    if mint_data.mint_authority.is_none() {
-                        checklist.mint_authority_renounced = true;
+                debug!(slot = mint_log.context.slot, "mint log received");
+                on_mint_notification(checklist, &mint_log.value);
+            }
+            _ = poll_interval.tick() => {
+                let accounts = match poll().await {
+                    Ok(accounts) => accounts,
+                    Err(e) => {
+                        warn!(?e, "vault and mint poll failed");
+                        continue;
+                    }
+                };
+                debug!("vault and mint polled");
+                if let Some(Some(mint_account)) = accounts.get(1) {
+                    match unpack_mint_info(&mint_account.owner, &mint_account.data) {
+                        Ok(info) => checklist.apply_mint_info(&info),
+                        Err(e) => warn!(?e, "failed to unpack polled mint"),
                     }
-                    if mint_data.freeze_authority.is_none() {
-                        checklist.freeze_authority_renounced = true;
+                }
+                if let Some(Some(vault)) = accounts.first() {
+                    if let Some(ok) = on_vault_update(checklist, config, quote, vault.lamports, &vault.data) {
+                        return ok;
                     }
                 }
             }
-            _ = tokio::time::sleep(tokio::time::Duration::from_secs(900)) => {
+            _ = &mut timeout => {
                 info!("timeout");
                 checklist.timeout = true;
-                break false;
+                return false;
             }
         }
-    };
+    }
+}
+
+/// is_pump_fun tells pump fun tokens apart by the mint suffix or, for the
+/// ones without it, by their bonding curve; a single account read at most
+async fn is_pump_fun(
+    rpc_client: &RpcClient,
+    mint: &Pubkey,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    if check_if_pump_fun(mint).await? {
+        return Ok(true);
+    }
+    let bonding_curve = derive_pump_accounts(mint)?.bonding_curve;
+    let curve = rpc_client
+        .get_account_with_commitment(&bonding_curve, rpc_client.commitment())
+        .await?
+        .value;
+    Ok(curve.is_some_and(|curve| curve.owner == PUMP_FUN_PROGRAM))
+}
 
-    mint_unsub().await;
-    lp_unsub().await;
-    sol_vault_unsub().await;
+/// early_decision is the outcome of the checks known before any pool state is
+/// read, None if the pool has to be checked
+fn early_decision(
+    checklist: &Checklist,
+    config: &CheckConfig,
+    ignore_non_pump_funs: bool,
+) -> Option<bool> {
+    if checklist.is_pump_fun {
+        return config.decide_pump_fun_early.then_some(true);
+    }
+    if ignore_non_pump_funs {
+        // ignoring any other tokens, way too many scams (noise to profit ratio
+        // is too low), even with higher, centralized supply
+        // only profit opp is a fair launch of a larger token, but this happens rarely
+        // current strat is to flip pumps for 30-50% profit
+        return Some(false);
+    }
+    None
+}
 
-    Ok((ok, checklist))
+/// on_vault_update records the pooled liquidity and returns the decision if
+/// it can be made, too little liquidity or a drain from the peak fails right
+/// away
+pub(crate) fn on_vault_update(
+    checklist: &mut Checklist,
+    config: &CheckConfig,
+    quote: QuoteKind,
+    lamports: u64,
+    data: &[u8],
+) -> Option<bool> {
+    let sol_pooled =
+        match quote_pooled(quote, lamports, data, config.sol_price) {
+            Ok(sol_pooled) => sol_pooled,
+            Err(e) => {
+                // not recorded as an empty pool, the next update may decode
+                warn!(?e, "failed to value the quote vault");
+                return None;
+            }
+        };
+    checklist.record_sol_pooled(sol_pooled);
+    if !config.has_min_liquidity(sol_pooled) || checklist.is_drained(config) {
+        return Some(false);
+    }
+    checklist.is_safe(config).then_some(true)
 }
 
+const SNAPSHOT_USER_LP_TOKEN: usize = 0;
+const SNAPSHOT_MINT: usize = 1;
+const SNAPSHOT_SOL_VAULT: usize = 2;
+const SNAPSHOT_AMM_POOL: usize = 3;
+
+/// the snapshot accounts waited for, as (index, name, required); right
+/// after the pool is created they may not be visible yet; the lp token
+/// account is gone once it is closed after a burn and the checks go on
+/// without the amm pool (the market is then unknown)
+const SNAPSHOT_EXPECTED: [(usize, &str, bool); 4] = [
+    (SNAPSHOT_USER_LP_TOKEN, "user lp token", false),
+    (SNAPSHOT_MINT, "mint", true),
+    (SNAPSHOT_SOL_VAULT, "sol vault", true),
+    (SNAPSHOT_AMM_POOL, "amm pool", false),
+];
+
+/// missing_snapshot_accounts names the expected accounts absent from the
+/// snapshot, only the required ones if `required_only`
+pub fn missing_snapshot_accounts(
+    snapshot: &[Option<Account>],
+    required_only: bool,
+) -> Vec<&'static str> {
+    SNAPSHOT_EXPECTED
+        .iter()
+        .filter(|(_, _, required)| *required || !required_only)
+        .filter(|(index, _, _)| {
+            snapshot.get(*index).map_or(true, Option::is_none)
+        })
+        .map(|(_, name, _)| *name)
+        .collect()
+}
+
+/// fetch_snapshot re-queries the snapshot until all the expected accounts
+/// are present or `attempts` run out, then fails only if a required one is
+/// still missing
+pub async fn fetch_snapshot<F, Fut>(
+    mut fetch: F,
+    attempts: usize,
+    retry_delay: Duration,
+) -> Result<Vec<Option<Account>>, Box<dyn std::error::Error>>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ClientResult<Vec<Option<Account>>>>,
+{
+    let mut snapshot = vec![];
+    for attempt in 0..attempts.max(1) {
+        if attempt > 0 {
+            tokio::time::sleep(retry_delay).await;
+        }
+        snapshot = fetch().await?;
+        let missing = missing_snapshot_accounts(&snapshot, false);
+        if missing.is_empty() {
+            return Ok(snapshot);
+        }
+        debug!(?missing, attempt, "snapshot accounts not visible yet");
+    }
+    let missing = missing_snapshot_accounts(&snapshot, true);
+    if !missing.is_empty() {
+        return Err(format!(
+            "Could not get account(s): {}",
+            missing.join(", ")
+        )
+        .into());
+    }
+    Ok(snapshot)
+}
+
+/// snapshot_pubkeys lists the accounts fetched for the initial state of the
+/// pool, indexed by the SNAPSHOT_* constants
+pub fn snapshot_pubkeys(
+    accounts: &PoolAccounts,
+    mint: &Pubkey,
+) -> Result<[Pubkey; 4], Box<dyn std::error::Error>> {
+    let (sol_vault, _, _) =
+        quote_side(accounts).ok_or("No quote mint in pool")?;
+    Ok([accounts.user_lp_token, *mint, sol_vault, accounts.amm_pool])
+}
+
+/// quote_side returns the quote vault of the pool, the traded mint and how the
+/// quote is valued, None if neither side is one of the QUOTE_MINTS
+pub fn quote_side(
+    accounts: &PoolAccounts,
+) -> Option<(Pubkey, Pubkey, QuoteKind)> {
+    if let Some(quote) = classify_quote(&accounts.coin_mint) {
+        return Some((
+            accounts.pool_coin_token_account,
+            accounts.pc_mint,
+            quote,
+        ));
+    }
+    classify_quote(&accounts.pc_mint).map(|quote| {
+        (accounts.pool_pc_token_account, accounts.coin_mint, quote)
+    })
+}
+
+/// checked_quote_side is quote_side for a pool the checks can value, a
+/// stablecoin quoted pool can't be checked without the sol price
+pub fn checked_quote_side(
+    accounts: &PoolAccounts,
+    config: &CheckConfig,
+) -> Result<(Pubkey, Pubkey, QuoteKind), Box<dyn std::error::Error>> {
+    let (sol_vault, mint, quote) =
+        quote_side(accounts).ok_or("No quote mint in pool")?;
+    if quote == QuoteKind::Usd && config.sol_price.is_none() {
+        return Err("Usd quoted pool but no sol price is configured".into());
+    }
+    Ok((sol_vault, mint, quote))
+}
+
+/// quote_pooled values the quote vault in sol; a wsol vault holds it as
+/// lamports, a stablecoin vault holds the token amount in its data and needs
+/// the sol price to be converted
+pub fn quote_pooled(
+    quote: QuoteKind,
+    lamports: u64,
+    data: &[u8],
+    sol_price: Option<f64>,
+) -> Result<f64, Box<dyn std::error::Error>> {
+    match quote {
+        QuoteKind::Sol => Ok(lamports_to_sol(lamports)),
+        QuoteKind::Usd => {
+            let vault = spl_token::state::Account::unpack(data)?;
+            let usd =
+                ui_amount(vault.amount, constants::USD_QUOTE_DECIMALS as u8);
+            Ok(
+                usd / sol_price
+                    .ok_or("No sol price to value the usd quote")?,
+            )
+        }
+    }
+}
+
+/// parse_accounts finds the raydium initialize2 instruction and reads the
+/// pool accounts from its 21 account layout
+///
+/// jsonParsed transactions come with the accounts already resolved by the
+/// node, json (raw) ones only carry indexes which in v0 transactions can
+/// point past the static keys into the addresses loaded from lookup tables
 pub fn parse_accounts(
     tx: &EncodedConfirmedTransactionWithStatusMeta,
 ) -> Result<PoolAccounts, Box<dyn std::error::Error>> {
     if let EncodedTransaction::Json(ui_tx) = &tx.transaction.transaction {
-        if let UiMessage::Parsed(UiParsedMessage {
-            account_keys: _,
-            instructions,
-            recent_blockhash: _,
-            address_table_lookups: _,
-        }) = &ui_tx.message
-        {
-            for ix in instructions.iter() {
-                if let UiInstruction::Parsed(
-                    UiParsedInstruction::PartiallyDecoded(
-                        UiPartiallyDecodedInstruction {
-                            accounts,
-                            program_id,
-                            data: _,
-                            stack_height: _,
-                        },
-                    ),
-                ) = ix
-                {
-                    if accounts.len() == 21
-                        && program_id
-                            == &constants::RAYDIUM_LIQUIDITY_POOL_V4_PUBKEY
-                                .to_string()
+        match &ui_tx.message {
+            UiMessage::Parsed(UiParsedMessage { instructions, .. }) => {
+                for ix in instructions.iter() {
+                    if let UiInstruction::Parsed(
+                        UiParsedInstruction::PartiallyDecoded(
+                            UiPartiallyDecodedInstruction {
+                                accounts,
+                                program_id,
+                                ..
+                            },
+                        ),
+                    ) = ix
                     {
-                        let amm_pool = Pubkey::from_str(&accounts[4]).unwrap();
-                        let lp_mint = Pubkey::from_str(&accounts[7]).unwrap();
-                        let coin_mint =
-                            Pubkey::from_str(&accounts[8]).unwrap();
-                        let pc_mint = Pubkey::from_str(&accounts[9]).unwrap();
-                        let pool_coin_token_account =
-                            Pubkey::from_str(&accounts[10]).unwrap();
-                        let pool_pc_token_account =
-                            Pubkey::from_str(&accounts[11]).unwrap();
-                        let user_wallet =
-                            Pubkey::from_str(&accounts[17]).unwrap();
-                        let user_token_coin =
-                            Pubkey::from_str(&accounts[18]).unwrap();
-                        let user_token_pc =
-                            Pubkey::from_str(&accounts[19]).unwrap();
-                        let user_lp_token =
-                            Pubkey::from_str(&accounts[20]).unwrap();
-
-                        return Ok(PoolAccounts {
-                            amm_pool,
-                            lp_mint,
-                            coin_mint,
-                            pc_mint,
-                            pool_coin_token_account,
-                            pool_pc_token_account,
-                            user_wallet,
-                            user_token_coin,
-                            user_token_pc,
-                            user_lp_token,
-                        });
+                        if is_raydium_init(program_id, accounts.len()) {
+                            return pool_accounts_from_keys(accounts);
+                        }
                     }
                 }
             }
+            UiMessage::Raw(UiRawMessage {
+                account_keys,
+                instructions,
+                ..
+            }) => {
+                let account_keys = resolve_account_keys(account_keys, tx);
+                for ix in instructions.iter() {
+                    let program_id = account_keys
+                        .get(ix.program_id_index as usize)
+                        .ok_or("Program id index out of range")?;
+                    if !is_raydium_init(program_id, ix.accounts.len()) {
+                        continue;
+                    }
+                    let accounts = ix
+                        .accounts
+                        .iter()
+                        .map(|index| {
+                            account_keys.get(*index as usize).cloned().ok_or(
+                                format!(
+                                    "Account index {} out of range",
+                                    index
+                                ),
+                            )
+                        })
+                        .collect::<Result<Vec<String>, String>>()?;
+                    return pool_accounts_from_keys(&accounts);
+                }
+            }
         }
     }
     Err("Could not parse accounts".into())
 }
 
+fn is_raydium_init(program_id: &str, accounts_len: usize) -> bool {
+    accounts_len == 21
+        && program_id
+            == constants::RAYDIUM_LIQUIDITY_POOL_V4_PUBKEY.to_string()
+}
+
+/// resolve_account_keys returns the static keys followed by the loaded
+/// writable and then readonly addresses, the order the indexes refer to
+fn resolve_account_keys(
+    static_keys: &[String],
+    tx: &EncodedConfirmedTransactionWithStatusMeta,
+) -> Vec<String> {
+    let mut account_keys = static_keys.to_vec();
+    if let Some(meta) = &tx.transaction.meta {
+        if let OptionSerializer::Some(loaded) = &meta.loaded_addresses {
+            account_keys.extend(loaded.writable.iter().cloned());
+            account_keys.extend(loaded.readonly.iter().cloned());
+        }
+    }
+    account_keys
+}
+
+fn pool_accounts_from_keys(
+    accounts: &[String],
+) -> Result<PoolAccounts, Box<dyn std::error::Error>> {
+    Ok(PoolAccounts {
+        amm_pool: Pubkey::from_str(&accounts[4])?,
+        lp_mint: Pubkey::from_str(&accounts[7])?,
+        coin_mint: Pubkey::from_str(&accounts[8])?,
+        pc_mint: Pubkey::from_str(&accounts[9])?,
+        pool_coin_token_account: Pubkey::from_str(&accounts[10])?,
+        pool_pc_token_account: Pubkey::from_str(&accounts[11])?,
+        user_wallet: Pubkey::from_str(&accounts[17])?,
+        user_token_coin: Pubkey::from_str(&accounts[18])?,
+        user_token_pc: Pubkey::from_str(&accounts[19])?,
+        user_lp_token: Pubkey::from_str(&accounts[20])?,
+    })
+}
+
 #[cfg(test)]
 mod tests {
+    use solana_sdk::program_error::ProgramError;
     use solana_sdk::program_pack::Pack;
+    use spl_token_2022::extension::ExtensionType;
 
     #[tokio::test]
     async fn test_run_checks() {
         let signature = "2cbovtqtKSGgEcrTkg2AV4h5aC3mRt3QfrWwnn4dccAehjMfptMCLxRpdWsRJ2XWafCuqcR6AWQC1ieq4E13xrap".to_string();
-        super::run_checks(signature).await.unwrap();
+        let rpc_pool = super::RpcPool::from_env(
+            solana_sdk::commitment_config::CommitmentConfig::processed(),
+        );
+        super::run_checks(signature, &rpc_pool).await.unwrap();
+    }
+
+    #[test]
+    fn test_checklist_is_safe() {
+        use super::{CheckConfig, Checklist};
+
+        let config = CheckConfig::default();
+        let allow_pump_fun = CheckConfig {
+            allow_pump_fun: true,
+            ..Default::default()
+        };
+        let lenient = CheckConfig {
+            require_lp_burnt: false,
+            require_mint_renounced: false,
+            require_freeze_renounced: false,
+            ..Default::default()
+        };
+
+        // (pump fun, lp burnt, mint, freeze, timeout, sol pooled, config, safe)
+        let cases = [
+            (false, true, true, true, false, 10.0, &config, true),
+            (true, true, true, true, false, 10.0, &config, false),
+            (true, true, true, true, false, 10.0, &allow_pump_fun, true),
+            (false, false, true, true, false, 10.0, &config, false),
+            (false, true, false, true, false, 10.0, &config, false),
+            (false, true, true, false, false, 10.0, &config, false),
+            (false, true, true, true, true, 10.0, &config, false),
+            (false, true, true, true, false, 6.8, &config, false),
+            (false, true, true, true, false, 6.9, &config, true),
+            (false, false, false, false, false, 10.0, &lenient, true),
+            (false, false, false, false, true, 10.0, &lenient, false),
+            (false, false, false, false, false, 1.0, &lenient, false),
+        ];
+        for (i, (pump, lp, mint, freeze, timeout, sol, config, safe)) in
+            cases.into_iter().enumerate()
+        {
+            let checklist = Checklist {
+                is_pump_fun: pump,
+                lp_burnt: lp,
+                mint_authority_renounced: mint,
+                freeze_authority_renounced: freeze,
+                timeout,
+                sol_pooled: sol,
+                ..Default::default()
+            };
+            assert_eq!(checklist.is_safe(config), safe, "case {}", i);
+        }
+    }
+
+    #[test]
+    fn test_min_liquidity_usd() {
+        use super::CheckConfig;
+
+        let at_price = |sol_price| CheckConfig {
+            min_liquidity_usd: Some(1500.0),
+            sol_price,
+            ..Default::default()
+        };
+        // same 10 sol pooled, $2000 at 200 but $1000 at 100
+        assert!(at_price(Some(200.0)).has_min_liquidity(10.0));
+        assert!(!at_price(Some(100.0)).has_min_liquidity(10.0));
+        // no price to evaluate the usd threshold at
+        assert!(!at_price(None).has_min_liquidity(10.0));
+        // sol threshold when no usd one is set
+        assert!(CheckConfig::default().has_min_liquidity(6.9));
+        assert!(!CheckConfig::default().has_min_liquidity(6.8));
+    }
+
+    #[test]
+    fn test_subscription_configs() {
+        use solana_account_decoder::UiAccountEncoding;
+        use solana_sdk::commitment_config::CommitmentConfig;
+
+        let config = super::CheckConfig {
+            lp_commitment: CommitmentConfig::confirmed(),
+            vault_commitment: CommitmentConfig::processed(),
+            mint_commitment: CommitmentConfig::finalized(),
+            ..Default::default()
+        };
+        let configs = config.subscription_configs();
+        assert_eq!(configs.lp.commitment, Some(CommitmentConfig::confirmed()));
+        assert_eq!(
+            configs.vault.commitment,
+            Some(CommitmentConfig::processed())
+        );
+        assert_eq!(
+            configs.mint.commitment,
+            Some(CommitmentConfig::finalized())
+        );
+        assert_eq!(configs.lp.encoding, Some(UiAccountEncoding::Base64));
+        assert_eq!(configs.vault.encoding, Some(UiAccountEncoding::Base64));
+        assert_eq!(configs.mint.encoding, Some(UiAccountEncoding::Base64));
     }
 
     #[test]
@@ -410,4 +1221,671 @@ mod tests {
         )
         .unwrap();
     }
+
+    /// make_token_2022_mint packs a renounced mint carrying `extension`,
+    /// only the extensions unpack_mint_info flags are supported
+    fn make_token_2022_mint(
+        extension: ExtensionType,
+    ) -> Result<Vec<u8>, ProgramError> {
+        use solana_sdk::program_option::COption;
+        use spl_token_2022::extension::{
+            permanent_delegate::PermanentDelegate,
+            transfer_fee::TransferFeeConfig, transfer_hook::TransferHook,
+            StateWithExtensionsMut,
+        };
+        use spl_token_2022::state::Mint;
+
+        let len =
+            ExtensionType::try_calculate_account_len::<Mint>(&[extension])?;
+        let mut data = vec![0; len];
+        let mut state =
+            StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data)?;
+        match extension {
+            ExtensionType::TransferFeeConfig => {
+                state.init_extension::<TransferFeeConfig>(true)?;
+            }
+            ExtensionType::TransferHook => {
+                state.init_extension::<TransferHook>(true)?;
+            }
+            ExtensionType::PermanentDelegate => {
+                state.init_extension::<PermanentDelegate>(true)?;
+            }
+            _ => return Err(ProgramError::InvalidArgument),
+        }
+        state.base = Mint {
+            mint_authority: COption::None,
+            supply: 1_000_000_000,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        };
+        state.pack_base();
+        state.init_account_type()?;
+        Ok(data)
+    }
+
+    #[test]
+    fn test_unpack_mint_info_token_2022_extensions() {
+        use super::{CheckConfig, Checklist, MintInfo};
+
+        let cases = [
+            (
+                ExtensionType::TransferFeeConfig,
+                MintInfo {
+                    has_transfer_fee: true,
+                    ..Default::default()
+                },
+            ),
+            (
+                ExtensionType::TransferHook,
+                MintInfo {
+                    has_transfer_hook: true,
+                    ..Default::default()
+                },
+            ),
+            (
+                ExtensionType::PermanentDelegate,
+                MintInfo {
+                    has_permanent_delegate: true,
+                    ..Default::default()
+                },
+            ),
+        ];
+        for (extension, expected) in cases {
+            let data = make_token_2022_mint(extension).unwrap();
+            let info =
+                super::unpack_mint_info(&spl_token_2022::ID, &data).unwrap();
+            assert_eq!(
+                info,
+                MintInfo {
+                    mint_authority_renounced: true,
+                    freeze_authority_renounced: true,
+                    ..expected
+                },
+                "{:?}",
+                extension
+            );
+
+            let mut checklist = Checklist {
+                lp_burnt: true,
+                sol_pooled: 10.0,
+                ..Default::default()
+            };
+            checklist.apply_mint_info(&info);
+            assert!(!checklist.is_safe(&CheckConfig::default()));
+            assert!(checklist.is_safe(&CheckConfig {
+                reject_token_2022_extensions: false,
+                ..Default::default()
+            }));
+        }
+    }
+
+    #[test]
+    fn test_unpack_mint_info_token_2022_fixture() {
+        use solana_account_decoder::UiAccount;
+        use solana_sdk::account::Account;
+
+        // a transfer fee mint with on-chain metadata, renounced
+        let account: UiAccount = serde_json::from_reader(
+            std::fs::File::open("mock/token_2022_mint.json").unwrap(),
+        )
+        .unwrap();
+        let account = account.decode::<Account>().unwrap();
+        assert_eq!(account.owner, spl_token_2022::ID);
+        let info =
+            super::unpack_mint_info(&account.owner, &account.data).unwrap();
+        assert_eq!(
+            info,
+            super::MintInfo {
+                mint_authority_renounced: true,
+                freeze_authority_renounced: true,
+                has_transfer_fee: true,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_unpack_mint_info_spl_token() {
+        let data = "1111Dk7tnoddMvATwtoKYbhf9c51kPxy4Siv5Ubb93zssnpGt5j2ELBnz1TT5a7jGAeKE9zEsoFAY5kByXAhfi8EYHCg3ChYCmZ6rnyNYPxQrK".to_string();
+        let info = super::unpack_mint_info(
+            &spl_token::ID,
+            bs58::decode(data).into_vec().unwrap().as_slice(),
+        )
+        .unwrap();
+        assert!(!info.has_transfer_fee);
+        assert!(!info.has_transfer_hook);
+        assert!(!info.has_permanent_delegate);
+    }
+
+    #[test]
+    fn test_snapshot_pubkeys() {
+        let tx = serde_json::from_reader(
+            std::fs::File::open("mock/raydium_v0_lut_tx.json").unwrap(),
+        )
+        .unwrap();
+        let accounts = super::parse_accounts(&tx).unwrap();
+        let pubkeys =
+            super::snapshot_pubkeys(&accounts, &accounts.coin_mint).unwrap();
+        // pc is sol, so the sol vault is the pc token account
+        assert_eq!(
+            pubkeys,
+            [
+                accounts.user_lp_token,
+                accounts.coin_mint,
+                accounts.pool_pc_token_account,
+                accounts.amm_pool,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_market_disabled() {
+        // head padding and flags of an openbook market, the rest of the
+        // market state doesn't matter here
+        let market = |flags: u64| {
+            let mut data = vec![0u8; 388];
+            data[..5].copy_from_slice(b"serum");
+            data[5..13].copy_from_slice(&flags.to_le_bytes());
+            data[381..].copy_from_slice(b"padding");
+            data
+        };
+        assert!(!super::is_market_disabled(&market(0b11)).unwrap());
+        assert!(super::is_market_disabled(&market(0b11 | 1 << 7)).unwrap());
+        // open orders account, not a market
+        assert!(super::is_market_disabled(&market(0b101)).is_err());
+        assert!(super::is_market_disabled(&[0u8; 8]).is_err());
+
+        let config = super::CheckConfig {
+            require_lp_burnt: false,
+            require_mint_renounced: false,
+            require_freeze_renounced: false,
+            min_sol_pooled: 0.0,
+            ..Default::default()
+        };
+        let mut checklist = super::Checklist::default();
+        assert!(checklist.is_safe(&config));
+        checklist.market_disabled = true;
+        assert!(!checklist.is_safe(&config));
+    }
+
+    #[tokio::test]
+    async fn test_pump_fun_decided_before_subscribing() {
+        use solana_client::nonblocking::rpc_client::RpcClient;
+        use solana_sdk::pubkey::Pubkey;
+        use std::str::FromStr;
+
+        // neither the rpc nor the websocket are reachable, a pump fun is
+        // settled by its mint alone
+        let rpc_client = RpcClient::new("http://127.0.0.1:1".to_string());
+        let accounts = super::PoolAccounts {
+            coin_mint: Pubkey::from_str(
+                "FAJVRnNHuwozDi5UL8guMyobveadXDFxeikvN4Hupump",
+            )
+            .unwrap(),
+            pc_mint: crate::constants::SOLANA_PROGRAM_ID,
+            ..Default::default()
+        };
+        let config = super::CheckConfig {
+            decide_pump_fun_early: true,
+            ..Default::default()
+        };
+        let (ok, checklist) =
+            super::run_pool_checks(&rpc_client, accounts, 1, true, &config)
+                .await
+                .unwrap();
+        assert!(ok);
+        assert!(checklist.is_pump_fun);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_snapshot_retries_missing_accounts() {
+        use solana_sdk::account::Account;
+        use std::cell::Cell;
+        use std::time::Duration;
+
+        let snapshot = |with_lp: bool| {
+            let mut snapshot = vec![Some(Account::default()); 4];
+            if !with_lp {
+                snapshot[super::SNAPSHOT_USER_LP_TOKEN] = None;
+            }
+            snapshot
+        };
+
+        // the lp account only shows up on the second query
+        let calls = Cell::new(0);
+        let fetched = super::fetch_snapshot(
+            || {
+                calls.set(calls.get() + 1);
+                let snapshot = snapshot(calls.get() > 1);
+                async move { Ok(snapshot) }
+            },
+            3,
+            Duration::ZERO,
+        )
+        .await
+        .unwrap();
+        assert_eq!(calls.get(), 2);
+        assert!(fetched[0].is_some());
+
+        // closed after a burn, the checks go on without it
+        let calls = Cell::new(0);
+        let fetched = super::fetch_snapshot(
+            || {
+                calls.set(calls.get() + 1);
+                let snapshot = snapshot(false);
+                async move { Ok(snapshot) }
+            },
+            2,
+            Duration::ZERO,
+        )
+        .await
+        .unwrap();
+        assert_eq!(calls.get(), 2);
+        assert!(fetched[super::SNAPSHOT_USER_LP_TOKEN].is_none());
+
+        // the mint and the vault are required
+        let err = super::fetch_snapshot(
+            || {
+                let mut snapshot = snapshot(false);
+                snapshot[super::SNAPSHOT_SOL_VAULT] = None;
+                async move { Ok(snapshot) }
+            },
+            2,
+            Duration::ZERO,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.to_string(), "Could not get account(s): sol vault");
+    }
+
+    #[test]
+    fn test_quote_pooled() {
+        use crate::constants::QuoteKind;
+
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            amount: 1_000_000_000,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+
+        // a usdc vault of 1000 usd at 200 usd per sol
+        assert_eq!(
+            super::quote_pooled(QuoteKind::Usd, 2_039_280, &data, Some(200.0))
+                .unwrap(),
+            5.0
+        );
+        assert!(super::quote_pooled(QuoteKind::Usd, 2_039_280, &data, None)
+            .is_err());
+        assert!(
+            super::quote_pooled(QuoteKind::Usd, 0, &[], Some(200.0)).is_err()
+        );
+        assert_eq!(
+            super::quote_pooled(QuoteKind::Sol, 2_000_000_000, &[], None)
+                .unwrap(),
+            2.0
+        );
+
+        // a usdc quoted pool is only checked with a sol price to value it
+        let accounts = super::PoolAccounts {
+            coin_mint: solana_sdk::pubkey::Pubkey::new_unique(),
+            pc_mint: crate::constants::USDC_TOKEN_PUBKEY,
+            ..Default::default()
+        };
+        let config = super::CheckConfig::default();
+        assert!(super::checked_quote_side(&accounts, &config).is_err());
+        let config = super::CheckConfig {
+            sol_price: Some(200.0),
+            ..config
+        };
+        let (vault, mint, quote) =
+            super::checked_quote_side(&accounts, &config).unwrap();
+        assert_eq!(vault, accounts.pool_pc_token_account);
+        assert_eq!(mint, accounts.coin_mint);
+        assert_eq!(quote, QuoteKind::Usd);
+    }
+
+    #[tokio::test]
+    async fn test_poll_decides_without_notifications() {
+        use crate::constants::QuoteKind;
+        use solana_account_decoder::UiAccount;
+        use solana_client::rpc_response::Response;
+        use solana_sdk::{account::Account, native_token::LAMPORTS_PER_SOL};
+
+        let mut mint_data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            supply: 1_000_000_000,
+            decimals: 6,
+            is_initialized: true,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut mint_data);
+        let mint = Account {
+            owner: spl_token::ID,
+            data: mint_data,
+            ..Default::default()
+        };
+        let vault = Account {
+            lamports: 10 * LAMPORTS_PER_SOL,
+            ..Default::default()
+        };
+
+        let config = super::CheckConfig {
+            poll_interval_secs: 1,
+            timeout_secs: 5,
+            ..Default::default()
+        };
+        let mut checklist = super::Checklist {
+            lp_burnt: true,
+            ..Default::default()
+        };
+        // the streams never deliver, only the poll can reach the decision
+        let silent = || futures_util::stream::pending::<Response<UiAccount>>();
+        let ok = super::watch_checks(
+            &mut checklist,
+            &config,
+            QuoteKind::Sol,
+            silent(),
+            silent(),
+            silent(),
+            || {
+                let accounts = vec![Some(vault.clone()), Some(mint.clone())];
+                async move { Ok(accounts) }
+            },
+        )
+        .await;
+
+        assert!(ok);
+        assert!(!checklist.timeout);
+        assert!(checklist.mint_authority_renounced);
+        assert!(checklist.freeze_authority_renounced);
+        assert_eq!(checklist.sol_pooled, 10.0);
+    }
+
+    #[test]
+    fn test_pending_notifications_win_over_snapshot() {
+        use crate::constants::QuoteKind;
+        use futures_util::StreamExt;
+        use solana_account_decoder::{UiAccount, UiAccountEncoding};
+        use solana_client::rpc_response::{Response, RpcResponseContext};
+        use solana_sdk::{
+            account::Account, native_token::LAMPORTS_PER_SOL, pubkey::Pubkey,
+        };
+
+        fn notifications(
+            accounts: Vec<Account>,
+        ) -> impl futures_util::Stream<Item = Response<UiAccount>> + Unpin
+        {
+            let notifications = accounts.into_iter().map(|account| Response {
+                context: RpcResponseContext {
+                    slot: 2,
+                    api_version: None,
+                },
+                value: UiAccount::encode(
+                    &Pubkey::new_unique(),
+                    &account,
+                    UiAccountEncoding::Base64,
+                    None,
+                    None,
+                ),
+            });
+            // still subscribed, nothing else received yet
+            futures_util::stream::iter(notifications)
+                .chain(futures_util::stream::pending())
+        }
+        let vault = |sol: u64| Account {
+            lamports: sol * LAMPORTS_PER_SOL,
+            ..Default::default()
+        };
+        let mut lp_data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            amount: 0,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut lp_data);
+        let burnt_lp = Account {
+            owner: spl_token::ID,
+            data: lp_data,
+            ..Default::default()
+        };
+
+        let config = super::CheckConfig::default();
+        // the snapshot saw 20 sol and the lp not burnt yet
+        let snapshot_checklist = || {
+            let mut checklist = super::Checklist::default();
+            checklist.record_sol_pooled(20.0);
+            checklist
+        };
+
+        // the lp was burnt while the snapshot was in flight, nothing to
+        // decide from the vault
+        let mut checklist = snapshot_checklist();
+        let decision = super::apply_pending_notifications(
+            &mut checklist,
+            &config,
+            QuoteKind::Sol,
+            &mut notifications(vec![burnt_lp]),
+            &mut notifications(vec![vault(20)]),
+            &mut notifications(vec![]),
+        );
+        assert_eq!(decision, None);
+        assert!(checklist.lp_burnt);
+
+        // pulled meanwhile, the snapshot's 20 sol don't count
+        let mut checklist = snapshot_checklist();
+        let decision = super::apply_pending_notifications(
+            &mut checklist,
+            &config,
+            QuoteKind::Sol,
+            &mut notifications(vec![]),
+            &mut notifications(vec![vault(1)]),
+            &mut notifications(vec![]),
+        );
+        assert_eq!(decision, Some(false));
+        assert_eq!(checklist.sol_pooled, 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_pump_fun_checked_when_not_decided_early() {
+        use crate::constants::QuoteKind;
+        use solana_account_decoder::UiAccount;
+        use solana_client::rpc_response::Response;
+        use solana_sdk::{account::Account, native_token::LAMPORTS_PER_SOL};
+
+        let mut checklist = super::Checklist {
+            is_pump_fun: true,
+            lp_burnt: true,
+            mint_authority_renounced: true,
+            freeze_authority_renounced: true,
+            ..Default::default()
+        };
+        // decided before the pool is even read by default
+        let config = super::CheckConfig::default();
+        assert_eq!(
+            super::early_decision(&checklist, &config, true),
+            Some(true)
+        );
+        assert_eq!(
+            super::early_decision(&checklist, &config, false),
+            Some(true)
+        );
+
+        let config = super::CheckConfig {
+            decide_pump_fun_early: false,
+            allow_pump_fun: true,
+            poll_interval_secs: 1,
+            timeout_secs: 5,
+            ..Default::default()
+        };
+        // not dropped as a non pump fun either
+        assert_eq!(super::early_decision(&checklist, &config, true), None);
+
+        let silent = || futures_util::stream::pending::<Response<UiAccount>>();
+        let poll = |lamports| {
+            let vault = Account {
+                lamports,
+                ..Default::default()
+            };
+            move || {
+                let accounts = vec![Some(vault.clone()), None];
+                async move { Ok(accounts) }
+            }
+        };
+        // the liquidity check still applies
+        let ok = super::watch_checks(
+            &mut checklist,
+            &config,
+            QuoteKind::Sol,
+            silent(),
+            silent(),
+            silent(),
+            poll(LAMPORTS_PER_SOL),
+        )
+        .await;
+        assert!(!ok);
+        assert_eq!(checklist.sol_pooled, 1.0);
+
+        let ok = super::watch_checks(
+            &mut checklist,
+            &config,
+            QuoteKind::Sol,
+            silent(),
+            silent(),
+            silent(),
+            poll(10 * LAMPORTS_PER_SOL),
+        )
+        .await;
+        assert!(ok);
+        assert!(checklist.is_pump_fun);
+    }
+
+    #[test]
+    fn test_drawdown_from_peak_fails() {
+        use crate::constants::QuoteKind;
+        use solana_sdk::native_token::LAMPORTS_PER_SOL;
+
+        let config = super::CheckConfig::default();
+        // mint not renounced yet, so only a rejection can decide
+        let mut checklist = super::Checklist {
+            lp_burnt: true,
+            ..Default::default()
+        };
+        let mut decisions = vec![];
+        // stays well above min_sol_pooled the whole time
+        for sol in [12.0, 20.0, 19.0, 17.0, 15.0, 13.0] {
+            decisions.push(super::on_vault_update(
+                &mut checklist,
+                &config,
+                QuoteKind::Sol,
+                (sol * LAMPORTS_PER_SOL as f64) as u64,
+                &[],
+            ));
+        }
+        assert_eq!(decisions, vec![None, None, None, None, None, Some(false)]);
+        assert_eq!(checklist.peak_sol_pooled, 20.0);
+        assert!((checklist.max_drawdown_pct - 35.0).abs() < 1e-9);
+        assert!(checklist.is_drained(&config));
+        assert!(!checklist.is_safe(&config));
+    }
+
+    #[test]
+    fn test_pool_accounts_from_amm() {
+        use solana_account_decoder::UiAccount;
+        use solana_sdk::{account::Account, pubkey::Pubkey};
+
+        let account: UiAccount = serde_json::from_reader(
+            std::fs::File::open("mock/raydium_amm_pool.json").unwrap(),
+        )
+        .unwrap();
+        let account = account.decode::<Account>().unwrap();
+        assert_eq!(
+            account.owner,
+            crate::constants::RAYDIUM_LIQUIDITY_POOL_V4_PUBKEY
+        );
+        let amm_pool = Pubkey::new_unique();
+        let accounts =
+            super::pool_accounts_from_amm(&amm_pool, &account.data).unwrap();
+        assert_eq!(accounts.amm_pool, amm_pool);
+        assert_eq!(
+            accounts.lp_mint.to_string(),
+            "83WevmL2JzaEvDmuJUFMxcFNnHqP4xonfvAzKmsPWjwu"
+        );
+        assert_eq!(
+            accounts.coin_mint.to_string(),
+            "ukHH6c7mMyiWCf1b9pnWe25TSpkDDt3H5pQZgZ74J82"
+        );
+        assert_eq!(
+            accounts.pc_mint.to_string(),
+            "So11111111111111111111111111111111111111112"
+        );
+        assert_eq!(
+            accounts.pool_coin_token_account.to_string(),
+            "FBba2XsQVhkoQDMfbNLVmo7dsvssdT39BMzVc2eFfE21"
+        );
+        assert_eq!(
+            accounts.pool_pc_token_account.to_string(),
+            "GuXKCb9ibwSeRSdSYqaCL3dcxBZ7jJcj6Y7rDwzmUBu9"
+        );
+        assert_eq!(accounts.user_wallet, Pubkey::default());
+        // the market is read off the same length checked account
+        let amm = super::unpack_amm(&account.data).unwrap();
+        assert_ne!(amm.market, Pubkey::default());
+        assert!(super::unpack_amm(&account.data[..100]).is_err());
+        assert!(super::pool_accounts_from_amm(
+            &amm_pool,
+            &account.data[..100]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_accounts_v0_lookup_table() {
+        let tx = serde_json::from_reader(
+            std::fs::File::open("mock/raydium_v0_lut_tx.json").unwrap(),
+        )
+        .unwrap();
+        let accounts = super::parse_accounts(&tx).unwrap();
+        // static keys
+        assert_eq!(
+            accounts.amm_pool.to_string(),
+            "Ho8Endys75fzwxFcnp555kzm8Yg8YxTBTXMUzVupiZJZ"
+        );
+        assert_eq!(
+            accounts.lp_mint.to_string(),
+            "J3zjncU3RJLyqpyTCaJ22Wh3VN9UbgXwjejDJyeWiTrw"
+        );
+        assert_eq!(
+            accounts.coin_mint.to_string(),
+            "JAJNpxXwtmoPn3DGX5vNe2HJSaa6zLd6LLn1wiPvFnSR"
+        );
+        assert_eq!(accounts.pc_mint, crate::constants::SOLANA_PROGRAM_ID);
+        assert_eq!(
+            accounts.user_wallet.to_string(),
+            "75mcNqWEY7v1xC3JPgfUQDUhmcFiGGCPa9CaYNdVhw3u"
+        );
+        assert_eq!(
+            accounts.user_token_coin.to_string(),
+            "F25kqDotmhWsKroFq4x131jGDijC65vniXZJawHDKhEs"
+        );
+        assert_eq!(
+            accounts.user_token_pc.to_string(),
+            "4ZCBCHBzXVu7n9bfqFd7J4C9mstRv3fTGSMfHTYd1tZ1"
+        );
+        assert_eq!(
+            accounts.user_lp_token.to_string(),
+            "33METC6Tnr5W9235PQtGqPRK1YCWKyLTtQVYjHxmuG3L"
+        );
+        // loaded from the lookup table
+        assert_eq!(
+            accounts.pool_coin_token_account.to_string(),
+            "6hZbsPHhyFQ3ij75ZcQyMNhVYiaSquEtT5zGht9dtpNu"
+        );
+        assert_eq!(
+            accounts.pool_pc_token_account.to_string(),
+            "45z8PS28d1BzAzMYVDWTrop327WZy8GjjJor5jyVB4Jh"
+        );
+    }
 }
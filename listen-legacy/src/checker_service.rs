@@ -1,16 +1,17 @@
 use crate::{
     buyer_service::BuyRequest,
-    checker::{Checklist, PoolAccounts, _run_checks},
+    checker::{CheckConfig, Checklist, PoolAccounts, _run_checks},
     constants,
     http_client::HttpClient,
-    util::{env, healthz},
+    rpc_pool::RpcPool,
+    util::healthz,
 };
-use actix_web::web::Json;
+use actix_web::web::{Data, Json};
 use actix_web::{post, App, Error, HttpResponse, HttpServer, Result};
 use log::info;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
 
 #[derive(Deserialize, Serialize)]
 pub struct ChecksRequest {
@@ -32,6 +33,7 @@ pub struct TokenResult {
 #[post("/checks")]
 pub async fn handle_checks(
     checks_request: Json<ChecksRequest>,
+    rpc_pool: Data<RpcPool>,
 ) -> Result<HttpResponse, Error> {
     info!(
         "handling checks request {}",
@@ -42,15 +44,20 @@ pub async fn handle_checks(
         timestamp_received: chrono::Utc::now().to_rfc3339(),
         ..Default::default()
     };
-    let rpc_client = RpcClient::new(env("RPC_URL"));
-    let (ok, checklist) = match _run_checks(
-        &rpc_client,
-        checks_request.accounts,
-        checks_request.slot,
-        true,
-    )
-    .await
-    {
+    let rpc_client = rpc_pool.get();
+    // the checks fail for plenty of reasons besides the endpoint, only its
+    // transport errors count against it
+    let (ok, checklist) = match rpc_client.track_transport(
+        _run_checks(
+            &rpc_client,
+            checks_request.accounts,
+            checks_request.slot,
+            true,
+            &CheckConfig::default(),
+            None,
+        )
+        .await,
+    ) {
         Ok((ok, checklist)) => (ok, checklist),
         Err(e) => {
             return Ok(HttpResponse::InternalServerError().json(
@@ -97,8 +104,15 @@ pub async fn handle_checks(
 
 pub async fn run_checker_service() -> std::io::Result<()> {
     info!("Running checker service on 8079");
-    HttpServer::new(move || App::new().service(handle_checks).service(healthz))
-        .bind(("0.0.0.0", 8079))?
-        .run()
-        .await
+    // shared across requests, so concurrent checks reuse connections
+    let rpc_pool = Data::new(RpcPool::from_env(CommitmentConfig::default()));
+    HttpServer::new(move || {
+        App::new()
+            .service(handle_checks)
+            .service(healthz)
+            .app_data(rpc_pool.clone())
+    })
+    .bind(("0.0.0.0", 8079))?
+    .run()
+    .await
 }
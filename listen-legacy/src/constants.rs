@@ -11,6 +11,33 @@ pub const RAYDIUM_AUTHORITY_V4_PUBKEY: Pubkey = pubkey!("5Q544fKrFoe6tsEbD7S8Emx
 
 pub const USDC_TOKEN_PUBKEY: Pubkey = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
 
+pub const USDT_TOKEN_PUBKEY: Pubkey = pubkey!("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB");
+
+/// QuoteKind is how a quote mint is valued, off the sol price or 1:1 usd
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuoteKind {
+    Sol,
+    Usd,
+}
+
+/// QUOTE_MINTS are the mints accepted as the quote side of a pool, adding a
+/// quote currency is a matter of adding a row here
+pub const QUOTE_MINTS: &[(Pubkey, QuoteKind)] = &[
+    (SOLANA_PROGRAM_ID, QuoteKind::Sol),
+    (USDC_TOKEN_PUBKEY, QuoteKind::Usd),
+    (USDT_TOKEN_PUBKEY, QuoteKind::Usd),
+];
+
+/// both usd quotes (usdc, usdt) have 6 decimals
+pub const USD_QUOTE_DECIMALS: u32 = 6;
+
+pub fn classify_quote(mint: &Pubkey) -> Option<QuoteKind> {
+    QUOTE_MINTS
+        .iter()
+        .find(|(quote, _)| quote == mint)
+        .map(|(_, kind)| *kind)
+}
+
 pub const OPENBOOK_PROGRAM_ID: Pubkey = pubkey!("srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX");
 
 pub const FEE_PROGRAM_ID: Pubkey = pubkey!("7YttLkHDoNj9wyDur5pM1ejNaAvT9X4eqaYcHQqtj2G5");
@@ -30,3 +57,17 @@ pub fn ruggers() -> Vec<String> {
         "32A1b5pbYyqgrtcg49UfpuWmTqz9hrQeE2du91A6pxX8".to_string(),
     ]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_quote() {
+        assert_eq!(classify_quote(&SOLANA_PROGRAM_ID), Some(QuoteKind::Sol));
+        assert_eq!(classify_quote(&USDC_TOKEN_PUBKEY), Some(QuoteKind::Usd));
+        assert_eq!(classify_quote(&USDT_TOKEN_PUBKEY), Some(QuoteKind::Usd));
+        let other = pubkey!("JAJNpxXwtmoPn3DGX5vNe2HJSaa6zLd6LLn1wiPvFnSR");
+        assert_eq!(classify_quote(&other), None);
+    }
+}
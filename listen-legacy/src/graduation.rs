@@ -0,0 +1,153 @@
+use std::collections::HashSet;
+use std::error::Error;
+
+use futures_util::StreamExt;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_config::{
+    RpcTransactionLogsConfig, RpcTransactionLogsFilter,
+};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+use tracing::{debug, info, warn};
+
+use crate::checker::{parse_accounts, PoolAccounts};
+use crate::rpc_pool::RpcPool;
+use crate::util::env;
+use crate::{get_tx_async_with_client, GetTxError};
+
+/// raydium logs this for the pool init, cheap filter before fetching the tx
+pub const RAYDIUM_INIT_LOG: &str = "initialize2";
+
+/// GraduationWatcher tracks pump fun mints that have not yet completed their
+/// bonding curve and matches them against raydium pool inits, the migration
+/// creates the pool with the mint on one side and sol on the other
+#[derive(Debug, Default)]
+pub struct GraduationWatcher {
+    mints: HashSet<Pubkey>,
+}
+
+impl GraduationWatcher {
+    pub fn track(&mut self, mint: Pubkey) {
+        self.mints.insert(mint);
+    }
+
+    pub fn untrack(&mut self, mint: &Pubkey) -> bool {
+        self.mints.remove(mint)
+    }
+
+    pub fn is_tracked(&self, mint: &Pubkey) -> bool {
+        self.mints.contains(mint)
+    }
+
+    /// on_pool_init returns the pool accounts if the tx is a raydium pool init
+    /// of a tracked mint, the mint is untracked since it graduates only once
+    pub fn on_pool_init(
+        &mut self,
+        tx: &EncodedConfirmedTransactionWithStatusMeta,
+    ) -> Option<PoolAccounts> {
+        let accounts = parse_accounts(tx).ok()?;
+        [accounts.coin_mint, accounts.pc_mint]
+            .iter()
+            .find(|mint| self.mints.remove(*mint))
+            .map(|_| accounts)
+    }
+}
+
+/// watch_graduation waits for the raydium pool of the pump fun `mint` to be
+/// initialized and returns its accounts
+pub async fn watch_graduation(
+    mint: Pubkey,
+    rpc_pool: &RpcPool,
+) -> Result<PoolAccounts, Box<dyn Error>> {
+    let mut watcher = GraduationWatcher::default();
+    watcher.track(mint);
+
+    let client = PubsubClient::new(&env("WS_URL")).await?;
+    let (mut notifications, unsub) = client
+        .logs_subscribe(
+            RpcTransactionLogsFilter::Mentions(vec![mint.to_string()]),
+            RpcTransactionLogsConfig {
+                commitment: Some(CommitmentConfig::processed()),
+            },
+        )
+        .await?;
+
+    info!(%mint, "watching for graduation");
+    while let Some(log) = notifications.next().await {
+        // the bonding curve trades mention the mint too, skip those
+        if log.value.err.is_some()
+            || !log.value.logs.iter().any(|l| l.contains(RAYDIUM_INIT_LOG))
+        {
+            continue;
+        }
+        let sig = log.value.signature;
+        debug!(%mint, signature = sig, "possible pool init");
+        let rpc_client = rpc_pool.get();
+        let tx = match get_tx_async_with_client(
+            &rpc_client,
+            &sig,
+            CommitmentConfig::confirmed(),
+            5,
+        )
+        .await
+        {
+            Ok(tx) => {
+                rpc_client.report_success();
+                tx
+            }
+            Err(e) => {
+                if let GetTxError::RpcError(_) = e {
+                    rpc_client.report_error();
+                }
+                warn!(signature = sig, %e, "did not get tx in time");
+                continue;
+            }
+        };
+        if let Some(accounts) = watcher.on_pool_init(&tx) {
+            info!(%mint, pool = %accounts.amm_pool, "graduated");
+            unsub().await;
+            return Ok(accounts);
+        }
+    }
+    unsub().await;
+    Err(format!("Log stream closed before {} graduated", mint).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn load_pool_init_tx() -> EncodedConfirmedTransactionWithStatusMeta {
+        serde_json::from_reader(
+            std::fs::File::open("mock/raydium_v0_lut_tx.json").unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_graduation_watcher_fires_on_tracked_mint() {
+        let tx = load_pool_init_tx();
+        let mint =
+            Pubkey::from_str("JAJNpxXwtmoPn3DGX5vNe2HJSaa6zLd6LLn1wiPvFnSR")
+                .unwrap();
+
+        let mut watcher = GraduationWatcher::default();
+        assert!(watcher.on_pool_init(&tx).is_none());
+
+        watcher.track(Pubkey::new_unique());
+        assert!(watcher.on_pool_init(&tx).is_none());
+
+        watcher.track(mint);
+        let accounts = watcher.on_pool_init(&tx).unwrap();
+        assert_eq!(accounts.coin_mint, mint);
+        assert_eq!(
+            accounts.amm_pool.to_string(),
+            "Ho8Endys75fzwxFcnp555kzm8Yg8YxTBTXMUzVupiZJZ"
+        );
+        // fires once
+        assert!(!watcher.is_tracked(&mint));
+        assert!(watcher.on_pool_init(&tx).is_none());
+    }
+}
@@ -3,7 +3,7 @@ use std::str::FromStr;
 use crate::jito::send_jito_tx;
 use crate::pump::{
     _make_buy_ixs, get_bonding_curve, get_token_amount, make_pump_sell_ix,
-    mint_to_pump_accounts,
+    mint_to_pump_accounts, TOKEN_PROGRAM,
 };
 use crate::state::ServiceState;
 use actix_web::{
@@ -83,6 +83,7 @@ pub async fn handle_pump_buy(
         pump_accounts.associated_bonding_curve,
         token_amount,
         pump_buy_request.sol_amount,
+        TOKEN_PROGRAM,
     )
     .map_err(actix_web::error::ErrorInternalServerError)?;
 
@@ -0,0 +1,298 @@
+use std::error::Error;
+
+use raydium_library::amm;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{
+    RpcSimulateTransactionAccountsConfig, RpcSimulateTransactionConfig,
+};
+use solana_client::rpc_response::RpcSimulateTransactionResult;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_instruction::transfer;
+use solana_sdk::transaction::{
+    Transaction, TransactionError, VersionedTransaction,
+};
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use spl_associated_token_account::{
+    get_associated_token_address, get_associated_token_address_with_program_id,
+};
+use tracing::{debug, instrument};
+
+use crate::constants;
+use crate::raydium::{get_calc_result, make_compute_budget_ixs};
+
+/// lamports spent on the simulated buy, small enough to not move the price
+pub const ROUND_TRIP_LAMPORTS: u64 = 10_000_000;
+pub const ROUND_TRIP_SLIPPAGE_BPS: u64 = 500;
+/// a regular pool returns all but the fees and the price impact of the two
+/// swaps, a sell tax shows up as a much lower recovery
+pub const MIN_RECOVERED_PCT: u64 = 50;
+
+/// RoundTripResult is the outcome of a simulated buy then sell of the token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoundTripResult {
+    pub sell_ok: bool,
+    pub lamports_in: u64,
+    /// lamports the sell returned, None if it failed
+    pub lamports_recovered: Option<u64>,
+    pub sell_error: Option<String>,
+}
+
+impl RoundTripResult {
+    /// is_sellable is false for tokens whose sell fails or is taxed away
+    pub fn is_sellable(&self) -> bool {
+        self.sell_ok
+            && self.lamports_recovered.is_some_and(|recovered| {
+                recovered * 100 >= self.lamports_in * MIN_RECOVERED_PCT
+            })
+    }
+}
+
+/// simulate_round_trip simulates buying the token with ROUND_TRIP_LAMPORTS
+/// of wsol and selling all of it back in the same transaction, a honeypot
+/// passes every static check but fails (or taxes) the sell leg
+///
+/// the transaction is never signed, the payer only needs the sol for it; the
+/// token account is derived with the program owning the mint, spl token or
+/// Token-2022
+#[instrument(skip(rpc_client))]
+pub async fn simulate_round_trip(
+    rpc_client: &RpcClient,
+    mint: &Pubkey,
+    amm_pool: &Pubkey,
+    payer: &Pubkey,
+) -> Result<RoundTripResult, Box<dyn Error>> {
+    let (result, market_keys, amm_keys) =
+        get_calc_result(rpc_client, amm_pool).await?;
+    let direction = if amm_keys.amm_coin_mint == constants::SOLANA_PROGRAM_ID {
+        amm::utils::SwapDirection::Coin2PC
+    } else {
+        amm::utils::SwapDirection::PC2Coin
+    };
+    let tokens_out_min = amm::swap_with_slippage(
+        result.pool_pc_vault_amount,
+        result.pool_coin_vault_amount,
+        result.swap_fee_numerator,
+        result.swap_fee_denominator,
+        direction,
+        ROUND_TRIP_LAMPORTS,
+        true,
+        ROUND_TRIP_SLIPPAGE_BPS,
+    )?;
+
+    let token_program = rpc_client.get_account(mint).await?.owner;
+    let wsol_ata =
+        get_associated_token_address(payer, &constants::SOLANA_PROGRAM_ID);
+    let token_ata = get_associated_token_address_with_program_id(
+        payer,
+        mint,
+        &token_program,
+    );
+    let mut ixs = make_compute_budget_ixs(0, 400_000);
+    ixs.extend(wrap_sol_ixs(payer, &wsol_ata, ROUND_TRIP_LAMPORTS)?);
+    ixs.push(create_associated_token_account_idempotent(
+        payer,
+        payer,
+        mint,
+        &token_program,
+    ));
+    // the buy's minimum out is what the sell can count on holding
+    for (source, destination, amount_in, min_out) in [
+        (wsol_ata, token_ata, ROUND_TRIP_LAMPORTS, tokens_out_min),
+        (token_ata, wsol_ata, tokens_out_min, 0),
+    ] {
+        ixs.push(amm::instructions::swap(
+            &constants::RAYDIUM_LIQUIDITY_POOL_V4_PUBKEY,
+            &amm_keys,
+            &market_keys,
+            payer,
+            &source,
+            &destination,
+            amount_in,
+            min_out,
+            true,
+        )?);
+    }
+    let sell_index = (ixs.len() - 1) as u8;
+
+    let wsol_pre = match rpc_client.get_token_account_balance(&wsol_ata).await
+    {
+        Ok(balance) => balance.amount.parse()?,
+        Err(_) => 0,
+    };
+    let transaction = VersionedTransaction::from(Transaction::new_with_payer(
+        &ixs,
+        Some(payer),
+    ));
+    let simulation = rpc_client
+        .simulate_transaction_with_config(
+            &transaction,
+            RpcSimulateTransactionConfig {
+                sig_verify: false,
+                replace_recent_blockhash: true,
+                commitment: Some(CommitmentConfig::processed()),
+                accounts: Some(RpcSimulateTransactionAccountsConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    addresses: vec![wsol_ata.to_string()],
+                }),
+                ..Default::default()
+            },
+        )
+        .await?
+        .value;
+    debug!(?simulation.err, "round trip simulated");
+
+    evaluate_round_trip(&simulation, sell_index, wsol_pre, ROUND_TRIP_LAMPORTS)
+}
+
+fn wrap_sol_ixs(
+    payer: &Pubkey,
+    wsol_ata: &Pubkey,
+    lamports: u64,
+) -> Result<Vec<Instruction>, Box<dyn Error>> {
+    Ok(vec![
+        create_associated_token_account_idempotent(
+            payer,
+            payer,
+            &constants::SOLANA_PROGRAM_ID,
+            &spl_token::ID,
+        ),
+        transfer(payer, wsol_ata, lamports),
+        spl_token::instruction::sync_native(&spl_token::ID, wsol_ata)?,
+    ])
+}
+
+/// evaluate_round_trip reads the simulation of the round trip, a failure of
+/// the sell instruction is the honeypot signal while a failure anywhere
+/// before it means the round trip couldn't be judged
+pub fn evaluate_round_trip(
+    simulation: &RpcSimulateTransactionResult,
+    sell_index: u8,
+    wsol_pre: u64,
+    lamports_in: u64,
+) -> Result<RoundTripResult, Box<dyn Error>> {
+    match &simulation.err {
+        None => {}
+        Some(TransactionError::InstructionError(index, err))
+            if *index == sell_index =>
+        {
+            return Ok(RoundTripResult {
+                sell_ok: false,
+                lamports_in,
+                lamports_recovered: None,
+                sell_error: Some(err.to_string()),
+            });
+        }
+        Some(err) => {
+            return Err(format!(
+                "Round trip failed before the sell: {} {:?}",
+                err, simulation.logs
+            )
+            .into())
+        }
+    }
+
+    let account = simulation
+        .accounts
+        .as_ref()
+        .and_then(|accounts| accounts.first().cloned().flatten())
+        .ok_or("Simulation did not return the wsol account")?
+        .decode::<solana_sdk::account::Account>()
+        .ok_or("Could not decode the simulated wsol account")?;
+    let wsol_post = spl_token::state::Account::unpack(&account.data)?.amount;
+    Ok(RoundTripResult {
+        sell_ok: true,
+        lamports_in,
+        lamports_recovered: Some(wsol_post.saturating_sub(wsol_pre)),
+        sell_error: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_account_decoder::UiAccount;
+    use solana_sdk::account::Account;
+    use solana_sdk::instruction::InstructionError;
+
+    fn make_simulation(
+        err: Option<TransactionError>,
+        wsol_amount: u64,
+    ) -> RpcSimulateTransactionResult {
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint: constants::SOLANA_PROGRAM_ID,
+            amount: wsol_amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        let wsol_ata = Pubkey::new_unique();
+        RpcSimulateTransactionResult {
+            err,
+            logs: None,
+            accounts: Some(vec![Some(UiAccount::encode(
+                &wsol_ata,
+                &Account {
+                    owner: spl_token::ID,
+                    data,
+                    ..Default::default()
+                },
+                UiAccountEncoding::Base64,
+                None,
+                None,
+            ))]),
+            units_consumed: None,
+            return_data: None,
+        }
+    }
+
+    #[test]
+    fn test_round_trip_sell_fails() {
+        // a transfer hook rejecting the sell
+        let simulation = make_simulation(
+            Some(TransactionError::InstructionError(
+                7,
+                InstructionError::Custom(6000),
+            )),
+            0,
+        );
+        let result =
+            evaluate_round_trip(&simulation, 7, 0, ROUND_TRIP_LAMPORTS)
+                .unwrap();
+        assert!(!result.sell_ok);
+        assert!(!result.is_sellable());
+        assert_eq!(result.lamports_recovered, None);
+
+        // a failing buy says nothing about the token
+        let simulation = make_simulation(
+            Some(TransactionError::InstructionError(
+                6,
+                InstructionError::Custom(30),
+            )),
+            0,
+        );
+        assert!(evaluate_round_trip(&simulation, 7, 0, ROUND_TRIP_LAMPORTS)
+            .is_err());
+    }
+
+    #[test]
+    fn test_round_trip_recovered() {
+        let pre = 1_000_000;
+        let ok = make_simulation(None, pre + 9_900_000);
+        let result =
+            evaluate_round_trip(&ok, 7, pre, ROUND_TRIP_LAMPORTS).unwrap();
+        assert_eq!(result.lamports_recovered, Some(9_900_000));
+        assert!(result.is_sellable());
+
+        // the sell goes through but a 90% tax eats it
+        let taxed = make_simulation(None, pre + 990_000);
+        let result =
+            evaluate_round_trip(&taxed, 7, pre, ROUND_TRIP_LAMPORTS).unwrap();
+        assert!(result.sell_ok);
+        assert!(!result.is_sellable());
+    }
+}
@@ -205,12 +205,16 @@ pub mod ata;
 pub mod blockhash;
 pub mod buyer;
 pub mod buyer_service;
+pub mod check_manager;
+pub mod check_multiplexer;
 pub mod checker;
 pub mod checker_service;
 pub mod collector;
 pub mod constants;
 pub mod execute;
+pub mod graduation;
 pub mod handlers;
+pub mod honeypot;
 pub mod http_client;
 pub mod jito;
 pub mod jup;
@@ -219,16 +223,19 @@ pub mod listener_service;
 pub mod orca;
 pub mod prometheus;
 pub mod provider;
+pub mod pubsub;
 pub mod pump;
 pub mod pump_service;
 pub mod raydium;
 pub mod rpc;
+pub mod rpc_pool;
 pub mod seller;
 pub mod seller_service;
 pub mod service;
 pub mod state;
 pub mod tx_parser;
 pub mod types;
+pub mod units;
 pub mod util;
 pub mod ws;
 
@@ -1,4 +1,3 @@
-use flexi_logger::{colored_detailed_format, Duplicate, Logger, WriteMode};
 use jito_protos::searcher::{MempoolSubscription, NextScheduledLeaderRequest};
 use jito_searcher_client::get_searcher_client;
 use raydium_library::amm;
@@ -20,7 +19,9 @@ use listen::{
     pump::{self},
     pump_service,
     raydium::{self, Raydium, SwapArgs},
-    rpc, seller, seller_service,
+    rpc,
+    rpc_pool::RpcPool,
+    seller, seller_service,
     service::run_listen_service,
     tx_parser, util, BlockAndProgramSubscribable, Listener, Provider,
 };
@@ -29,11 +30,15 @@ use solana_client::{
     rpc_response::{Response, RpcLogsResponse},
 };
 use solana_sdk::{
+    commitment_config::CommitmentConfig,
     pubkey::Pubkey,
     signature::Keypair,
     signer::{EncodableKey, Signer},
 };
 use tokio::sync::Mutex;
+use tracing_subscriber::{
+    layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer,
+};
 
 use log::{error, info, warn};
 
@@ -41,18 +46,24 @@ use log::{error, info, warn};
 async fn main() -> Result<(), Box<dyn Error>> {
     dotenv::dotenv().ok();
 
-    let _logger =
-        Logger::try_with_str(std::option_env!("RUST_LOG").unwrap_or("info"))?
-            .format(colored_detailed_format)
-            .write_mode(WriteMode::Async)
-            .duplicate_to_stdout(Duplicate::Info)
-            .start()?;
-
     let app = App::parse();
 
-    if app.args.tokio_console.unwrap_or(false) {
-        console_subscriber::init();
-    }
+    // the modules not moved to tracing yet still log through the `log`
+    // macros, try_init installs the tracing-log bridge (the `tracing-log`
+    // feature) so those go through the same layers and carry the fields of
+    // the span they are emitted in
+    let fmt_layer = tracing_subscriber::fmt::layer().with_filter(
+        EnvFilter::new(std::option_env!("RUST_LOG").unwrap_or("info")),
+    );
+    let console_layer = app
+        .args
+        .tokio_console
+        .unwrap_or(false)
+        .then(console_subscriber::spawn);
+    tracing_subscriber::registry()
+        .with(fmt_layer)
+        .with(console_layer)
+        .try_init()?;
 
     let sol_price = 210.;
 
@@ -105,7 +116,12 @@ async fn main() -> Result<(), Box<dyn Error>> {
             pump_service::run_pump_service().await?;
         }
         Command::GrabMetadata { mint } => {
-            pump::fetch_metadata(&Pubkey::from_str(&mint)?).await?;
+            pump::fetch_metadata(
+                &Pubkey::from_str(&mint)?,
+                pump::METADATA_TIMEOUT,
+                &tokio_util::sync::CancellationToken::new(),
+            )
+            .await?;
         }
         Command::SellPump { mint } => {
             let keypair = Keypair::read_from_file(env("FUND_KEYPAIR_PATH"))
@@ -253,7 +269,9 @@ async fn main() -> Result<(), Box<dyn Error>> {
             seller::get_spl_balance_stream(&pubsub_client, &ata).await?;
         }
         Command::Checks { signature } => {
-            let (ok, checklist) = checker::run_checks(signature).await?;
+            let rpc_pool = RpcPool::from_env(CommitmentConfig::processed());
+            let (ok, checklist) =
+                checker::run_checks(signature, &rpc_pool).await?;
             println!("ok? {}, {:?}", ok, checklist);
         }
         Command::Blockhash {} => {
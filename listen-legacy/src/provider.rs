@@ -3,12 +3,15 @@ use crate::{
     types,
     util::env,
 };
+use serde_json::json;
 use std::str::FromStr;
 
 use log::{debug, info, warn};
 use solana_client::{
-    nonblocking::rpc_client::RpcClient, rpc_client::SerializableTransaction,
-    rpc_config::RpcTransactionConfig, rpc_request::TokenAccountsFilter,
+    nonblocking::rpc_client::RpcClient,
+    rpc_client::SerializableTransaction,
+    rpc_config::RpcTransactionConfig,
+    rpc_request::{RpcRequest, TokenAccountsFilter},
 };
 use solana_sdk::{
     commitment_config::CommitmentConfig, program_pack::Pack, pubkey::Pubkey,
@@ -204,68 +207,96 @@ impl Provider {
     }
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum GetTxError {
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+    #[error("Transaction not found: {0}")]
+    NotFound(String),
+    #[error("RPC error: {0}")]
+    RpcError(String),
+}
+
+/// get_tx_async_with_client polls for the transaction until it is found at
+/// the given commitment or the retry budget is exhausted, freshly landed
+/// transactions are often not yet available on the first attempt
+///
+/// getTransaction does not support processed, use confirmed or finalized
 pub async fn get_tx_async_with_client(
     rpc_client: &RpcClient,
     signature: &str,
+    commitment: CommitmentConfig,
     retries: u32,
-) -> Result<
-    EncodedConfirmedTransactionWithStatusMeta,
-    Box<dyn std::error::Error>,
-> {
-    let sig = Signature::from_str(signature)?;
-    let mut backoff = 100;
-    for _ in 0..retries {
-        match rpc_client
-            .get_transaction_with_config(
-                &sig,
-                RpcTransactionConfig {
-                    encoding: Some(UiTransactionEncoding::JsonParsed),
-                    commitment: Some(CommitmentConfig::confirmed()),
-                    max_supported_transaction_version: Some(1),
-                },
-            )
-            .await
-        {
-            Ok(tx) => return Ok(tx),
-            Err(e) => {
-                warn!("Error getting tx: {:?}", e);
-                std::thread::sleep(std::time::Duration::from_millis(backoff));
-                backoff *= 2;
-            }
+) -> Result<EncodedConfirmedTransactionWithStatusMeta, GetTxError> {
+    let sig = Signature::from_str(signature)
+        .map_err(|e| GetTxError::InvalidSignature(e.to_string()))?;
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::JsonParsed),
+        commitment: Some(commitment),
+        max_supported_transaction_version: Some(1),
+    };
+    let params = json!([sig.to_string(), config]);
+    poll_tx(signature, retries, || {
+        let params = params.clone();
+        async move {
+            // null result means the tx is not (yet) available, as opposed to
+            // get_transaction_with_config which fails to deserialize it
+            rpc_client
+                .send::<Option<EncodedConfirmedTransactionWithStatusMeta>>(
+                    RpcRequest::GetTransaction,
+                    params,
+                )
+                .await
+                .map_err(|e| GetTxError::RpcError(e.to_string()))
         }
-    }
-    Err(format!("could not fetch {}", signature).into())
+    })
+    .await
 }
 
 pub async fn get_tx_async(
     signature: &str,
-) -> Result<
-    EncodedConfirmedTransactionWithStatusMeta,
-    Box<dyn std::error::Error>,
-> {
+    commitment: CommitmentConfig,
+    retries: u32,
+) -> Result<EncodedConfirmedTransactionWithStatusMeta, GetTxError> {
     let rpc_client = RpcClient::new(env("RPC_URL"));
-    let sig = Signature::from_str(signature)?;
+    get_tx_async_with_client(&rpc_client, signature, commitment, retries).await
+}
+
+/// poll_tx retries fetch with a doubling backoff, starting at 100ms; the
+/// last rpc error is returned if the tx was never reported as missing
+pub(crate) async fn poll_tx<F, Fut>(
+    signature: &str,
+    retries: u32,
+    mut fetch: F,
+) -> Result<EncodedConfirmedTransactionWithStatusMeta, GetTxError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<
+        Output = Result<
+            Option<EncodedConfirmedTransactionWithStatusMeta>,
+            GetTxError,
+        >,
+    >,
+{
     let mut backoff = 100;
-    let retries = 5;
-    for _ in 0..retries {
-        match rpc_client
-            .get_transaction_with_config(
-                &sig,
-                RpcTransactionConfig {
-                    encoding: Some(UiTransactionEncoding::JsonParsed),
-                    commitment: Some(CommitmentConfig::confirmed()),
-                    max_supported_transaction_version: Some(1),
-                },
-            )
-            .await
-        {
-            Ok(tx) => return Ok(tx),
+    let mut last_err = GetTxError::NotFound(signature.to_string());
+    for attempt in 0..retries {
+        if attempt > 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(backoff))
+                .await;
+            backoff *= 2;
+        }
+        match fetch().await {
+            Ok(Some(tx)) => return Ok(tx),
+            Ok(None) => {
+                debug!("tx {} not found (attempt {})", signature, attempt + 1);
+                last_err = GetTxError::NotFound(signature.to_string());
+            }
             Err(e) => {
-                debug!("Error getting tx: {:?}", e);
-                std::thread::sleep(std::time::Duration::from_millis(backoff));
-                backoff *= 2;
+                warn!("Error getting tx: {:?}", e);
+                last_err = e;
             }
         }
     }
-    Err(format!("could not fetch {}", signature).into())
+    Err(last_err)
 }
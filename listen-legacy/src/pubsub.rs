@@ -0,0 +1,313 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::{Stream, StreamExt};
+use solana_account_decoder::UiAccount;
+use solana_client::nonblocking::pubsub_client::{
+    PubsubClient, PubsubClientError,
+};
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcTransactionLogsConfig, RpcTransactionLogsFilter,
+};
+use solana_client::rpc_response::{Response, RpcLogsResponse};
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::{mpsc, Mutex};
+use tracing::{debug, warn};
+
+pub const DEFAULT_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+pub const DEFAULT_MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+pub const RECONNECTING_PUBSUB_BUFFER: usize = 256;
+
+/// ReconnectingPubsub opens subscriptions that survive the websocket
+/// dropping, on disconnect the connection is re-established and the
+/// subscription re-created with its original filter and config, the
+/// consumer keeps reading from the same stream
+///
+/// the subscriptions (of every clone too) share one connection, the first
+/// subscription to see it drop opens the next one and the others resubscribe
+/// on it; failed reconnects back off exponentially up to
+/// `max_reconnect_delay`; notifications sent while reconnecting are lost, an
+/// account subscription only reports the next change of the account
+#[derive(Debug, Clone)]
+pub struct ReconnectingPubsub {
+    url: String,
+    reconnect_delay: Duration,
+    max_reconnect_delay: Duration,
+    connection: Arc<Mutex<Option<Arc<PubsubClient>>>>,
+}
+
+impl ReconnectingPubsub {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            reconnect_delay: DEFAULT_RECONNECT_DELAY,
+            max_reconnect_delay: DEFAULT_MAX_RECONNECT_DELAY,
+            connection: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn with_reconnect_delay(mut self, reconnect_delay: Duration) -> Self {
+        self.reconnect_delay = reconnect_delay;
+        self
+    }
+
+    pub fn with_max_reconnect_delay(
+        mut self,
+        max_reconnect_delay: Duration,
+    ) -> Self {
+        self.max_reconnect_delay = max_reconnect_delay;
+        self
+    }
+
+    /// connection returns the shared connection, opening it if there is
+    /// none yet or it is `stale` (seen dropping by a subscription); another
+    /// subscription may have replaced a stale one already
+    async fn connection(
+        &self,
+        stale: Option<&Arc<PubsubClient>>,
+    ) -> Result<Arc<PubsubClient>, PubsubClientError> {
+        let mut connection = self.connection.lock().await;
+        if let Some(current) = connection.as_ref() {
+            if !stale.is_some_and(|stale| Arc::ptr_eq(current, stale)) {
+                return Ok(current.clone());
+            }
+        }
+        let client = Arc::new(PubsubClient::new(&self.url).await?);
+        *connection = Some(client.clone());
+        Ok(client)
+    }
+
+    /// account_subscribe streams the account, the subscription ends once the
+    /// stream is dropped; fails if the websocket can't be connected
+    pub async fn account_subscribe(
+        &self,
+        pubkey: Pubkey,
+        config: RpcAccountInfoConfig,
+    ) -> Result<
+        impl Stream<Item = Response<UiAccount>> + Unpin,
+        PubsubClientError,
+    > {
+        let connection = self.connection(None).await?;
+        let pubsub = self.clone();
+        Ok(spawn_reconnecting(
+            self.reconnect_delay,
+            self.max_reconnect_delay,
+            connection,
+            move |stale| {
+                let pubsub = pubsub.clone();
+                async move { pubsub.connection(Some(&stale)).await }
+            },
+            move |client, sender| {
+                let config = config.clone();
+                async move {
+                    let (notifications, unsub) = client
+                        .account_subscribe(&pubkey, Some(config))
+                        .await?;
+                    forward(notifications, &sender).await;
+                    unsub().await;
+                    Ok(())
+                }
+            },
+        ))
+    }
+
+    /// logs_subscribe streams the logs matching the filter, the subscription
+    /// ends once the stream is dropped; fails if the websocket can't be
+    /// connected
+    pub async fn logs_subscribe(
+        &self,
+        filter: RpcTransactionLogsFilter,
+        config: RpcTransactionLogsConfig,
+    ) -> Result<
+        impl Stream<Item = Response<RpcLogsResponse>> + Unpin,
+        PubsubClientError,
+    > {
+        let connection = self.connection(None).await?;
+        let pubsub = self.clone();
+        Ok(spawn_reconnecting(
+            self.reconnect_delay,
+            self.max_reconnect_delay,
+            connection,
+            move |stale| {
+                let pubsub = pubsub.clone();
+                async move { pubsub.connection(Some(&stale)).await }
+            },
+            move |client, sender| {
+                let filter = filter.clone();
+                let config = config.clone();
+                async move {
+                    let (notifications, unsub) =
+                        client.logs_subscribe(filter, config).await?;
+                    forward(notifications, &sender).await;
+                    unsub().await;
+                    Ok(())
+                }
+            },
+        ))
+    }
+}
+
+/// spawn_reconnecting runs `session` (subscribe on the connection, forward
+/// until it drops) over and over until the returned stream is dropped;
+/// between sessions `reconnect` gets a live connection in place of the one
+/// the session ended on, retried with a delay doubling from
+/// `reconnect_delay` up to `max_reconnect_delay` while it keeps failing
+fn spawn_reconnecting<C, T, R, RFut, F, Fut>(
+    reconnect_delay: Duration,
+    max_reconnect_delay: Duration,
+    mut connection: C,
+    mut reconnect: R,
+    mut session: F,
+) -> impl Stream<Item = T> + Unpin
+where
+    C: Clone + Send + 'static,
+    T: Send + 'static,
+    R: FnMut(C) -> RFut + Send + 'static,
+    RFut: Future<Output = Result<C, PubsubClientError>> + Send,
+    F: FnMut(C, mpsc::Sender<T>) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), PubsubClientError>> + Send,
+{
+    let (sender, mut receiver) = mpsc::channel(RECONNECTING_PUBSUB_BUFFER);
+    tokio::spawn(async move {
+        let mut delay = reconnect_delay;
+        loop {
+            match session(connection.clone(), sender.clone()).await {
+                Ok(()) => {
+                    debug!("subscription disconnected");
+                    delay = reconnect_delay;
+                }
+                Err(e) => warn!(?e, "subscription failed"),
+            }
+            loop {
+                if sender.is_closed() {
+                    return;
+                }
+                tokio::select! {
+                    _ = sender.closed() => return,
+                    _ = tokio::time::sleep(delay) => {}
+                }
+                delay = (delay * 2).min(max_reconnect_delay);
+                match reconnect(connection.clone()).await {
+                    Ok(reconnected) => {
+                        connection = reconnected;
+                        break;
+                    }
+                    Err(e) => warn!(?e, ?delay, "reconnect failed"),
+                }
+            }
+            debug!("resubscribing");
+        }
+    });
+    futures_util::stream::poll_fn(move |cx| receiver.poll_recv(cx))
+}
+
+/// forward sends the notifications on until the connection drops (the
+/// stream ends) or the receiver is gone
+async fn forward<T, S>(mut notifications: S, sender: &mpsc::Sender<T>)
+where
+    S: Stream<Item = T> + Unpin,
+{
+    loop {
+        tokio::select! {
+            _ = sender.closed() => return,
+            notification = notifications.next() => match notification {
+                Some(notification) => {
+                    if sender.send(notification).await.is_err() {
+                        return;
+                    }
+                }
+                None => return,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_resubscribes_after_disconnect() {
+        let sessions = Arc::new(std::sync::Mutex::new(vec![]));
+        let reconnects = Arc::new(AtomicUsize::new(0));
+        let (sessions_, reconnects_) = (sessions.clone(), reconnects.clone());
+        let mut stream = spawn_reconnecting(
+            Duration::from_millis(1),
+            Duration::from_millis(4),
+            0,
+            move |connection: usize| {
+                let reconnect = reconnects_.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    // the endpoint refuses the first reconnect
+                    if reconnect == 0 {
+                        return Err(PubsubClientError::ConnectionClosed(
+                            "refused".to_string(),
+                        ));
+                    }
+                    Ok(connection + 1)
+                }
+            },
+            move |connection, sender| {
+                let mut sessions = sessions_.lock().unwrap();
+                let session = sessions.len();
+                sessions.push(connection);
+                async move {
+                    match session {
+                        // delivers, then the websocket drops
+                        0 => {
+                            forward(
+                                futures_util::stream::iter([1, 2]),
+                                &sender,
+                            )
+                            .await
+                        }
+                        // the subscription is refused
+                        1 => {
+                            return Err(PubsubClientError::ConnectionClosed(
+                                "refused".to_string(),
+                            ))
+                        }
+                        // delivers, then stays connected
+                        _ => {
+                            forward(
+                                futures_util::stream::iter([3])
+                                    .chain(futures_util::stream::pending()),
+                                &sender,
+                            )
+                            .await
+                        }
+                    }
+                    Ok(())
+                }
+            },
+        );
+
+        let mut received = vec![];
+        for _ in 0..3 {
+            received.push(stream.next().await.unwrap());
+        }
+        assert_eq!(received, vec![1, 2, 3]);
+        // the failed reconnect is retried, every session runs on a fresh
+        // connection
+        assert_eq!(*sessions.lock().unwrap(), vec![0, 1, 2]);
+        assert_eq!(reconnects.load(Ordering::SeqCst), 3);
+
+        // dropping the stream ends the subscription instead of reconnecting
+        drop(stream);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(sessions.lock().unwrap().len(), 3);
+        assert_eq!(reconnects.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_connect_failure_surfaced() {
+        let pubsub = ReconnectingPubsub::new("ws://127.0.0.1:1");
+        let subscription = pubsub
+            .account_subscribe(Pubkey::new_unique(), Default::default())
+            .await;
+        assert!(subscription.is_err());
+    }
+}
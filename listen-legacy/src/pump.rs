@@ -1,44 +1,62 @@
 use anchor_lang::system_program;
-use futures_util::StreamExt;
+use futures_util::{Stream, StreamExt};
 use jito_protos::searcher::SubscribeBundleResultsRequest;
 use jito_searcher_client::{
     get_searcher_client, send_bundle_with_confirmation,
 };
-use log::{debug, error, info, warn};
 use solana_account_decoder::UiAccountEncoding;
+use solana_client::client_error::{
+    ClientError, ClientErrorKind, Result as ClientResult,
+};
+use solana_client::rpc_request::RpcError;
 use solana_sdk::system_instruction::transfer;
-use solana_sdk::transaction::{Transaction, VersionedTransaction};
-use std::collections::HashMap;
+use solana_sdk::transaction::{
+    Transaction, TransactionError, VersionedTransaction,
+};
+use std::collections::{HashMap, VecDeque};
 use std::error::Error;
+use std::future::Future;
 use std::str::FromStr;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use tokio::time::{sleep, Duration};
+use tokio::time::{sleep, Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, field, info, info_span, warn, Instrument, Span};
 
 use borsh::{BorshDeserialize, BorshSerialize};
 
 use serde::{Deserialize, Serialize};
-use solana_client::nonblocking::pubsub_client::PubsubClient;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_client::rpc_config::{
-    RpcAccountInfoConfig, RpcSendTransactionConfig, RpcTransactionLogsConfig,
-    RpcTransactionLogsFilter,
+    RpcAccountInfoConfig, RpcSendTransactionConfig,
+    RpcSimulateTransactionAccountsConfig, RpcSimulateTransactionConfig,
+    RpcTransactionLogsConfig, RpcTransactionLogsFilter,
+};
+use solana_client::rpc_response::{
+    Response, RpcLogsResponse, RpcSimulateTransactionResult,
 };
 use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
+use solana_sdk::hash::Hash;
 use solana_sdk::instruction::{AccountMeta, Instruction};
-use solana_sdk::signature::Keypair;
+use solana_sdk::message::Message;
+use solana_sdk::packet::PACKET_DATA_SIZE;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::signature::{Keypair, Signature};
 use solana_sdk::signer::{EncodableKey, Signer};
 use solana_sdk::{pubkey, pubkey::Pubkey};
 use solana_transaction_status::{
-    EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction, UiMessage,
-    UiParsedMessage,
+    EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction,
+    UiInstruction, UiMessage, UiParsedInstruction, UiParsedMessage,
+    UiPartiallyDecodedInstruction, UiTransactionEncoding,
 };
 
 use crate::constants::JITO_TIP_PUBKEY;
-use crate::get_tx_async_with_client;
 use crate::jito::{send_swap_tx_no_wait, SearcherClient};
+use crate::pubsub::ReconnectingPubsub;
 use crate::raydium::make_compute_budget_ixs;
+use crate::rpc_pool::RpcPool;
 use crate::util::{env, pubkey_to_string, string_to_pubkey, string_to_u64};
+use crate::{get_tx_async_with_client, GetTxError};
 
 pub const PUMP_GLOBAL_ADDRESS: Pubkey =
     pubkey!("4wTV1YmiEkRvAtNtsSGPtUrqRYQMe5SKy2uB4Jjaxnjf");
@@ -54,20 +72,97 @@ pub const PUMP_BUY_METHOD: [u8; 8] =
     [0x66, 0x06, 0x3d, 0x12, 0x01, 0xda, 0xeb, 0xea];
 pub const PUMP_SELL_METHOD: [u8; 8] =
     [0x33, 0xe6, 0x85, 0xa4, 0x01, 0x7f, 0x83, 0xad];
+pub const PUMP_CREATE_METHOD: [u8; 8] =
+    [0x18, 0x1e, 0xc8, 0x28, 0x05, 0x1c, 0x07, 0x77];
 pub const TOKEN_PROGRAM: Pubkey =
     pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
 pub const RENT_PROGRAM: Pubkey =
     pubkey!("SysvarRent111111111111111111111111111111111");
 pub const ASSOCIATED_TOKEN_PROGRAM: Pubkey =
     pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+pub const MPL_TOKEN_METADATA_PROGRAM: Pubkey =
+    pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
 
-#[derive(BorshSerialize)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub struct PumpFunSwapInstructionData {
     pub method_id: [u8; 8],
     pub token_amount: u64,
     pub lamports: u64,
 }
 
+impl PumpFunSwapInstructionData {
+    /// parse_buy decodes the data of an observed pump.fun buy, for a buy the
+    /// lamports are the max sol cost
+    pub fn parse_buy(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let ix_data = Self::try_from(data)?;
+        if !ix_data.is_buy() {
+            return Err("Not a pump.fun buy instruction".into());
+        }
+        Ok(ix_data)
+    }
+
+    /// parse_sell decodes the data of an observed pump.fun sell, for a sell
+    /// the lamports are the min sol output
+    pub fn parse_sell(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let ix_data = Self::try_from(data)?;
+        if !ix_data.is_sell() {
+            return Err("Not a pump.fun sell instruction".into());
+        }
+        Ok(ix_data)
+    }
+
+    pub fn is_buy(&self) -> bool {
+        self.method_id == PUMP_BUY_METHOD
+    }
+
+    pub fn is_sell(&self) -> bool {
+        self.method_id == PUMP_SELL_METHOD
+    }
+}
+
+impl TryFrom<&[u8]> for PumpFunSwapInstructionData {
+    type Error = Box<dyn Error>;
+
+    /// accepts both the buy and the sell method, trailing bytes are ignored
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < 24 {
+            return Err(
+                format!("Invalid swap data length: {}", data.len()).into()
+            );
+        }
+        if data[..8] != PUMP_BUY_METHOD && data[..8] != PUMP_SELL_METHOD {
+            return Err(format!(
+                "Unknown pump.fun method id: {:?}",
+                &data[..8]
+            )
+            .into());
+        }
+        Ok(Self::try_from_slice(&data[..24])?)
+    }
+}
+
+/// PumpCreateInstructionData are the arguments of the pump.fun create
+/// instruction, the metadata is there right away unlike the metaplex account
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct PumpCreateInstructionData {
+    pub method_id: [u8; 8],
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+}
+
+impl TryFrom<&[u8]> for PumpCreateInstructionData {
+    type Error = Box<dyn Error>;
+
+    /// trailing bytes are ignored
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < 8 || data[..8] != PUMP_CREATE_METHOD {
+            return Err("Not a pump.fun create instruction".into());
+        }
+        Ok(Self::deserialize(&mut &data[..])?)
+    }
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub struct BondingCurveLayout {
     pub blob1: u64,
@@ -91,6 +186,10 @@ pub struct PumpTokenData {
     pub value: f64,
 }
 
+/// real sol reserves at which the bonding curve completes and the token
+/// migrates to raydium, ~85 sol
+pub const PUMP_MIGRATION_SOL_RESERVES: u64 = 85_000_000_000;
+
 impl BondingCurveLayout {
     pub const LEN: usize = 8 + 8 + 8 + 8 + 8 + 8 + 1;
 
@@ -99,6 +198,22 @@ impl BondingCurveLayout {
     }
 }
 
+/// is_near_completion returns true if the curve is complete or its real sol
+/// reserves are within threshold_bps of the migration cap, buying there is
+/// risky since the migration can land before the buy does
+pub fn is_near_completion(
+    curve: &BondingCurveLayout,
+    threshold_bps: u16,
+) -> bool {
+    if curve.complete {
+        return true;
+    }
+    let threshold = PUMP_MIGRATION_SOL_RESERVES as u128
+        * 10_000u128.saturating_sub(threshold_bps as u128)
+        / 10_000;
+    curve.real_sol_reserves as u128 >= threshold
+}
+
 pub fn get_local_timestamp() -> chrono::DateTime<chrono::Local> {
     let utc_now = chrono::Utc::now();
     utc_now.with_timezone(&chrono::Local)
@@ -110,29 +225,41 @@ pub fn get_local_timestamp() -> chrono::DateTime<chrono::Local> {
 pub async fn mint_to_pump_accounts(
     mint: &Pubkey,
 ) -> Result<PumpAccounts, Box<dyn Error>> {
-    // Constants
-    const PUMP_FUN_PROGRAM: &str =
-        "6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P";
+    derive_pump_accounts(mint)
+}
 
-    // Derive the bonding curve address
+/// derive_pump_accounts derives the bonding curve PDA, its token account and
+/// the metaplex metadata PDA of the mint, the dev is not derivable and is
+/// left as default
+pub fn derive_pump_accounts(
+    mint: &Pubkey,
+) -> Result<PumpAccounts, Box<dyn Error>> {
     let (bonding_curve, _) = Pubkey::find_program_address(
         &[b"bonding-curve", mint.as_ref()],
-        &Pubkey::from_str(PUMP_FUN_PROGRAM)?,
+        &PUMP_FUN_PROGRAM,
     );
 
-    // Derive the associated bonding curve address
     let associated_bonding_curve =
         spl_associated_token_account::get_associated_token_address(
             &bonding_curve,
             mint,
         );
 
+    let (metadata, _) = Pubkey::find_program_address(
+        &[
+            b"metadata",
+            MPL_TOKEN_METADATA_PROGRAM.as_ref(),
+            mint.as_ref(),
+        ],
+        &MPL_TOKEN_METADATA_PROGRAM,
+    );
+
     Ok(PumpAccounts {
         mint: *mint,
         bonding_curve,
         associated_bonding_curve,
         dev: Pubkey::default(),
-        metadata: Pubkey::default(),
+        metadata,
     })
 }
 
@@ -330,6 +457,7 @@ pub async fn instabuy_pump_token(
         pump_buy_request.associated_bonding_curve,
         token_amount,
         lamports,
+        TOKEN_PROGRAM,
     )?;
     let tip = 100000;
     let mut searcher_client = searcher_client.lock().await;
@@ -351,12 +479,24 @@ pub async fn buy_pump_token(
     pump_accounts: PumpAccounts,
     lamports: u64,
     searcher_client: &mut Arc<Mutex<SearcherClient>>,
-    use_jito: bool,
+    options: BuyOptions,
 ) -> Result<(), Box<dyn Error>> {
     let owner = wallet.pubkey();
 
-    let bonding_curve =
-        get_bonding_curve(rpc_client, pump_accounts.bonding_curve).await?;
+    let (bonding_curve, token_program) = tokio::join!(
+        get_bonding_curve(rpc_client, pump_accounts.bonding_curve),
+        pump_token_program(rpc_client, &pump_accounts.mint),
+    );
+    let (bonding_curve, token_program) = (bonding_curve?, token_program?);
+    if let Some(threshold_bps) = options.refuse_near_completion_bps {
+        if is_near_completion(&bonding_curve, threshold_bps) {
+            return Err(format!(
+                "Bonding curve of {} is near completion ({} lamports)",
+                pump_accounts.mint, bonding_curve.real_sol_reserves
+            )
+            .into());
+        }
+    }
     let token_amount = get_token_amount(
         bonding_curve.virtual_sol_reserves,
         bonding_curve.virtual_token_reserves,
@@ -369,18 +509,45 @@ pub async fn buy_pump_token(
 
     info!("buying {}", token_amount);
 
+    // derived pump accounts assume the classic token program
+    let associated_bonding_curve = get_pump_ata(
+        &pump_accounts.bonding_curve,
+        &pump_accounts.mint,
+        &token_program,
+    );
     let mut ixs = _make_buy_ixs(
         owner,
         pump_accounts.mint,
         pump_accounts.bonding_curve,
-        pump_accounts.associated_bonding_curve,
+        associated_bonding_curve,
         token_amount,
         lamports,
+        token_program,
     )?;
 
+    if let Some(min_tokens_out) = options.min_tokens_out {
+        let ata = get_pump_ata(&owner, &pump_accounts.mint, &token_program);
+        let (simulation, pre_amount) = tokio::join!(
+            simulate_pump_buy(wallet, rpc_client, &ixs, &ata),
+            rpc_client.get_token_account_balance(&ata),
+        );
+        // no ata yet means no tokens held
+        let pre_amount = match pre_amount {
+            Ok(balance) => balance.amount.parse::<u64>()?,
+            Err(e) if is_account_not_found(&e) => 0,
+            Err(e) => return Err(e.into()),
+        };
+        let tokens_out = check_simulated_tokens_out(
+            &simulation?,
+            pre_amount,
+            min_tokens_out,
+        )?;
+        info!("simulated buy of {} tokens", tokens_out);
+    }
+
     // send transaction with jito
     // 0.0001 sol tip
-    if use_jito {
+    if options.use_jito {
         let tip = 100000;
         let mut searcher_client = searcher_client.lock().await;
         send_swap_tx_no_wait(
@@ -392,7 +559,15 @@ pub async fn buy_pump_token(
         )
         .await?;
     } else {
-        _send_tx_standard(ixs, wallet, rpc_client, owner).await?;
+        _send_tx_standard(
+            ixs,
+            wallet,
+            rpc_client,
+            owner,
+            &options.send_retry,
+            &options.send_config,
+        )
+        .await?;
     }
 
     // send the tx with spinner
@@ -415,6 +590,37 @@ pub async fn buy_pump_token(
     Ok(())
 }
 
+/// get_pump_ata derives the owner's token account of the mint under its
+/// token program, a Token-2022 mint has a different ata than a classic one
+pub fn get_pump_ata(
+    owner: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Pubkey {
+    spl_associated_token_account::get_associated_token_address_with_program_id(
+        owner,
+        mint,
+        token_program,
+    )
+}
+
+/// pump_token_program reads the token program owning the mint, pump mints
+/// are classic spl tokens but graduated or exotic ones can be Token-2022
+pub async fn pump_token_program(
+    rpc_client: &RpcClient,
+    mint: &Pubkey,
+) -> Result<Pubkey, Box<dyn Error>> {
+    let owner = rpc_client.get_account(mint).await?.owner;
+    if owner != TOKEN_PROGRAM && owner != spl_token_2022::ID {
+        return Err(format!(
+            "{} is not a token mint, owned by {}",
+            mint, owner
+        )
+        .into());
+    }
+    Ok(owner)
+}
+
 pub fn _make_buy_ixs(
     owner: Pubkey,
     mint: Pubkey,
@@ -422,66 +628,475 @@ pub fn _make_buy_ixs(
     associated_bonding_curve: Pubkey,
     token_amount: u64,
     lamports: u64,
+    token_program: Pubkey,
 ) -> Result<Vec<Instruction>, Box<dyn Error>> {
     let mut ixs = vec![];
     ixs.append(&mut make_compute_budget_ixs(262500, 100000));
-    let ata = spl_associated_token_account::get_associated_token_address(
-        &owner, &mint,
-    );
-    let mut ata_ixs = raydium_library::common::create_ata_token_or_not(
-        &owner, &mint, &owner,
+    let ata = get_pump_ata(&owner, &mint, &token_program);
+    ixs.push(
+        spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+            &owner,
+            &owner,
+            &mint,
+            &token_program,
+        ),
     );
-
-    ixs.append(&mut ata_ixs);
     ixs.push(make_pump_swap_ix(
-        owner,
-        mint,
-        bonding_curve,
-        associated_bonding_curve,
+        &PumpSwapAccounts {
+            owner,
+            mint,
+            bonding_curve,
+            associated_bonding_curve,
+            ata,
+            token_program,
+        },
         token_amount,
         lamports,
-        ata,
     )?);
 
     Ok(ixs)
 }
 
-async fn _send_tx_standard(
-    ixs: Vec<Instruction>,
+/// the basket buys run on the same budget as a single one, 100k units for
+/// the ata creation and the swap
+const PUMP_BASKET_UNIT_PRICE: u64 = 262500;
+const PUMP_BASKET_UNITS_PER_BUY: u32 = 100000;
+
+/// PumpBasketBuy is a single buy of a basket, `token_program` is the one
+/// owning the mint (see pump_token_program)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PumpBasketBuy {
+    pub accounts: PumpAccounts,
+    pub token_program: Pubkey,
+    pub token_amount: u64,
+    /// the most lamports the buy can spend
+    pub lamports: u64,
+}
+
+/// build_pump_basket_txs packs buys into as few transactions as fit the
+/// packet size, in order; each buy is the idempotent ata creation followed
+/// by the swap and is never split across transactions; the transactions are
+/// left unsigned
+pub fn build_pump_basket_txs(
+    owner: Pubkey,
+    buys: Vec<PumpBasketBuy>,
+    blockhash: Hash,
+) -> Result<Vec<Transaction>, Box<dyn Error>> {
+    let mut txs = vec![];
+    let mut basket: Vec<Vec<Instruction>> = vec![];
+    for buy in buys {
+        let PumpBasketBuy {
+            accounts: pump_accounts,
+            token_program,
+            ..
+        } = buy;
+        let buy_ixs = vec![
+            spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                &owner,
+                &owner,
+                &pump_accounts.mint,
+                &token_program,
+            ),
+            make_pump_swap_ix(
+                &PumpSwapAccounts {
+                    owner,
+                    mint: pump_accounts.mint,
+                    bonding_curve: pump_accounts.bonding_curve,
+                    // derived pump accounts assume the classic token program
+                    associated_bonding_curve: get_pump_ata(
+                        &pump_accounts.bonding_curve,
+                        &pump_accounts.mint,
+                        &token_program,
+                    ),
+                    ata: get_pump_ata(
+                        &owner,
+                        &pump_accounts.mint,
+                        &token_program,
+                    ),
+                    token_program,
+                },
+                buy.token_amount,
+                buy.lamports,
+            )?,
+        ];
+        basket.push(buy_ixs);
+        if fits_packet(&make_basket_tx(owner, &basket, blockhash))? {
+            continue;
+        }
+        let buy_ixs = basket.pop().expect("just pushed");
+        if basket.is_empty() {
+            return Err(format!(
+                "Buy of {} doesn't fit a transaction",
+                pump_accounts.mint
+            )
+            .into());
+        }
+        txs.push(make_basket_tx(owner, &basket, blockhash));
+        basket = vec![buy_ixs];
+    }
+    if !basket.is_empty() {
+        txs.push(make_basket_tx(owner, &basket, blockhash));
+    }
+    Ok(txs)
+}
+
+fn make_basket_tx(
+    owner: Pubkey,
+    basket: &[Vec<Instruction>],
+    blockhash: Hash,
+) -> Transaction {
+    let mut ixs = make_compute_budget_ixs(
+        PUMP_BASKET_UNIT_PRICE,
+        PUMP_BASKET_UNITS_PER_BUY * basket.len() as u32,
+    );
+    ixs.extend(basket.iter().flatten().cloned());
+    Transaction::new_unsigned(Message::new_with_blockhash(
+        &ixs,
+        Some(&owner),
+        &blockhash,
+    ))
+}
+
+/// fits_packet checks the wire size, the placeholder signatures of an
+/// unsigned transaction are as long as the real ones
+fn fits_packet(tx: &Transaction) -> Result<bool, Box<dyn Error>> {
+    Ok(bincode::serialized_size(tx)? as usize <= PACKET_DATA_SIZE)
+}
+
+/// simulate_pump_buy simulates the buy ixs and returns the post-state of the
+/// owner's token account along with the result
+async fn simulate_pump_buy(
     wallet: &Keypair,
     rpc_client: &RpcClient,
-    owner: Pubkey,
-) -> Result<(), Box<dyn Error>> {
+    ixs: &[Instruction],
+    ata: &Pubkey,
+) -> Result<RpcSimulateTransactionResult, Box<dyn Error>> {
+    // the blockhash is replaced by the node, saves fetching a fresh one
     let transaction =
         VersionedTransaction::from(Transaction::new_signed_with_payer(
-            &ixs,
-            Some(&owner),
+            ixs,
+            Some(&wallet.pubkey()),
             &[wallet],
-            rpc_client.get_latest_blockhash().await?,
+            Hash::default(),
         ));
     let res = rpc_client
-        .send_transaction_with_config(
+        .simulate_transaction_with_config(
             &transaction,
-            RpcSendTransactionConfig {
-                skip_preflight: true,
+            RpcSimulateTransactionConfig {
+                sig_verify: false,
+                replace_recent_blockhash: true,
+                commitment: Some(CommitmentConfig::processed()),
+                encoding: Some(UiTransactionEncoding::Base64),
+                accounts: Some(RpcSimulateTransactionAccountsConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    addresses: vec![ata.to_string()],
+                }),
                 min_context_slot: None,
-                preflight_commitment: Some(CommitmentLevel::Processed),
-                max_retries: None,
-                encoding: None,
             },
         )
-        .await;
+        .await?;
+    Ok(res.value)
+}
 
-    match res {
-        Ok(sig) => {
-            info!("Transaction sent: {}", sig);
+/// check_simulated_tokens_out returns the tokens received in the simulation,
+/// erroring if the simulation failed or the output is below min_tokens_out
+pub fn check_simulated_tokens_out(
+    simulation: &RpcSimulateTransactionResult,
+    pre_amount: u64,
+    min_tokens_out: u64,
+) -> Result<u64, Box<dyn Error>> {
+    if let Some(err) = &simulation.err {
+        return Err(format!(
+            "Simulation failed: {} {:?}",
+            err, simulation.logs
+        )
+        .into());
+    }
+    let account = simulation
+        .accounts
+        .as_ref()
+        .and_then(|accounts| accounts.first().cloned().flatten())
+        .ok_or("Simulation did not return the token account")?;
+    let account = account
+        .decode::<solana_sdk::account::Account>()
+        .ok_or("Could not decode the simulated token account")?;
+    let amount = spl_token::state::Account::unpack(&account.data)?.amount;
+    let tokens_out = amount.saturating_sub(pre_amount);
+    if tokens_out < min_tokens_out {
+        return Err(format!(
+            "Simulated output {} below min tokens out {}",
+            tokens_out, min_tokens_out
+        )
+        .into());
+    }
+    Ok(tokens_out)
+}
+
+async fn _send_tx_standard(
+    ixs: Vec<Instruction>,
+    wallet: &Keypair,
+    rpc_client: &RpcClient,
+    owner: Pubkey,
+    send_retry: &SendRetryConfig,
+    send_config: &SendConfig,
+) -> Result<(), Box<dyn Error>> {
+    let sender = RpcTxSender {
+        rpc_client,
+        send_config,
+    };
+    let sig = send_with_retry(&sender, send_retry, |blockhash| {
+        VersionedTransaction::from(Transaction::new_signed_with_payer(
+            &ixs,
+            Some(&owner),
+            &[wallet],
+            blockhash,
+        ))
+    })
+    .await?;
+    info!("Transaction sent: {}", sig);
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SendRetryConfig {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+}
+
+impl Default for SendRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(200),
         }
-        Err(e) => {
-            return Err(e.into());
+    }
+}
+
+/// SendConfig is how the rpc node handles a sent transaction
+///
+/// preflight simulates the tx at `preflight_commitment` before forwarding
+/// it, which catches failing txs for free but costs a round of latency, and
+/// a preflight against a lagging state read can reject a tx that would have
+/// landed; skipping it is the sniping default, a failing tx then only shows
+/// up on chain (and pays its fee)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SendConfig {
+    pub skip_preflight: bool,
+    pub preflight_commitment: CommitmentLevel,
+    /// how many times the rpc node rebroadcasts the tx, None leaves it to
+    /// the node
+    pub max_retries: Option<usize>,
+}
+
+impl Default for SendConfig {
+    fn default() -> Self {
+        Self {
+            skip_preflight: true,
+            preflight_commitment: CommitmentLevel::Processed,
+            max_retries: None,
         }
     }
+}
 
-    Ok(())
+/// BuyOptions are the knobs of buy_pump_token, the defaults send through the
+/// rpc node without any guard against a bad fill
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BuyOptions {
+    /// send as a jito bundle rather than through the rpc node
+    pub use_jito: bool,
+    /// refuse to buy a curve within this many bps of the migration cap, see
+    /// is_near_completion
+    pub refuse_near_completion_bps: Option<u16>,
+    /// simulate the buy first and refuse it if it fills fewer tokens
+    pub min_tokens_out: Option<u64>,
+    pub send_retry: SendRetryConfig,
+    pub send_config: SendConfig,
+}
+
+impl SendConfig {
+    pub fn rpc_send_config(&self) -> RpcSendTransactionConfig {
+        RpcSendTransactionConfig {
+            skip_preflight: self.skip_preflight,
+            preflight_commitment: Some(self.preflight_commitment),
+            max_retries: self.max_retries,
+            min_context_slot: None,
+            encoding: None,
+        }
+    }
+}
+
+impl SendRetryConfig {
+    /// backoff before retry number `attempt` (from 0) doubles every time, the
+    /// up to base_delay of jitter keeps concurrent senders out of lockstep
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let jitter = match self.base_delay.as_nanos() as u64 {
+            0 => 0,
+            max_jitter => {
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .subsec_nanos() as u64
+                    % max_jitter
+            }
+        };
+        self.base_delay * 2u32.saturating_pow(attempt)
+            + Duration::from_nanos(jitter)
+    }
+}
+
+/// is_retryable_send_error is true for the errors the tx may get past on a
+/// later attempt (transport, a node that doesn't know the blockhash yet);
+/// failing instructions like slippage or insufficient funds are terminal
+pub fn is_retryable_send_error(err: &ClientError) -> bool {
+    if let Some(tx_err) = err.get_transaction_error() {
+        return tx_err == TransactionError::BlockhashNotFound;
+    }
+    match err.kind() {
+        ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) => true,
+        ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            message,
+            ..
+        }) => {
+            let message = message.to_lowercase();
+            message.contains("blockhash not found")
+                || message.contains("block height exceeded")
+        }
+        _ => false,
+    }
+}
+
+/// is_account_not_found is true for the error of a token account query on
+/// an account that doesn't exist, the node rejects it as an invalid param
+pub fn is_account_not_found(err: &ClientError) -> bool {
+    matches!(
+        err.kind(),
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { message, .. })
+            if message.contains("could not find account")
+    )
+}
+
+/// TxSender is the rpc surface send_with_retry runs on
+pub(crate) trait TxSender {
+    /// a blockhash and the last block height a tx signed with it can land at
+    async fn latest_blockhash(&self) -> ClientResult<(Hash, u64)>;
+    async fn send(&self, tx: &VersionedTransaction)
+        -> ClientResult<Signature>;
+    async fn block_height(&self) -> ClientResult<u64>;
+    /// None if the tx hasn't landed (or the node doesn't know it yet)
+    async fn signature_status(
+        &self,
+        sig: &Signature,
+    ) -> ClientResult<Option<Result<(), TransactionError>>>;
+}
+
+pub(crate) struct RpcTxSender<'a> {
+    pub rpc_client: &'a RpcClient,
+    pub send_config: &'a SendConfig,
+}
+
+impl TxSender for RpcTxSender<'_> {
+    async fn latest_blockhash(&self) -> ClientResult<(Hash, u64)> {
+        self.rpc_client
+            .get_latest_blockhash_with_commitment(self.rpc_client.commitment())
+            .await
+    }
+
+    async fn send(
+        &self,
+        tx: &VersionedTransaction,
+    ) -> ClientResult<Signature> {
+        self.rpc_client
+            .send_transaction_with_config(
+                tx,
+                self.send_config.rpc_send_config(),
+            )
+            .await
+    }
+
+    async fn block_height(&self) -> ClientResult<u64> {
+        self.rpc_client.get_block_height().await
+    }
+
+    async fn signature_status(
+        &self,
+        sig: &Signature,
+    ) -> ClientResult<Option<Result<(), TransactionError>>> {
+        let statuses = self.rpc_client.get_signature_statuses(&[*sig]).await?;
+        Ok(statuses
+            .value
+            .into_iter()
+            .next()
+            .flatten()
+            .map(|status| status.status))
+    }
+}
+
+/// send_with_retry signs the tx with `sign` and sends it until the node
+/// accepts it, it returns a terminal error or the retries run out
+///
+/// a failed send may still have been forwarded (the connection dropped after
+/// the node got the tx, and with skip_preflight the node doesn't check the
+/// blockhash anyway), so a retry first looks the signature up and resends
+/// the same signed tx while its blockhash is valid; a tx is only re-signed
+/// with a fresh blockhash once the block height is past
+/// `last_valid_block_height`, when the old one can no longer land and a
+/// second buy is impossible
+pub(crate) async fn send_with_retry<S: TxSender>(
+    sender: &S,
+    send_retry: &SendRetryConfig,
+    sign: impl Fn(Hash) -> VersionedTransaction,
+) -> Result<Signature, ClientError> {
+    let mut attempt = 0;
+    'sign: loop {
+        let (blockhash, last_valid_block_height) =
+            sender.latest_blockhash().await?;
+        let transaction = sign(blockhash);
+        let sig = transaction.signatures[0];
+        loop {
+            let e = match sender.send(&transaction).await {
+                Ok(_) => return Ok(sig),
+                Err(e) => e,
+            };
+            if attempt >= send_retry.max_retries
+                || !is_retryable_send_error(&e)
+            {
+                return Err(e);
+            }
+            let backoff = send_retry.backoff(attempt);
+            warn!(
+                "send of {} failed ({}), retry {}/{} in {:?}",
+                sig,
+                e,
+                attempt + 1,
+                send_retry.max_retries,
+                backoff
+            );
+            sleep(backoff).await;
+            attempt += 1;
+
+            // the height is read before the status, a tx landing in between
+            // is then still seen as landed
+            let block_height = sender.block_height().await;
+            match sender.signature_status(&sig).await {
+                Ok(Some(Ok(()))) => return Ok(sig),
+                Ok(Some(Err(tx_err))) => return Err(tx_err.into()),
+                Ok(None) => {}
+                // unknown, resending the same tx can't buy twice
+                Err(e) => {
+                    warn!("failed to get the status of {}: {}", sig, e);
+                    continue;
+                }
+            }
+            match block_height {
+                Ok(height) if height > last_valid_block_height => {
+                    debug!("blockhash of {} expired, re-signing", sig);
+                    continue 'sign;
+                }
+                _ => continue,
+            }
+        }
+    }
 }
 
 pub async fn sell_pump_token(
@@ -582,6 +1197,19 @@ pub fn make_pump_sell_ix(
     ))
 }
 
+/// PumpSwapAccounts are the accounts of a pump buy that differ per buyer and
+/// mint, the ata and the associated bonding curve are derived under
+/// `token_program`, the one owning the mint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PumpSwapAccounts {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub bonding_curve: Pubkey,
+    pub associated_bonding_curve: Pubkey,
+    pub ata: Pubkey,
+    pub token_program: Pubkey,
+}
+
 /// Interact With Pump.Fun 6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P
 /// Input Accounts
 /// #1 - Global: 4wTV1YmiEkRvAtNtsSGPtUrqRYQMe5SKy2uB4Jjaxnjf
@@ -597,24 +1225,20 @@ pub fn make_pump_sell_ix(
 /// #11 - Event Authority: Ce6TQqeHC9p8KetsN6JsjHK7UTZk7nasjjnr7XxXp9F1
 /// #12 - Program: Pump.fun Program 6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P
 pub fn make_pump_swap_ix(
-    owner: Pubkey,
-    mint: Pubkey,
-    bonding_curve: Pubkey,
-    associated_bonding_curve: Pubkey,
+    swap_accounts: &PumpSwapAccounts,
     token_amount: u64,
     lamports: u64,
-    ata: Pubkey,
 ) -> Result<Instruction, Box<dyn Error>> {
     let accounts: [AccountMeta; 12] = [
         AccountMeta::new_readonly(PUMP_GLOBAL_ADDRESS, false),
         AccountMeta::new(PUMP_FEE_ADDRESS, false),
-        AccountMeta::new_readonly(mint, false),
-        AccountMeta::new(bonding_curve, false),
-        AccountMeta::new(associated_bonding_curve, false),
-        AccountMeta::new(ata, false),
-        AccountMeta::new(owner, true),
+        AccountMeta::new_readonly(swap_accounts.mint, false),
+        AccountMeta::new(swap_accounts.bonding_curve, false),
+        AccountMeta::new(swap_accounts.associated_bonding_curve, false),
+        AccountMeta::new(swap_accounts.ata, false),
+        AccountMeta::new(swap_accounts.owner, true),
         AccountMeta::new_readonly(system_program::ID, false),
-        AccountMeta::new_readonly(TOKEN_PROGRAM, false),
+        AccountMeta::new_readonly(swap_accounts.token_program, false),
         AccountMeta::new_readonly(RENT_PROGRAM, false),
         AccountMeta::new_readonly(EVENT_AUTHORITY, false),
         AccountMeta::new_readonly(PUMP_FUN_PROGRAM, false),
@@ -638,7 +1262,7 @@ pub async fn snipe_pump(only_listen: bool) -> Result<(), Box<dyn Error>> {
         Keypair::read_from_file(env("FUND_KEYPAIR_PATH"))
             .expect("read wallet"),
     );
-    let rpc_client = Arc::new(RpcClient::new(env("RPC_URL")));
+    let rpc_pool = Arc::new(RpcPool::from_env(CommitmentConfig::default()));
     let auth =
         Arc::new(Keypair::read_from_file(env("AUTH_KEYPAIR_PATH")).unwrap());
 
@@ -648,10 +1272,70 @@ pub async fn snipe_pump(only_listen: bool) -> Result<(), Box<dyn Error>> {
             .expect("makes searcher client"),
     ));
 
-    let client = PubsubClient::new(&env("WS_URL"))
-        .await
-        .expect("pubsub client async");
-    let (mut notifications, unsub) = client
+    // ctrl-c stops the listener and interrupts the candidate in flight
+    let shutdown = CancellationToken::new();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                shutdown.cancel();
+            }
+        }
+    });
+
+    let creations = listen_pump(rpc_pool.clone()).await?;
+    futures_util::pin_mut!(creations);
+
+    info!("Listening for PumpFun events");
+    loop {
+        let accounts = tokio::select! {
+            _ = shutdown.cancelled() => {
+                info!("Shutting down the pump listener");
+                break;
+            }
+            accounts = creations.next() => match accounts {
+                Some(accounts) => accounts,
+                None => break,
+            },
+        };
+        let accounts = match accounts {
+            Ok(accounts) => accounts,
+            Err(e) => {
+                warn!("did not get pump candidate: {}", e);
+                continue;
+            }
+        };
+        if only_listen {
+            info!(
+                accounts = %serde_json::to_string(&accounts)?,
+                "new pump token"
+            );
+            continue;
+        }
+        handle_pump_candidate(
+            accounts,
+            &rpc_pool,
+            &wallet,
+            &searcher_client,
+            &shutdown.child_token(),
+        )
+        .instrument(info_span!("pump_candidate", mint = %accounts.mint))
+        .await?;
+    }
+    Ok(())
+}
+
+/// listen_pump streams the accounts of newly created pump tokens
+///
+/// the logs subscription is re-created whenever the websocket drops and is
+/// unsubscribed once the returned stream is dropped
+pub async fn listen_pump(
+    rpc_pool: Arc<RpcPool>,
+) -> Result<
+    impl Stream<Item = Result<PumpAccounts, Box<dyn Error>>>,
+    Box<dyn Error>,
+> {
+    let logs = ReconnectingPubsub::new(&env("WS_URL"))
         .logs_subscribe(
             RpcTransactionLogsFilter::Mentions(vec![
                 PUMP_FUN_MINT_AUTHORITY.to_string()
@@ -660,89 +1344,242 @@ pub async fn snipe_pump(only_listen: bool) -> Result<(), Box<dyn Error>> {
                 commitment: Some(CommitmentConfig::processed()),
             },
         )
-        .await
-        .expect("subscribe to logs");
-
-    info!("Listening for PumpFun events");
-    let mut cache = HashMap::<String, bool>::new();
-    while let Some(log) = notifications.next().await {
-        let sig = log.value.signature;
-        // max 1 retry, otherwise too slow
-        let tx = match get_tx_async_with_client(&rpc_client, &sig, 5).await {
-            Ok(tx) => tx,
-            Err(_) => {
-                warn!("did not get tx in time");
-                continue;
+        .await?;
+    Ok(pump_creations(logs, move |sig| {
+        let rpc_pool = rpc_pool.clone();
+        async move {
+            let rpc_client = rpc_pool.get();
+            // max 1 retry, otherwise too slow
+            match get_tx_async_with_client(
+                &rpc_client,
+                &sig,
+                CommitmentConfig::confirmed(),
+                5,
+            )
+            .await
+            {
+                Ok(tx) => {
+                    rpc_client.report_success();
+                    Ok(tx)
+                }
+                Err(e) => {
+                    if let GetTxError::RpcError(_) = e {
+                        rpc_client.report_error();
+                    }
+                    Err(Box::<dyn Error>::from(e))
+                }
             }
-        };
-        let slot = tx.slot;
-        let accounts = parse_pump_accounts(tx)?;
-        info!(
-            "PumpFun shitter: {} (slot: {})",
-            accounts.mint.to_string(),
-            slot,
-        );
-        if only_listen {
-            continue;
         }
-        let mint = accounts.mint.to_string();
-        if cache.contains_key(&mint) {
-            info!("Already bought {} shitter", mint);
-            continue;
-        }
-        cache.insert(mint.clone(), true);
+    }))
+}
 
-        // sanity check if all fields are populated
-        let metadata = fetch_metadata(&accounts.mint)
-            .await
-            .expect("fetch_metadata");
-        if metadata.website.is_none() {
-            warn!("No website for {}", mint);
-            continue;
+/// pump_creations turns the create tx notifications into pump accounts,
+/// fetching each tx with `fetch_tx`; repeated signatures and mints as well
+/// as failed txs are skipped
+pub fn pump_creations<S, F, Fut>(
+    logs: S,
+    fetch_tx: F,
+) -> impl Stream<Item = Result<PumpAccounts, Box<dyn Error>>>
+where
+    S: Stream<Item = Response<RpcLogsResponse>>,
+    F: FnMut(String) -> Fut,
+    Fut: Future<
+        Output = Result<
+            EncodedConfirmedTransactionWithStatusMeta,
+            Box<dyn Error>,
+        >,
+    >,
+{
+    futures_util::stream::unfold(
+        (Box::pin(logs), SeenCache::from_env(), fetch_tx),
+        |(mut logs, mut seen, mut fetch_tx)| async move {
+            while let Some(log) = logs.next().await {
+                let sig = log.value.signature;
+                // the same tx can be notified more than once, skip the fetch
+                if log.value.err.is_some()
+                    || !seen.insert(&sig, Instant::now())
+                {
+                    continue;
+                }
+                let span = pump_candidate_span(&sig);
+                let accounts = async {
+                    parse_pump_candidate(fetch_tx(sig.clone()).await?)
+                }
+                .instrument(span.clone())
+                .await;
+                if let Ok(accounts) = &accounts {
+                    if !seen.insert(&accounts.mint.to_string(), Instant::now())
+                    {
+                        span.in_scope(|| info!("Already processed shitter"));
+                        continue;
+                    }
+                }
+                return Some((accounts, (logs, seen, fetch_tx)));
+            }
+            None
+        },
+    )
+}
+
+pub const DEFAULT_SEEN_CAPACITY: usize = 10_000;
+pub const DEFAULT_SEEN_TTL: Duration = Duration::from_secs(3600);
+
+/// SeenCache remembers keys (signatures, mints) for `ttl`, holding at most
+/// `capacity` of them by evicting the oldest first
+#[derive(Debug)]
+pub struct SeenCache {
+    capacity: usize,
+    ttl: Duration,
+    seen_at: HashMap<String, Instant>,
+    order: VecDeque<(String, Instant)>,
+}
+
+impl SeenCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            seen_at: HashMap::new(),
+            order: VecDeque::new(),
         }
-        if metadata.twitter.is_none() {
-            warn!("No twitter for {}", mint);
-            continue;
+    }
+
+    /// from_env reads PUMP_SEEN_CAPACITY and PUMP_SEEN_TTL_SECS, falling
+    /// back to the defaults
+    pub fn from_env() -> Self {
+        let capacity = std::env::var("PUMP_SEEN_CAPACITY")
+            .ok()
+            .and_then(|capacity| capacity.parse().ok())
+            .unwrap_or(DEFAULT_SEEN_CAPACITY);
+        let ttl = std::env::var("PUMP_SEEN_TTL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_SEEN_TTL);
+        Self::new(capacity, ttl)
+    }
+
+    pub fn len(&self) -> usize {
+        self.seen_at.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen_at.is_empty()
+    }
+
+    /// insert returns true if the key was not seen within the ttl
+    pub fn insert(&mut self, key: &str, now: Instant) -> bool {
+        self.evict(now);
+        if self.seen_at.contains_key(key) {
+            return false;
         }
-        if metadata.telegram.is_none() {
-            warn!("No telegram for {}", mint);
-            continue;
+        if self.seen_at.len() >= self.capacity {
+            if let Some((oldest, _)) = self.order.pop_front() {
+                self.seen_at.remove(&oldest);
+            }
         }
+        self.seen_at.insert(key.to_string(), now);
+        self.order.push_back((key.to_string(), now));
+        true
+    }
 
-        // ensure that someone is not passing in the same link for all of the socials
-        let website = metadata.website.unwrap();
-        let twitter = metadata.twitter.unwrap();
-        let telegram = metadata.telegram.unwrap();
-        if website == twitter || website == telegram || twitter == telegram {
-            warn!("Same link for all socials for {}", mint);
-            continue;
+    fn evict(&mut self, now: Instant) {
+        while let Some((key, seen_at)) = self.order.front() {
+            if now.duration_since(*seen_at) < self.ttl {
+                break;
+            }
+            self.seen_at.remove(key);
+            self.order.pop_front();
         }
+    }
+}
+
+/// pump_candidate_span carries the identity of a candidate through its log
+/// lines, the mint is recorded once the create tx is parsed
+pub fn pump_candidate_span(signature: &str) -> Span {
+    info_span!("pump_candidate", signature, mint = field::Empty)
+}
+
+/// parse_pump_candidate parses the create tx, recording the mint on the
+/// current candidate span
+pub fn parse_pump_candidate(
+    tx: EncodedConfirmedTransactionWithStatusMeta,
+) -> Result<PumpAccounts, Box<dyn Error>> {
+    let slot = tx.slot;
+    let accounts = parse_pump_accounts(tx)?;
+    Span::current().record("mint", field::display(accounts.mint));
+    info!("PumpFun shitter (slot: {})", slot);
+    Ok(accounts)
+}
+
+async fn handle_pump_candidate(
+    accounts: PumpAccounts,
+    rpc_pool: &Arc<RpcPool>,
+    wallet: &Arc<Keypair>,
+    searcher_client: &Arc<Mutex<SearcherClient>>,
+    cancel: &CancellationToken,
+) -> Result<(), Box<dyn Error>> {
+    // sanity check if all fields are populated
+    let metadata =
+        match fetch_metadata(&accounts.mint, METADATA_TIMEOUT, cancel).await {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                warn!("did not get metadata: {}", e);
+                return Ok(());
+            }
+        };
+    if metadata.website.is_none() {
+        warn!("No website");
+        return Ok(());
+    }
+    if metadata.twitter.is_none() {
+        warn!("No twitter");
+        return Ok(());
+    }
+    if metadata.telegram.is_none() {
+        warn!("No telegram");
+        return Ok(());
+    }
+
+    // ensure that someone is not passing in the same link for all of the socials
+    let website = metadata.website.unwrap();
+    let twitter = metadata.twitter.unwrap();
+    let telegram = metadata.telegram.unwrap();
+    if website == twitter || website == telegram || twitter == telegram {
+        warn!("Same link for all socials");
+        return Ok(());
+    }
 
-        let wallet_clone = Arc::clone(&wallet);
-        let rpc_client_clone = Arc::clone(&rpc_client);
-        let mut searcher_client = Arc::clone(&searcher_client);
+    let wallet_clone = Arc::clone(wallet);
+    let rpc_pool = Arc::clone(rpc_pool);
+    let mut searcher_client = Arc::clone(searcher_client);
 
-        tokio::spawn(async move {
+    tokio::spawn(
+        async move {
+            let rpc_client = rpc_pool.get();
             // buy with 0.001 sol
             let result = buy_pump_token(
                 &wallet_clone,
-                &rpc_client_clone,
+                &rpc_client,
                 accounts,
                 1_000_000,
                 &mut searcher_client,
-                true, // use_jito
+                BuyOptions {
+                    use_jito: true,
+                    ..Default::default()
+                },
             )
             .await;
             if let Err(e) = result {
                 error!("Error buying pump token: {:?}", e);
             }
-        });
-    }
-    unsub().await;
+        }
+        .in_current_span(),
+    );
     Ok(())
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub struct PumpAccounts {
     #[serde(
         serialize_with = "pubkey_to_string",
@@ -771,41 +1608,100 @@ pub struct PumpAccounts {
     pub metadata: Pubkey,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PumpCreate {
+    pub accounts: PumpAccounts,
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+}
+
+/// parse_pump_create reads the accounts along with the name, symbol and uri
+/// passed to the create instruction of the creating tx
+pub fn parse_pump_create(
+    tx: EncodedConfirmedTransactionWithStatusMeta,
+) -> Result<PumpCreate, Box<dyn Error>> {
+    let create = find_pump_create_data(&tx)?;
+    Ok(PumpCreate {
+        accounts: parse_pump_accounts(tx)?,
+        name: create.name,
+        symbol: create.symbol,
+        uri: create.uri,
+    })
+}
+
+fn find_pump_create_data(
+    tx: &EncodedConfirmedTransactionWithStatusMeta,
+) -> Result<PumpCreateInstructionData, Box<dyn Error>> {
+    let EncodedTransaction::Json(ui_tx) = &tx.transaction.transaction else {
+        return Err("Not a JSON transaction".into());
+    };
+    let UiMessage::Parsed(UiParsedMessage { instructions, .. }) =
+        &ui_tx.message
+    else {
+        return Err("Not a parsed transaction".into());
+    };
+    instructions
+        .iter()
+        .find_map(|ix| match ix {
+            UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(
+                UiPartiallyDecodedInstruction {
+                    program_id, data, ..
+                },
+            )) if *program_id == PUMP_FUN_PROGRAM.to_string() => {
+                let data = bs58::decode(data).into_vec().ok()?;
+                PumpCreateInstructionData::try_from(data.as_slice()).ok()
+            }
+            _ => None,
+        })
+        .ok_or_else(|| "No pump.fun create instruction".into())
+}
+
 pub fn parse_pump_accounts(
     tx: EncodedConfirmedTransactionWithStatusMeta,
 ) -> Result<PumpAccounts, Box<dyn Error>> {
-    if let EncodedTransaction::Json(tx) = &tx.transaction.transaction {
-        if let UiMessage::Parsed(UiParsedMessage {
-            account_keys,
-            instructions: _,
-            recent_blockhash: _,
-            address_table_lookups: _,
-        }) = &tx.message
-        {
-            debug!("Account keys: {:?}", account_keys);
-            if account_keys.len() >= 5 {
-                let dev = account_keys[0].pubkey.parse()?;
-                let mint = account_keys[1].pubkey.parse()?;
-                let bonding_curve = account_keys[3].pubkey.parse()?;
-                let associated_bonding_curve =
-                    account_keys[4].pubkey.parse()?;
-                let metadata = account_keys[5].pubkey.parse()?;
-
-                Ok(PumpAccounts {
-                    mint,
-                    bonding_curve,
-                    associated_bonding_curve,
-                    dev,
-                    metadata,
-                })
-            } else {
-                Err("Not enough account keys".into())
-            }
-        } else {
-            Err("Not a parsed transaction".into())
+    let account_keys = pump_account_keys(&tx.transaction.transaction)?;
+    debug!("Account keys: {:?}", account_keys);
+    if account_keys.len() < 6 {
+        return Err("Not enough account keys".into());
+    }
+    Ok(PumpAccounts {
+        dev: account_keys[0],
+        mint: account_keys[1],
+        bonding_curve: account_keys[3],
+        associated_bonding_curve: account_keys[4],
+        metadata: account_keys[5],
+    })
+}
+
+/// pump_account_keys reads the account keys of the create transaction however
+/// it was fetched: jsonParsed and json carry them as strings, base64 and
+/// base58 (legacy binary) are decoded into the transaction and read from its
+/// message; the accounts of the create are all static keys, so the lookup
+/// tables of a v0 message don't have to be resolved
+fn pump_account_keys(
+    tx: &EncodedTransaction,
+) -> Result<Vec<Pubkey>, Box<dyn Error>> {
+    match tx {
+        EncodedTransaction::Json(tx) => match &tx.message {
+            UiMessage::Parsed(message) => message
+                .account_keys
+                .iter()
+                .map(|key| Ok(key.pubkey.parse()?))
+                .collect(),
+            UiMessage::Raw(message) => message
+                .account_keys
+                .iter()
+                .map(|key| Ok(key.parse()?))
+                .collect(),
+        },
+        EncodedTransaction::Accounts(_) => {
+            Err("Transaction fetched without its message".into())
+        }
+        binary => {
+            let tx = binary.decode().ok_or("Failed to decode transaction")?;
+            Ok(tx.message.static_account_keys().to_vec())
         }
-    } else {
-        Err("Not a JSON transaction".into())
     }
 }
 
@@ -858,9 +1754,55 @@ pub struct IPFSMetadata {
     pub website: Option<String>,
 }
 
+/// overall budget of a metadata fetch, retries included
+pub const METADATA_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, thiserror::Error)]
+pub enum MetadataError {
+    #[error("Metadata fetch timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Metadata fetch cancelled")]
+    Cancelled,
+    #[error("Metadata fetch failed: {0}")]
+    Fetch(String),
+}
+
+/// fetch_metadata fetches the pump fun metadata of the mint, retrying within
+/// `timeout`; it sits on the listener hot path so a hanging api can't be
+/// allowed to block it, and `cancel` interrupts it on shutdown
 pub async fn fetch_metadata(
     mint: &Pubkey,
-) -> Result<PumpTokenInfo, Box<dyn Error>> {
+    timeout: Duration,
+    cancel: &CancellationToken,
+) -> Result<PumpTokenInfo, MetadataError> {
+    fetch_metadata_with_retry(|| fetch_metadata_inner(mint), timeout, cancel)
+        .await
+}
+
+async fn fetch_metadata_with_retry<F, Fut>(
+    fetch: F,
+    timeout: Duration,
+    cancel: &CancellationToken,
+) -> Result<PumpTokenInfo, MetadataError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<PumpTokenInfo, Box<dyn Error>>>,
+{
+    tokio::select! {
+        _ = cancel.cancelled() => Err(MetadataError::Cancelled),
+        res = tokio::time::timeout(timeout, retry_metadata(fetch)) => {
+            res.map_err(|_| MetadataError::Timeout(timeout))?
+        }
+    }
+}
+
+async fn retry_metadata<F, Fut>(
+    mut fetch: F,
+) -> Result<PumpTokenInfo, MetadataError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<PumpTokenInfo, Box<dyn Error>>>,
+{
     const MAX_RETRIES: u32 = 3;
     const INITIAL_DELAY_MS: u64 = 100;
 
@@ -868,7 +1810,7 @@ pub async fn fetch_metadata(
     let mut delay_ms = INITIAL_DELAY_MS;
 
     loop {
-        match fetch_metadata_inner(mint).await {
+        match fetch().await {
             Ok(metadata) => {
                 info!("Metadata fetched successfully");
                 return Ok(metadata);
@@ -876,7 +1818,7 @@ pub async fn fetch_metadata(
             Err(e) => {
                 if retry_count >= MAX_RETRIES {
                     info!("Failed to fetch metadata after all retries");
-                    return Err(e);
+                    return Err(MetadataError::Fetch(e.to_string()));
                 }
                 info!(
                     "Retry attempt {} failed: {:?}. Retrying in {} ms...",
@@ -939,7 +1881,7 @@ pub async fn send_pump_bump(
             pump_accounts,
             lamports,
             searcher_client,
-            false,
+            BuyOptions::default(),
         )
         .await?;
 
@@ -952,13 +1894,16 @@ pub async fn send_pump_bump(
     // ixs.append(&mut make_compute_budget_ixs(262500, 10000));
 
     ixs.push(make_pump_swap_ix(
-        owner,
-        pump_accounts.mint,
-        pump_accounts.bonding_curve,
-        pump_accounts.associated_bonding_curve,
+        &PumpSwapAccounts {
+            owner,
+            mint: pump_accounts.mint,
+            bonding_curve: pump_accounts.bonding_curve,
+            associated_bonding_curve: pump_accounts.associated_bonding_curve,
+            ata,
+            token_program: TOKEN_PROGRAM,
+        },
         token_amount,
         lamports,
-        ata,
     )?);
 
     ixs.push(make_pump_sell_ix(owner, pump_accounts, token_amount, ata)?);
@@ -1038,11 +1983,72 @@ mod tests {
         .expect("send_pump_bump");
     }
 
+    #[test]
+    fn test_make_buy_ixs_token_2022() {
+        let owner = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let accounts = derive_pump_accounts(&mint).unwrap();
+
+        let ixs = _make_buy_ixs(
+            owner,
+            mint,
+            accounts.bonding_curve,
+            accounts.associated_bonding_curve,
+            1_000_000,
+            1_000,
+            spl_token_2022::ID,
+        )
+        .unwrap();
+
+        let ata = get_pump_ata(&owner, &mint, &spl_token_2022::ID);
+        assert_ne!(
+            ata,
+            spl_associated_token_account::get_associated_token_address(
+                &owner, &mint
+            )
+        );
+        // the ata is created under Token-2022
+        let create_ata = &ixs[ixs.len() - 2];
+        assert_eq!(create_ata.program_id, ASSOCIATED_TOKEN_PROGRAM);
+        assert_eq!(create_ata.accounts[1].pubkey, ata);
+        assert_eq!(create_ata.accounts[5].pubkey, spl_token_2022::ID);
+        // and the buy goes to it with the Token-2022 program
+        let buy = ixs.last().unwrap();
+        assert_eq!(buy.program_id, PUMP_FUN_PROGRAM);
+        assert_eq!(buy.accounts[5].pubkey, ata);
+        assert_eq!(buy.accounts[8].pubkey, spl_token_2022::ID);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_metadata_timeout() {
+        // an api that never answers
+        let stalling =
+            || std::future::pending::<Result<PumpTokenInfo, Box<dyn Error>>>();
+        let timeout = Duration::from_millis(50);
+
+        let started = Instant::now();
+        let res = fetch_metadata_with_retry(
+            stalling,
+            timeout,
+            &CancellationToken::new(),
+        )
+        .await;
+        assert!(matches!(res, Err(MetadataError::Timeout(t)) if t == timeout));
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let res = fetch_metadata_with_retry(stalling, timeout, &cancel).await;
+        assert!(matches!(res, Err(MetadataError::Cancelled)));
+    }
+
     #[tokio::test]
     async fn test_fetch_metadata() {
         let metadata = fetch_metadata(
             &Pubkey::from_str("4cRkQ2dntpusYag6Zmvco8T78WxK9Jqh1eEZJox8pump")
                 .expect("parse mint"),
+            METADATA_TIMEOUT,
+            &CancellationToken::new(),
         )
         .await
         .expect("fetch_metadata");
@@ -1073,7 +2079,7 @@ mod tests {
         let tx: EncodedConfirmedTransactionWithStatusMeta =
             serde_json::from_str(&sample_tx).expect("parse tx");
         let accounts = parse_pump_accounts(tx).expect("parse accounts");
-        println!("{:?}", accounts);
+        info!("{:?}", accounts);
         assert!(
             accounts.mint.to_string()
                 == "6kPvKNrLqg23mApAvHzMKWohhVdSrA54HvrpYud8pump"
@@ -1092,6 +2098,250 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_pump_accounts_base64() {
+        let parse = |path| {
+            let sample_tx = std::fs::read_to_string(path).expect("read tx");
+            let tx: EncodedConfirmedTransactionWithStatusMeta =
+                serde_json::from_str(&sample_tx).expect("parse tx");
+            parse_pump_accounts(tx).expect("parse accounts")
+        };
+        // the same transaction fetched with the base64 encoding
+        let parsed = parse("pump_fun_tx.json");
+        let encoded = parse("pump_fun_tx_base64.json");
+        assert_eq!(encoded, parsed);
+        assert_eq!(
+            encoded.mint.to_string(),
+            "6kPvKNrLqg23mApAvHzMKWohhVdSrA54HvrpYud8pump"
+        );
+    }
+
+    #[test]
+    fn test_build_pump_basket_txs() {
+        let owner = Pubkey::new_unique();
+        let buys = (0..6u64)
+            .map(|i| {
+                let pump_accounts = PumpAccounts {
+                    mint: Pubkey::new_unique(),
+                    bonding_curve: Pubkey::new_unique(),
+                    associated_bonding_curve: Pubkey::new_unique(),
+                    dev: Pubkey::new_unique(),
+                    metadata: Pubkey::new_unique(),
+                };
+                PumpBasketBuy {
+                    accounts: pump_accounts,
+                    token_program: TOKEN_PROGRAM,
+                    token_amount: 1_000_000 * (i + 1),
+                    lamports: 10_000_000 * (i + 1),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let txs =
+            build_pump_basket_txs(owner, buys.clone(), Hash::new_unique())
+                .unwrap();
+        // ~180 bytes a buy, 4 fit along with the shared accounts
+        assert_eq!(txs.len(), 2);
+
+        let mut bought = vec![];
+        for tx in &txs {
+            assert!(fits_packet(tx).unwrap());
+            let keys = &tx.message.account_keys;
+            for ix in &tx.message.instructions {
+                if keys[ix.program_id_index as usize] != PUMP_FUN_PROGRAM {
+                    continue;
+                }
+                let data =
+                    PumpFunSwapInstructionData::parse_buy(&ix.data).unwrap();
+                bought.push((
+                    keys[ix.accounts[2] as usize],
+                    data.token_amount,
+                    data.lamports,
+                ));
+            }
+        }
+        let expected = buys
+            .iter()
+            .map(|buy| (buy.accounts.mint, buy.token_amount, buy.lamports))
+            .collect::<Vec<_>>();
+        assert_eq!(bought, expected);
+    }
+
+    #[test]
+    fn test_build_pump_basket_txs_token_2022() {
+        let owner = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let accounts = derive_pump_accounts(&mint).unwrap();
+        let buy = PumpBasketBuy {
+            accounts,
+            token_program: spl_token_2022::ID,
+            token_amount: 1_000_000,
+            lamports: 1_000,
+        };
+
+        let txs = build_pump_basket_txs(owner, vec![buy], Hash::new_unique())
+            .unwrap();
+        assert_eq!(txs.len(), 1);
+        let message = &txs[0].message;
+        let keys = &message.account_keys;
+        let swap = message
+            .instructions
+            .iter()
+            .find(|ix| keys[ix.program_id_index as usize] == PUMP_FUN_PROGRAM)
+            .unwrap();
+        // the ata, the curve's ata and the program are all Token-2022 ones
+        let ata = get_pump_ata(&owner, &mint, &spl_token_2022::ID);
+        let curve_ata =
+            get_pump_ata(&accounts.bonding_curve, &mint, &spl_token_2022::ID);
+        assert_ne!(curve_ata, accounts.associated_bonding_curve);
+        assert_eq!(keys[swap.accounts[4] as usize], curve_ata);
+        assert_eq!(keys[swap.accounts[5] as usize], ata);
+        assert_eq!(keys[swap.accounts[8] as usize], spl_token_2022::ID);
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_pump_candidate_span_fields() {
+        let sample_tx =
+            std::fs::read_to_string("pump_fun_tx.json").expect("read tx");
+        let tx: EncodedConfirmedTransactionWithStatusMeta =
+            serde_json::from_str(&sample_tx).expect("parse tx");
+        pump_candidate_span("sig123")
+            .in_scope(|| parse_pump_candidate(tx))
+            .expect("parse candidate");
+        assert!(logs_contain("pump_candidate{signature=\"sig123\""));
+        assert!(logs_contain(
+            "mint=6kPvKNrLqg23mApAvHzMKWohhVdSrA54HvrpYud8pump"
+        ));
+        assert!(logs_contain("PumpFun shitter"));
+    }
+
+    #[test]
+    fn test_parse_pump_create() {
+        let sample_tx =
+            std::fs::read_to_string("pump_fun_tx.json").expect("read tx");
+        let tx: EncodedConfirmedTransactionWithStatusMeta =
+            serde_json::from_str(&sample_tx).expect("parse tx");
+        let create = parse_pump_create(tx).expect("parse create");
+        assert_eq!(
+            create.accounts.mint.to_string(),
+            "6kPvKNrLqg23mApAvHzMKWohhVdSrA54HvrpYud8pump"
+        );
+        assert_eq!(create.name, "Dark Mister Miggles");
+        assert_eq!(create.symbol, "D MIGGLES");
+        assert_eq!(
+            create.uri,
+            "https://cf-ipfs.com/ipfs/QmXL8AJH4anLS6Dth3VeYHpaSzr4eaubkrVaG248S9HfDF"
+        );
+    }
+
+    #[test]
+    fn test_seen_cache_dedups_mint() {
+        let mut seen = SeenCache::new(16, Duration::from_secs(60));
+        let now = Instant::now();
+        // the create and a couple of follow-up logs of the same token
+        let events = [
+            ("sig-create", "mint-a"),
+            ("sig-create", "mint-a"),
+            ("sig-buy-1", "mint-a"),
+            ("sig-buy-2", "mint-a"),
+            ("sig-create-b", "mint-b"),
+        ];
+        let emitted = events
+            .iter()
+            .filter(|(sig, mint)| {
+                seen.insert(sig, now) && seen.insert(mint, now)
+            })
+            .map(|(_, mint)| *mint)
+            .collect::<Vec<_>>();
+        assert_eq!(emitted, ["mint-a", "mint-b"]);
+    }
+
+    #[test]
+    fn test_seen_cache_ttl_and_capacity() {
+        let mut seen = SeenCache::new(2, Duration::from_secs(60));
+        let now = Instant::now();
+        assert!(seen.insert("a", now));
+        assert!(!seen.insert("a", now + Duration::from_secs(59)));
+        // expired
+        assert!(seen.insert("a", now + Duration::from_secs(60)));
+
+        let later = now + Duration::from_secs(61);
+        assert!(seen.insert("b", later));
+        assert!(seen.insert("c", later));
+        // a was evicted to make room for c
+        assert_eq!(seen.len(), 2);
+        assert!(seen.insert("a", later));
+    }
+
+    #[tokio::test]
+    async fn test_pump_creations_stream() {
+        let sample_tx =
+            std::fs::read_to_string("pump_fun_tx.json").expect("read tx");
+        let log = |signature: &str, err: Option<TransactionError>| Response {
+            context: solana_client::rpc_response::RpcResponseContext {
+                slot: 0,
+                api_version: None,
+            },
+            value: RpcLogsResponse {
+                signature: signature.to_string(),
+                err,
+                logs: vec![],
+            },
+        };
+        // sig1 is notified twice, the failed tx is never fetched and sig3
+        // creates the same mint again
+        let logs = futures_util::stream::iter(vec![
+            log("sig1", None),
+            log("sig1", None),
+            log("failed", Some(TransactionError::AccountNotFound)),
+            log("sig2", None),
+            log("sig3", None),
+        ]);
+        let fetched = std::sync::Mutex::new(vec![]);
+        let creations = pump_creations(logs, |sig| {
+            fetched.lock().unwrap().push(sig.clone());
+            let sample_tx = sample_tx.clone();
+            async move {
+                let tx: Result<
+                    EncodedConfirmedTransactionWithStatusMeta,
+                    Box<dyn Error>,
+                > = match sig.as_str() {
+                    "sig2" => Err("tx not found".into()),
+                    _ => serde_json::from_str(&sample_tx).map_err(Into::into),
+                };
+                tx
+            }
+        });
+
+        let items = creations.collect::<Vec<_>>().await;
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items[0].as_ref().expect("accounts").mint.to_string(),
+            "6kPvKNrLqg23mApAvHzMKWohhVdSrA54HvrpYud8pump"
+        );
+        assert!(items[1].is_err());
+        assert_eq!(*fetched.lock().unwrap(), ["sig1", "sig2", "sig3"]);
+    }
+
+    #[test]
+    fn test_derive_pump_accounts() {
+        let sample_tx =
+            std::fs::read_to_string("pump_fun_tx.json").expect("read tx");
+        let tx: EncodedConfirmedTransactionWithStatusMeta =
+            serde_json::from_str(&sample_tx).expect("parse tx");
+        let parsed = parse_pump_accounts(tx).expect("parse accounts");
+
+        let derived =
+            derive_pump_accounts(&parsed.mint).expect("derive accounts");
+        assert_eq!(derived.bonding_curve, parsed.bonding_curve);
+        assert_eq!(
+            derived.associated_bonding_curve,
+            parsed.associated_bonding_curve
+        );
+        assert_eq!(derived.metadata, parsed.metadata);
+    }
+
     #[tokio::test]
     async fn test_buy_pump_token() {
         dotenv::from_filename(".env").unwrap();
@@ -1133,7 +2383,10 @@ mod tests {
             pump_accounts,
             lamports,
             &mut searcher_client,
-            true,
+            BuyOptions {
+                use_jito: true,
+                ..Default::default()
+            },
         )
         .await
         .expect("buy pump token");
@@ -1153,7 +2406,7 @@ mod tests {
                 .await
                 .expect("get bonding curve");
 
-        println!("{:?}", bonding_curve);
+        info!("{:?}", bonding_curve);
 
         assert!(!bonding_curve.complete);
         assert_ne!(bonding_curve.virtual_token_reserves, 0);
@@ -1175,7 +2428,7 @@ mod tests {
                 .await
                 .expect("get bonding curve");
 
-        println!("{:?}", bonding_curve);
+        info!("{:?}", bonding_curve);
 
         assert!(bonding_curve.complete);
         assert_eq!(bonding_curve.virtual_token_reserves, 0);
@@ -1183,6 +2436,326 @@ mod tests {
         assert_eq!(bonding_curve.real_token_reserves, 0);
     }
 
+    #[test]
+    fn test_parse_buy_instruction_data() {
+        // buy ix data from pump_fun_tx.json
+        let data = bs58::decode("AJTQ2h9DXrBuiGA4EsLYdCAn82eqVg17D")
+            .into_vec()
+            .expect("decode ix data");
+        let buy =
+            PumpFunSwapInstructionData::parse_buy(&data).expect("parse buy");
+        assert_eq!(buy.method_id, PUMP_BUY_METHOD);
+        assert_eq!(buy.token_amount, 51095238095238);
+        assert_eq!(buy.lamports, 1515000000);
+        assert!(PumpFunSwapInstructionData::parse_sell(&data).is_err());
+    }
+
+    #[test]
+    fn test_parse_sell_instruction_data() {
+        let pump_accounts = PumpAccounts {
+            mint: Pubkey::new_unique(),
+            bonding_curve: Pubkey::new_unique(),
+            associated_bonding_curve: Pubkey::new_unique(),
+            dev: Pubkey::default(),
+            metadata: Pubkey::default(),
+        };
+        let ix = make_pump_sell_ix(
+            Pubkey::new_unique(),
+            pump_accounts,
+            1_000_000,
+            Pubkey::new_unique(),
+        )
+        .expect("make sell ix");
+        let sell = PumpFunSwapInstructionData::parse_sell(&ix.data)
+            .expect("parse sell");
+        assert!(sell.is_sell());
+        assert_eq!(sell.token_amount, 1_000_000);
+        assert_eq!(sell.lamports, 0);
+    }
+
+    #[test]
+    fn test_parse_swap_instruction_data_invalid() {
+        assert!(PumpFunSwapInstructionData::try_from(&[0u8; 24][..]).is_err());
+        assert!(PumpFunSwapInstructionData::try_from(&PUMP_BUY_METHOD[..])
+            .is_err());
+    }
+
+    #[test]
+    fn test_is_near_completion() {
+        let mut curve = BondingCurveLayout {
+            blob1: 0,
+            virtual_token_reserves: 0,
+            virtual_sol_reserves: 0,
+            real_token_reserves: 0,
+            real_sol_reserves: 0,
+            blob4: 0,
+            complete: false,
+        };
+        // 500 bps of 85 sol is 80.75 sol
+        curve.real_sol_reserves = 80_749_999_999;
+        assert!(!is_near_completion(&curve, 500));
+        curve.real_sol_reserves = 80_750_000_000;
+        assert!(is_near_completion(&curve, 500));
+
+        curve.real_sol_reserves = 0;
+        curve.complete = true;
+        assert!(is_near_completion(&curve, 0));
+    }
+
+    fn mock_simulation(
+        amount: u64,
+        err: Option<solana_sdk::transaction::TransactionError>,
+    ) -> RpcSimulateTransactionResult {
+        let mut data = vec![0u8; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        let account = solana_sdk::account::Account {
+            lamports: 2_039_280,
+            data,
+            owner: TOKEN_PROGRAM,
+            executable: false,
+            rent_epoch: 0,
+        };
+        RpcSimulateTransactionResult {
+            err,
+            logs: Some(vec![]),
+            accounts: Some(vec![Some(
+                solana_account_decoder::UiAccount::encode(
+                    &Pubkey::new_unique(),
+                    &account,
+                    UiAccountEncoding::Base64,
+                    None,
+                    None,
+                ),
+            )]),
+            units_consumed: None,
+            return_data: None,
+        }
+    }
+
+    #[test]
+    fn test_check_simulated_tokens_out() {
+        let simulation = mock_simulation(1_500, None);
+        // holding 1000 before the buy, so 500 out
+        assert!(check_simulated_tokens_out(&simulation, 1_000, 501).is_err());
+        assert_eq!(
+            check_simulated_tokens_out(&simulation, 1_000, 500).unwrap(),
+            500
+        );
+
+        let failed = mock_simulation(
+            1_500,
+            Some(solana_sdk::transaction::TransactionError::AccountInUse),
+        );
+        assert!(check_simulated_tokens_out(&failed, 0, 0).is_err());
+    }
+
+    /// MockSender fails the sends with each of `errors` in turn, the txs
+    /// in `landed` have landed despite the error of their send
+    #[derive(Default)]
+    struct MockSender {
+        errors: std::sync::Mutex<VecDeque<ClientError>>,
+        block_height: std::sync::atomic::AtomicU64,
+        landed: std::sync::Mutex<Vec<Signature>>,
+        sent: std::sync::Mutex<Vec<Signature>>,
+        /// the send that "lands" before its error, if any
+        lands_on_send: Option<usize>,
+    }
+
+    const LAST_VALID_BLOCK_HEIGHT: u64 = 150;
+
+    impl TxSender for MockSender {
+        async fn latest_blockhash(&self) -> ClientResult<(Hash, u64)> {
+            Ok((Hash::new_unique(), LAST_VALID_BLOCK_HEIGHT))
+        }
+
+        async fn send(
+            &self,
+            tx: &VersionedTransaction,
+        ) -> ClientResult<Signature> {
+            let mut sent = self.sent.lock().unwrap();
+            if self.lands_on_send == Some(sent.len()) {
+                self.landed.lock().unwrap().push(tx.signatures[0]);
+            }
+            sent.push(tx.signatures[0]);
+            match self.errors.lock().unwrap().pop_front() {
+                Some(e) => Err(e),
+                None => Ok(tx.signatures[0]),
+            }
+        }
+
+        async fn block_height(&self) -> ClientResult<u64> {
+            Ok(self
+                .block_height
+                .fetch_add(100, std::sync::atomic::Ordering::Relaxed))
+        }
+
+        async fn signature_status(
+            &self,
+            sig: &Signature,
+        ) -> ClientResult<Option<Result<(), TransactionError>>> {
+            Ok(self.landed.lock().unwrap().contains(sig).then_some(Ok(())))
+        }
+    }
+
+    fn connection_reset() -> ClientError {
+        std::io::Error::from(std::io::ErrorKind::ConnectionReset).into()
+    }
+
+    /// send_mock runs send_with_retry against `sender`, returning the
+    /// result and the number of times the tx was signed
+    async fn send_mock(
+        sender: &MockSender,
+        send_retry: SendRetryConfig,
+    ) -> (Result<Signature, ClientError>, usize) {
+        let payer = Keypair::new();
+        let signed = std::sync::atomic::AtomicUsize::new(0);
+        let res = send_with_retry(sender, &send_retry, |blockhash| {
+            signed.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            VersionedTransaction::from(Transaction::new_signed_with_payer(
+                &[transfer(&payer.pubkey(), &Pubkey::new_unique(), 1)],
+                Some(&payer.pubkey()),
+                &[&payer],
+                blockhash,
+            ))
+        })
+        .await;
+        (res, signed.into_inner())
+    }
+
+    fn mock_sender(errors: Vec<ClientError>) -> MockSender {
+        MockSender {
+            errors: std::sync::Mutex::new(errors.into()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_with_retry() {
+        let send_retry = SendRetryConfig {
+            max_retries: 3,
+            base_delay: Duration::ZERO,
+        };
+
+        // the connection dropped but the tx was forwarded, it is looked up
+        // instead of being bought again
+        let sender = MockSender {
+            lands_on_send: Some(0),
+            ..mock_sender(vec![connection_reset()])
+        };
+        let (res, signed) = send_mock(&sender, send_retry).await;
+        assert_eq!(res.unwrap(), sender.sent.lock().unwrap()[0]);
+        assert_eq!(signed, 1);
+        assert_eq!(sender.sent.lock().unwrap().len(), 1);
+
+        // not landed and the blockhash still valid (height 0), the same
+        // signed tx is sent again
+        let sender = mock_sender(vec![connection_reset()]);
+        let (res, signed) = send_mock(&sender, send_retry).await;
+        let sent = sender.sent.lock().unwrap().clone();
+        assert_eq!(signed, 1);
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0], sent[1]);
+        assert_eq!(res.unwrap(), sent[1]);
+
+        // re-signed only once the height (0, 100, 200) is past the last
+        // valid one
+        let sender = mock_sender(vec![
+            connection_reset(),
+            connection_reset(),
+            ClientError::from(TransactionError::BlockhashNotFound),
+        ]);
+        let (res, signed) = send_mock(&sender, send_retry).await;
+        let sent = sender.sent.lock().unwrap().clone();
+        assert_eq!(signed, 2);
+        assert_eq!(sent.len(), 4);
+        assert_eq!(sent[0], sent[2]);
+        assert_ne!(sent[2], sent[3]);
+        assert_eq!(res.unwrap(), sent[3]);
+
+        // terminal errors are returned straight away
+        let sender = mock_sender(vec![ClientError::from(
+            TransactionError::InsufficientFundsForFee,
+        )]);
+        let (res, signed) = send_mock(&sender, send_retry).await;
+        assert_eq!(
+            res.unwrap_err().get_transaction_error(),
+            Some(TransactionError::InsufficientFundsForFee)
+        );
+        assert_eq!(signed, 1);
+        assert_eq!(sender.sent.lock().unwrap().len(), 1);
+
+        // gives up after max_retries
+        let sender = mock_sender((0..5).map(|_| connection_reset()).collect());
+        let (res, _) = send_mock(&sender, send_retry).await;
+        assert!(res.is_err());
+        assert_eq!(sender.sent.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_is_account_not_found() {
+        let rpc_error = |code, message: &str| {
+            ClientError::from(RpcError::RpcResponseError {
+                code,
+                message: message.to_string(),
+                data: solana_client::rpc_request::RpcResponseErrorData::Empty,
+            })
+        };
+        assert!(is_account_not_found(&rpc_error(
+            -32602,
+            "Invalid param: could not find account"
+        )));
+        assert!(!is_account_not_found(&rpc_error(
+            -32005,
+            "Node is behind by 42 slots"
+        )));
+        assert!(!is_account_not_found(&connection_reset()));
+    }
+
+    #[test]
+    fn test_send_config() {
+        let config = SendConfig {
+            skip_preflight: false,
+            preflight_commitment: CommitmentLevel::Confirmed,
+            max_retries: Some(2),
+        }
+        .rpc_send_config();
+        assert!(!config.skip_preflight);
+        assert_eq!(
+            config.preflight_commitment,
+            Some(CommitmentLevel::Confirmed)
+        );
+        assert_eq!(config.max_retries, Some(2));
+
+        // the sniping default, no preflight
+        let config = SendConfig::default().rpc_send_config();
+        assert!(config.skip_preflight);
+        assert_eq!(
+            config.preflight_commitment,
+            Some(CommitmentLevel::Processed)
+        );
+        assert_eq!(config.max_retries, None);
+    }
+
+    #[test]
+    fn test_send_retry_backoff() {
+        let send_retry = SendRetryConfig {
+            max_retries: 3,
+            base_delay: Duration::from_millis(100),
+        };
+        for attempt in 0..3 {
+            let backoff = send_retry.backoff(attempt);
+            let min = Duration::from_millis(100 * 2u64.pow(attempt));
+            assert!(backoff >= min && backoff < min + send_retry.base_delay);
+        }
+    }
+
     #[tokio::test]
     async fn test_get_token_amount() {
         // captured from prod
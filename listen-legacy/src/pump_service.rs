@@ -65,6 +65,7 @@ pub async fn handle_pump_buy(
         pump_buy_request.associated_bonding_curve,
         token_amount,
         lamports,
+        pump::TOKEN_PROGRAM,
     )?;
     ixs.push(transfer(&wallet.pubkey(), &JITO_TIP_PUBKEY, tip));
     let swap_tx =
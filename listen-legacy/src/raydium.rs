@@ -22,6 +22,7 @@ use utoipa::ToSchema;
 
 use crate::jito::send_jito_tx;
 use crate::seller_service::load_amm_keys;
+use crate::units::lamports_to_sol;
 use crate::{constants, Provider};
 use futures_util::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
@@ -311,7 +312,7 @@ pub fn calc_result_to_financials(
 ) -> f64 {
     let sol_price = 145.;
     if coin_mint_is_sol {
-        let sol_amount = result.pool_coin_vault_amount as f64 / 1e9;
+        let sol_amount = lamports_to_sol(result.pool_coin_vault_amount);
         let usd_amount = sol_amount * sol_price;
         let price = result.pool_coin_vault_amount as f64
             / result.pool_pc_vault_amount as f64;
@@ -332,7 +333,7 @@ pub fn calc_result_to_financials(
         );
         sol_amount
     } else {
-        let sol_amount = result.pool_pc_vault_amount as f64 / 1e9;
+        let sol_amount = lamports_to_sol(result.pool_pc_vault_amount);
         let usd_amount = sol_amount * sol_price;
         let price = result.pool_pc_vault_amount as f64
             / result.pool_coin_vault_amount as f64;
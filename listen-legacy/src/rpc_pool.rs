@@ -0,0 +1,320 @@
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_request::RpcError;
+use solana_sdk::commitment_config::CommitmentConfig;
+use tracing::warn;
+
+use crate::util::env;
+
+/// consecutive errors after which an endpoint is skipped for the cooldown
+pub const MAX_CONSECUTIVE_ERRORS: u64 = 3;
+pub const ENDPOINT_COOLDOWN: Duration = Duration::from_secs(30);
+
+struct Endpoint {
+    client: Arc<RpcClient>,
+    requests: AtomicU64,
+    errors: AtomicU64,
+    consecutive_errors: AtomicU64,
+    skip_until: Mutex<Option<Instant>>,
+}
+
+impl Endpoint {
+    fn is_available(&self, now: Instant) -> bool {
+        match *self.skip_until.lock().unwrap() {
+            Some(skip_until) => now >= skip_until,
+            None => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EndpointStats {
+    pub url: String,
+    pub requests: u64,
+    pub errors: u64,
+    pub available: bool,
+}
+
+/// RpcPool shares a set of rpc clients (and their connections) across calls,
+/// handing them out round-robin; an endpoint failing MAX_CONSECUTIVE_ERRORS
+/// times in a row is skipped for ENDPOINT_COOLDOWN
+pub struct RpcPool {
+    endpoints: Vec<Endpoint>,
+    next: AtomicUsize,
+    cooldown: Duration,
+}
+
+impl RpcPool {
+    pub fn new(urls: Vec<String>, commitment: CommitmentConfig) -> Self {
+        assert!(!urls.is_empty(), "RpcPool requires at least one endpoint");
+        let endpoints = urls
+            .into_iter()
+            .map(|url| Endpoint {
+                client: Arc::new(RpcClient::new_with_commitment(
+                    url, commitment,
+                )),
+                requests: AtomicU64::new(0),
+                errors: AtomicU64::new(0),
+                consecutive_errors: AtomicU64::new(0),
+                skip_until: Mutex::new(None),
+            })
+            .collect();
+        Self {
+            endpoints,
+            next: AtomicUsize::new(0),
+            cooldown: ENDPOINT_COOLDOWN,
+        }
+    }
+
+    /// from_env reads the comma separated RPC_URLS, falling back to RPC_URL
+    /// if it is unset or lists no url
+    pub fn from_env(commitment: CommitmentConfig) -> Self {
+        let mut urls =
+            parse_urls(&std::env::var("RPC_URLS").unwrap_or_default());
+        if urls.is_empty() {
+            urls.push(env("RPC_URL"));
+        }
+        Self::new(urls, commitment)
+    }
+
+    /// get returns the next available client, if every endpoint is cooling
+    /// down it falls back to plain round-robin rather than failing
+    pub fn get(&self) -> PooledClient<'_> {
+        let now = Instant::now();
+        let len = self.endpoints.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let index = (0..len)
+            .map(|offset| (start + offset) % len)
+            .find(|&i| self.endpoints[i].is_available(now))
+            .unwrap_or(start % len);
+        if index != start % len {
+            // move the cursor past the skipped endpoints
+            self.next.store(index + 1, Ordering::Relaxed);
+        }
+        let endpoint = &self.endpoints[index];
+        endpoint.requests.fetch_add(1, Ordering::Relaxed);
+        PooledClient {
+            endpoint,
+            cooldown: self.cooldown,
+        }
+    }
+
+    pub fn stats(&self) -> Vec<EndpointStats> {
+        let now = Instant::now();
+        self.endpoints
+            .iter()
+            .map(|endpoint| EndpointStats {
+                url: endpoint.client.url(),
+                requests: endpoint.requests.load(Ordering::Relaxed),
+                errors: endpoint.errors.load(Ordering::Relaxed),
+                available: endpoint.is_available(now),
+            })
+            .collect()
+    }
+}
+
+/// parse_urls splits a comma separated list of urls, skipping the empty
+/// entries, e.g. of a trailing comma
+fn parse_urls(urls: &str) -> Vec<String> {
+    urls.split(',')
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty())
+        .collect()
+}
+
+/// PooledClient derefs into the RpcClient, results of calls made with it
+/// should be passed through `track` to feed the error-rate tracking
+pub struct PooledClient<'a> {
+    endpoint: &'a Endpoint,
+    cooldown: Duration,
+}
+
+impl PooledClient<'_> {
+    pub fn client(&self) -> Arc<RpcClient> {
+        self.endpoint.client.clone()
+    }
+
+    pub fn track<T, E>(&self, res: Result<T, E>) -> Result<T, E> {
+        match res {
+            Ok(_) => self.report_success(),
+            Err(_) => self.report_error(),
+        }
+        res
+    }
+
+    /// track_transport is track for results mixing rpc calls with the logic
+    /// on top of them, only the transport errors count against the endpoint
+    pub fn track_transport<T>(
+        &self,
+        res: Result<T, Box<dyn std::error::Error>>,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        match &res {
+            Err(e) if is_transport_error(e.as_ref()) => self.report_error(),
+            _ => self.report_success(),
+        }
+        res
+    }
+
+    pub fn report_success(&self) {
+        self.endpoint.consecutive_errors.store(0, Ordering::Relaxed);
+    }
+
+    pub fn report_error(&self) {
+        self.endpoint.errors.fetch_add(1, Ordering::Relaxed);
+        let consecutive = self
+            .endpoint
+            .consecutive_errors
+            .fetch_add(1, Ordering::Relaxed)
+            + 1;
+        if consecutive >= MAX_CONSECUTIVE_ERRORS {
+            warn!(
+                "{} failed {} times in a row, skipping for {:?}",
+                self.endpoint.client.url(),
+                consecutive,
+                self.cooldown
+            );
+            *self.endpoint.skip_until.lock().unwrap() =
+                Some(Instant::now() + self.cooldown);
+            self.endpoint.consecutive_errors.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// is_transport_error is true for the failures of the endpoint itself, no
+/// connection or a malformed response, as opposed to the node rejecting the
+/// request or the caller failing on what it got back
+pub fn is_transport_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    let Some(err) = err.downcast_ref::<ClientError>() else {
+        return false;
+    };
+    matches!(
+        err.kind(),
+        ClientErrorKind::Io(_)
+            | ClientErrorKind::Reqwest(_)
+            | ClientErrorKind::RpcError(RpcError::RpcRequestError(_))
+    )
+}
+
+impl Deref for PooledClient<'_> {
+    type Target = RpcClient;
+
+    fn deref(&self) -> &Self::Target {
+        &self.endpoint.client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_pool() -> RpcPool {
+        RpcPool::new(
+            vec![
+                "http://rpc-a".to_string(),
+                "http://rpc-b".to_string(),
+                "http://rpc-c".to_string(),
+            ],
+            CommitmentConfig::processed(),
+        )
+    }
+
+    #[test]
+    fn test_rpc_pool_round_robin() {
+        let pool = make_pool();
+        let urls = (0..6).map(|_| pool.get().url()).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "http://rpc-a",
+                "http://rpc-b",
+                "http://rpc-c",
+                "http://rpc-a",
+                "http://rpc-b",
+                "http://rpc-c",
+            ]
+        );
+        assert!(pool.stats().iter().all(|stats| stats.requests == 2));
+    }
+
+    #[test]
+    fn test_rpc_pool_skips_failing_endpoint() {
+        let pool = make_pool();
+        for _ in 0..MAX_CONSECUTIVE_ERRORS {
+            // a, b, c, a, b, c.. only b fails
+            let a = pool.get();
+            a.track::<(), ()>(Ok(()));
+            let b = pool.get();
+            b.track::<(), ()>(Err(()));
+            pool.get();
+        }
+
+        let urls = (0..4).map(|_| pool.get().url()).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "http://rpc-a",
+                "http://rpc-c",
+                "http://rpc-a",
+                "http://rpc-c"
+            ]
+        );
+        let stats = pool.stats();
+        assert_eq!(stats[1].errors, MAX_CONSECUTIVE_ERRORS);
+        assert!(!stats[1].available);
+    }
+
+    #[test]
+    fn test_rpc_pool_all_endpoints_failing() {
+        let pool = RpcPool::new(
+            vec!["http://rpc-a".to_string()],
+            CommitmentConfig::processed(),
+        );
+        for _ in 0..MAX_CONSECUTIVE_ERRORS {
+            pool.get().report_error();
+        }
+        // still handed out, nothing else to pick
+        assert_eq!(pool.get().url(), "http://rpc-a");
+    }
+
+    #[test]
+    fn test_parse_urls() {
+        assert_eq!(
+            parse_urls("http://rpc-a, http://rpc-b,"),
+            ["http://rpc-a", "http://rpc-b"]
+        );
+        assert!(parse_urls("").is_empty());
+        assert!(parse_urls(" , ").is_empty());
+    }
+
+    #[test]
+    fn test_track_transport() {
+        let pool = RpcPool::new(
+            vec!["http://rpc-a".to_string()],
+            CommitmentConfig::processed(),
+        );
+        let client = pool.get();
+        let logic = || -> Box<dyn std::error::Error> { "not a pump".into() };
+        let rejected = ClientError::from(RpcError::ForUser(
+            "AccountNotFound".to_string(),
+        ));
+        let transport = ClientError::from(std::io::Error::from(
+            std::io::ErrorKind::ConnectionReset,
+        ));
+
+        // neither the checks failing nor the node rejecting the request
+        // are the endpoint's fault
+        for _ in 0..MAX_CONSECUTIVE_ERRORS {
+            assert!(client.track_transport::<()>(Err(logic())).is_err());
+        }
+        assert!(client.track_transport::<()>(Err(rejected.into())).is_err());
+        assert_eq!(pool.stats()[0].errors, 0);
+
+        assert!(client.track_transport::<()>(Err(transport.into())).is_err());
+        assert_eq!(pool.stats()[0].errors, 1);
+    }
+}
@@ -16,6 +16,7 @@ use solana_sdk::{
 use spl_token::state::Mint;
 
 use crate::constants;
+use crate::units::{lamports_to_sol, ui_amount};
 
 #[derive(Debug, Default)]
 pub struct VaultState {
@@ -45,9 +46,9 @@ impl Pool {
         // worth pulling it from chain, same as SOL price, this method is more
         // for looking, for trading another method should be used that returns the ratio
         // ratio is all
-        let token_amount = self.token_vault.amount as f64
-            / 10u64.pow(self.token_vault.decimals as u32) as f64;
-        let sol_amount = self.sol_vault.amount as f64 / 10u64.pow(9) as f64;
+        let token_amount =
+            ui_amount(self.token_vault.amount, self.token_vault.decimals);
+        let sol_amount = lamports_to_sol(self.sol_vault.amount);
         Some(sol_amount / token_amount * 170.)
     }
 
@@ -188,7 +189,7 @@ pub async fn get_sol_pooled_vault(
     rpc_client: &RpcClient,
 ) -> f64 {
     let sol_pooled = rpc_client.get_account(vault).await.unwrap().lamports;
-    sol_pooled as f64 / 10u64.pow(9) as f64
+    lamports_to_sol(sol_pooled)
 }
 
 pub async fn get_sol_pooled(amm_pool: &Pubkey, rpc_client: &RpcClient) -> f64 {
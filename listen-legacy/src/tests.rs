@@ -28,6 +28,36 @@ fn test_parse_notional() {
     assert!(1510000000 > sol_notional && sol_notional > 1500000000);
 }
 
+#[tokio::test]
+async fn test_get_tx_available_on_second_attempt() {
+    let tx: solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta =
+        serde_json::from_reader(std::fs::File::open("mock/tx.json").unwrap())
+            .unwrap();
+    let attempts = std::cell::Cell::new(0);
+    let res = crate::provider::poll_tx("sig", 3, || {
+        attempts.set(attempts.get() + 1);
+        let found = attempts.get() > 1;
+        let tx = tx.clone();
+        async move { Ok(found.then_some(tx)) }
+    })
+    .await
+    .unwrap();
+    assert_eq!(attempts.get(), 2);
+    assert_eq!(res.slot, tx.slot);
+
+    let missing = crate::provider::poll_tx("sig", 2, || async { Ok(None) })
+        .await
+        .unwrap_err();
+    assert!(matches!(missing, crate::provider::GetTxError::NotFound(_)));
+
+    let rpc_err = crate::provider::poll_tx("sig", 1, || async {
+        Err(crate::provider::GetTxError::RpcError("timeout".to_string()))
+    })
+    .await
+    .unwrap_err();
+    assert!(matches!(rpc_err, crate::provider::GetTxError::RpcError(_)));
+}
+
 #[tokio::test]
 async fn test_parse_new_pool() {
     let new_pool_tx_signature: &str =
@@ -0,0 +1,68 @@
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+
+pub const SOL_DECIMALS: u8 = 9;
+
+/// ui_amount converts a raw token amount to ui units
+///
+/// the whole and fractional parts are converted separately, so the whole
+/// part is exact up to 2^53 and the result is the closest f64 to the exact
+/// value; an f64 holds ~15 significant digits, amounts above ~10^6 ui units
+/// (with 9 decimals) lose the smallest raw units
+pub fn ui_amount(raw: u64, decimals: u8) -> f64 {
+    match 10u64.checked_pow(decimals as u32) {
+        Some(scale) => {
+            (raw / scale) as f64 + (raw % scale) as f64 / scale as f64
+        }
+        // more decimals than a u64 has digits, it's all fractional
+        None => raw as f64 / 10f64.powi(decimals as i32),
+    }
+}
+
+/// lamports_to_sol converts lamports to sol, see `ui_amount` for the
+/// precision
+pub fn lamports_to_sol(lamports: u64) -> f64 {
+    ui_amount(lamports, SOL_DECIMALS)
+}
+
+/// sol_to_lamports converts sol to lamports, rounding to the closest lamport
+/// (0.57 sol is 569999999.99999994 lamports as an f64); negative and NaN
+/// amounts are 0, amounts above u64::MAX lamports saturate
+pub fn sol_to_lamports(sol: f64) -> u64 {
+    (sol * LAMPORTS_PER_SOL as f64).round() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lamports_to_sol() {
+        assert_eq!(lamports_to_sol(0), 0.0);
+        assert_eq!(lamports_to_sol(1), 1e-9);
+        assert_eq!(lamports_to_sol(1_500_000_000), 1.5);
+        // the supply of sol, ~5.9e17 lamports, is above 2^53
+        assert_eq!(
+            lamports_to_sol(589_000_000_123_456_789),
+            589_000_000.1234568
+        );
+        assert_eq!(lamports_to_sol(u64::MAX), 18_446_744_073.709_553);
+    }
+
+    #[test]
+    fn test_sol_to_lamports() {
+        assert_eq!(sol_to_lamports(0.57), 570_000_000);
+        assert_eq!(sol_to_lamports(6.9), 6_900_000_000);
+        assert_eq!(sol_to_lamports(-1.0), 0);
+        assert_eq!(sol_to_lamports(f64::NAN), 0);
+        for lamports in [1, 999_999_999, 123_456_789_012] {
+            assert_eq!(sol_to_lamports(lamports_to_sol(lamports)), lamports);
+        }
+    }
+
+    #[test]
+    fn test_ui_amount() {
+        assert_eq!(ui_amount(6_822_422_379, 6), 6822.422379);
+        assert_eq!(ui_amount(42, 0), 42.0);
+        assert_eq!(ui_amount(1, 20), 1e-20);
+    }
+}
@@ -1,4 +1,5 @@
 use actix_web::{get, HttpResponse, Responder};
+use base64::Engine;
 use serde::Deserialize;
 use solana_sdk::pubkey::Pubkey;
 use std::str::FromStr;
@@ -7,13 +8,7 @@ pub fn env(var: &str) -> String {
     std::env::var(var).unwrap_or_else(|_| panic!("{} env var not set", var))
 }
 
-pub fn lamports_to_sol(lamports: u64) -> f64 {
-    lamports as f64 / 1000000000.0
-}
-
-pub fn sol_to_lamports(sol: f64) -> u64 {
-    (sol * 1000000000.0) as u64
-}
+pub use crate::units::{lamports_to_sol, sol_to_lamports};
 
 #[get("/healthz")]
 pub async fn healthz() -> impl Responder {
@@ -55,3 +50,58 @@ where
     let s: String = Deserialize::deserialize(deserializer)?;
     s.parse().map_err(serde::de::Error::custom)
 }
+
+#[derive(Debug, thiserror::Error)]
+pub enum DecodeError {
+    #[error("Empty account data")]
+    Empty,
+    #[error("Invalid base58 account data: {0}")]
+    Base58(#[from] bs58::decode::Error),
+    #[error("Invalid base64 account data: {0}")]
+    Base64(#[from] base64::DecodeError),
+}
+
+const BASE58_ALPHABET: &str =
+    "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// decode_account_data decodes account data given either as base58 (legacy
+/// binary encoding) or base64
+///
+/// input made only of base58 characters that isn't a valid base64 length is
+/// base58; base64 of account-sized data practically always contains one of
+/// `0 O I l + / =`, so an input that fits both is also taken as base58
+pub fn decode_account_data(input: &str) -> Result<Vec<u8>, DecodeError> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(DecodeError::Empty);
+    }
+    if input.chars().all(|c| BASE58_ALPHABET.contains(c)) {
+        return Ok(bs58::decode(input).into_vec()?);
+    }
+    Ok(base64::prelude::BASE64_STANDARD.decode(input)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_account_data() {
+        // the mint of checker::tests::test_unpack_mint
+        let bs58_data = "1111Dk7tnoddMvATwtoKYbhf9c51kPxy4Siv5Ubb93zssnpGt5j2ELBnz1TT5a7jGAeKE9zEsoFAY5kByXAhfi8EYHCg3ChYCmZ6rnyNYPxQrK";
+        let bytes = bs58::decode(bs58_data).into_vec().unwrap();
+        let base64_data = base64::prelude::BASE64_STANDARD.encode(&bytes);
+
+        assert_eq!(decode_account_data(bs58_data).unwrap(), bytes);
+        assert_eq!(decode_account_data(&base64_data).unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_decode_account_data_invalid() {
+        assert!(matches!(decode_account_data(""), Err(DecodeError::Empty)));
+        assert!(matches!(
+            decode_account_data("not base64!"),
+            Err(DecodeError::Base64(_))
+        ));
+    }
+}